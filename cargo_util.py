@@ -17,6 +17,7 @@ def split(s):
 
 crate_list=split("""
 slr_parser
+slr_config_derive
 slr_config
 """)
 
@@ -0,0 +1,118 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const BASE64_DIGITS: &[u8; 64] =
+	b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String
+{
+	let mut ret = String::with_capacity(bytes.len() * 2);
+	for &b in bytes
+	{
+		ret.push(HEX_DIGITS[(b >> 4) as usize] as char);
+		ret.push(HEX_DIGITS[(b & 0xf) as usize] as char);
+	}
+	ret
+}
+
+/// Decodes a hex string, ignoring whitespace. Both upper and lower case digits
+/// are accepted.
+pub(crate) fn decode_hex(string: &str) -> Result<Vec<u8>, String>
+{
+	let mut ret = Vec::with_capacity(string.len() / 2);
+	let mut high = None;
+	for c in string.chars().filter(|c| !c.is_whitespace())
+	{
+		let digit = match c.to_digit(16)
+		{
+			Some(digit) => digit as u8,
+			None => return Err(format!("Invalid hex digit '{}'.", c)),
+		};
+		match high.take()
+		{
+			Some(high) => ret.push(high << 4 | digit),
+			None => high = Some(digit),
+		}
+	}
+	if high.is_some()
+	{
+		return Err("Hex string has an odd number of digits.".to_string());
+	}
+	Ok(ret)
+}
+
+/// Encodes bytes using the standard base64 alphabet, with padding.
+pub(crate) fn encode_base64(bytes: &[u8]) -> String
+{
+	let mut ret = String::with_capacity(bytes.len().div_ceil(3) * 4);
+	for chunk in bytes.chunks(3)
+	{
+		let b = [
+			chunk[0],
+			chunk.get(1).cloned().unwrap_or(0),
+			chunk.get(2).cloned().unwrap_or(0),
+		];
+		let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+		for i in 0..4
+		{
+			if i <= chunk.len()
+			{
+				ret.push(BASE64_DIGITS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+			}
+			else
+			{
+				ret.push('=');
+			}
+		}
+	}
+	ret
+}
+
+/// Decodes a base64 string using the standard alphabet, ignoring whitespace.
+/// Padding is optional.
+pub(crate) fn decode_base64(string: &str) -> Result<Vec<u8>, String>
+{
+	let mut ret = Vec::with_capacity(string.len() / 4 * 3);
+	let mut n = 0u32;
+	let mut num_digits = 0;
+	let mut num_padding = 0;
+	for c in string.chars().filter(|c| !c.is_whitespace())
+	{
+		if c == '='
+		{
+			num_padding += 1;
+			continue;
+		}
+		if num_padding > 0
+		{
+			return Err("Unexpected base64 digit after the padding.".to_string());
+		}
+		let digit = match BASE64_DIGITS.iter().position(|&d| d as char == c)
+		{
+			Some(digit) => digit as u32,
+			None => return Err(format!("Invalid base64 digit '{}'.", c)),
+		};
+		n = n << 6 | digit;
+		num_digits += 1;
+		if num_digits == 4
+		{
+			ret.extend_from_slice(&[(n >> 16) as u8, (n >> 8) as u8, n as u8]);
+			n = 0;
+			num_digits = 0;
+		}
+	}
+	match (num_digits, num_padding)
+	{
+		(0, 0) =>
+		{}
+		(2, 0) | (2, 2) => ret.push((n >> 4) as u8),
+		(3, 0) | (3, 1) =>
+		{
+			ret.extend_from_slice(&[(n >> 10) as u8, (n >> 2) as u8]);
+		}
+		_ => return Err("Invalid base64 string length.".to_string()),
+	}
+	Ok(ret)
+}
@@ -4,23 +4,49 @@
 
 pub use ConfigElementKind::*;
 
+use crate::path::{ElementPath, PathSegment};
+use crate::visit::{Visit, VisitMut, WalkControl};
+use indexmap::map::Entry;
 use indexmap::IndexMap;
-use slr_parser::{parse_source, ConfigString, Error, ErrorKind, Printer, Source, Span, Visitor};
+use slr_parser::{
+	parse_source, ConfigString, Error, ErrorKind, Printer, Source, Span, StringQuoteType, Visitor,
+};
 use std::fmt::{self, Display, Formatter};
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::mem;
+use std::ops::{Index, IndexMut};
 use std::path::Path;
+use std::slice;
 use std::str::{from_utf8, FromStr};
+use std::sync::Arc;
 
 /// A configuration element.
+///
+/// Two elements are equal if they have the same kind, tags and contents, with
+/// table entries compared in order (see `eq_unordered` for the
+/// order-insensitive comparison). Spans, quote types, layouts and comments are
+/// ignored, since they do not affect what the element represents. `Hash` is
+/// consistent with this equality.
+///
+/// Cloning an element is cheap: the copies share their contents until one of
+/// them is modified, at which point the modified table or array is copied
+/// (but not its unmodified children). This also applies to the elements
+/// copied by expansions like `$name`.
 #[derive(Clone, Debug)]
 pub struct ConfigElement
 {
-	kind: ConfigElementKind,
+	kind: Arc<ConfigElementKind>,
+	layout: Layout,
 	span: Span,
+	key_span: Span,
+	tag_span: Span,
+	extent: Span,
+	quote_type: Option<StringQuoteType>,
+	key_quote_type: Option<StringQuoteType>,
+	comment: Option<String>,
 }
 
-// TODO: It's annoying that we lose the span information from Values and Table keys.
 /// The kind of the configuration element.
 #[derive(Clone, Debug)]
 pub enum ConfigElementKind
@@ -36,75 +62,111 @@ pub enum ConfigElementKind
 	Array(Vec<ConfigElement>),
 	/// An array of configuration elements with a string tag.
 	TaggedArray(String, Vec<ConfigElement>),
-	/// An array of configuration elements, printed with multiple lines.
-	MultiLineArray(Vec<ConfigElement>),
-	/// An array of configuration elements with a string tag, printed with multiple lines.
-	MultiLineTaggedArray(String, Vec<ConfigElement>),
+}
+
+/// How a table or an array is printed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Layout
+{
+	/// Empty tables and arrays are printed on one line, as are the arrays
+	/// that don't contain non-empty tables. Everything else is printed with
+	/// multiple lines.
+	#[default]
+	Auto,
+	/// Printed on one line, with the elements separated by commas.
+	OneLine,
+	/// Printed with multiple lines, one element per line.
+	MultiLine,
 }
 
 impl ConfigElement
 {
-	/// Creates a new empty table.
-	pub fn new_table() -> ConfigElement
+	fn from_kind(kind: ConfigElementKind) -> ConfigElement
 	{
 		ConfigElement {
-			kind: Table(IndexMap::new()),
+			kind: Arc::new(kind),
+			layout: Layout::Auto,
 			span: Span::new(),
+			key_span: Span::new(),
+			tag_span: Span::new(),
+			extent: Span::new(),
+			quote_type: None,
+			key_quote_type: None,
+			comment: None,
 		}
 	}
 
+	/// Creates a new empty table.
+	pub fn new_table() -> ConfigElement
+	{
+		ConfigElement::from_kind(Table(IndexMap::new()))
+	}
+
 	/// Creates a new empty tagged table.
 	pub fn new_tagged_table(tag: String) -> ConfigElement
 	{
-		ConfigElement {
-			kind: TaggedTable(tag, IndexMap::new()),
-			span: Span::new(),
-		}
+		ConfigElement::from_kind(TaggedTable(tag, IndexMap::new()))
 	}
 
 	/// Creates a new value.
 	pub fn new_value<T: ToString>(value: T) -> ConfigElement
 	{
-		ConfigElement {
-			kind: Value(value.to_string()),
-			span: Span::new(),
-		}
+		ConfigElement::from_kind(Value(value.to_string()))
 	}
 
 	/// Creates a new array.
 	pub fn new_array() -> ConfigElement
 	{
-		ConfigElement {
-			kind: Array(vec![]),
-			span: Span::new(),
-		}
+		ConfigElement::from_kind(Array(vec![]))
 	}
 
 	/// Creates a new tagged array.
 	pub fn new_tagged_array(tag: String) -> ConfigElement
 	{
-		ConfigElement {
-			kind: TaggedArray(tag, vec![]),
-			span: Span::new(),
-		}
+		ConfigElement::from_kind(TaggedArray(tag, vec![]))
 	}
 
-	/// Creates a new multi-line array.
+	/// Creates a new array with the `MultiLine` layout.
 	pub fn new_multi_line_array() -> ConfigElement
 	{
-		ConfigElement {
-			kind: MultiLineArray(vec![]),
-			span: Span::new(),
-		}
+		ConfigElement::new_array().with_layout(Layout::MultiLine)
 	}
 
-	/// Creates a new multi-line tagged array.
+	/// Creates a new tagged array with the `MultiLine` layout.
 	pub fn new_multi_line_tagged_array(tag: String) -> ConfigElement
 	{
-		ConfigElement {
-			kind: MultiLineTaggedArray(tag, vec![]),
-			span: Span::new(),
-		}
+		ConfigElement::new_tagged_array(tag).with_layout(Layout::MultiLine)
+	}
+
+	/// Sets the layout of this element, see `layout`.
+	pub fn with_layout(mut self, layout: Layout) -> ConfigElement
+	{
+		self.layout = layout;
+		self
+	}
+
+	pub(crate) fn with_span(mut self, span: Span) -> ConfigElement
+	{
+		self.span = span;
+		self
+	}
+
+	pub(crate) fn with_key_span(mut self, key_span: Span) -> ConfigElement
+	{
+		self.key_span = key_span;
+		self
+	}
+
+	pub(crate) fn with_tag_span(mut self, tag_span: Span) -> ConfigElement
+	{
+		self.tag_span = tag_span;
+		self
+	}
+
+	pub(crate) fn with_extent(mut self, extent: Span) -> ConfigElement
+	{
+		self.extent = extent;
+		self
 	}
 
 	/// Parses a source and returns a table. The source will be reset by this
@@ -152,22 +214,105 @@ impl ConfigElement
 		&self.kind
 	}
 
-	/// Returns the kind of this element.
+	/// Returns the kind of this element. If the contents are shared with other
+	/// clones of this element, they are copied first.
 	pub fn kind_mut(&mut self) -> &mut ConfigElementKind
 	{
-		&mut self.kind
+		Arc::make_mut(&mut self.kind)
+	}
+
+	/// Returns how this element is printed if it is a table or an array.
+	/// Values ignore the layout. Elements parsed from a source use the `Auto`
+	/// layout.
+	pub fn layout(&self) -> Layout
+	{
+		self.layout
+	}
+
+	/// Returns how this element is printed if it is a table or an array.
+	pub fn layout_mut(&mut self) -> &mut Layout
+	{
+		&mut self.layout
 	}
 
-	/// Returns the span associated with this element.
+	/// Returns the span associated with this element. For values this covers
+	/// the strings and expansions making up the value, for tables and arrays
+	/// this is the opening brace or bracket, and for the tagged variants this
+	/// is the tag. See `extent` for the span of the whole element.
 	pub fn span(&self) -> Span
 	{
 		self.span
 	}
 
+	/// Returns the span of the key this element was stored under in the
+	/// source. This is invalid for the elements of arrays and for elements not
+	/// parsed from a source.
+	pub fn key_span(&self) -> Span
+	{
+		self.key_span
+	}
+
+	/// Returns the span of the tag of this element, if it was parsed from a
+	/// source.
+	pub fn tag_span(&self) -> Span
+	{
+		self.tag_span
+	}
+
+	/// Returns the span of the whole element, e.g. from the tag or the opening
+	/// brace of a table to its closing brace. The key is not included. This is
+	/// invalid for the root table.
+	pub fn extent(&self) -> Span
+	{
+		self.extent
+	}
+
+	/// Returns how the value or the tag of this element is quoted, e.g. to keep
+	/// the original quoting of an element parsed from a source. This is `None`
+	/// for elements not read from a source and for values made of several
+	/// strings. When printing, it is used if it can still represent the value
+	/// or the tag.
+	pub fn quote_type(&self) -> Option<StringQuoteType>
+	{
+		self.quote_type
+	}
+
+	/// Returns how the value or the tag of this element is quoted.
+	pub fn quote_type_mut(&mut self) -> &mut Option<StringQuoteType>
+	{
+		&mut self.quote_type
+	}
+
+	/// Returns how the key this element is stored under is quoted, see
+	/// `quote_type`.
+	pub fn key_quote_type(&self) -> Option<StringQuoteType>
+	{
+		self.key_quote_type
+	}
+
+	/// Returns how the key this element is stored under is quoted.
+	pub fn key_quote_type_mut(&mut self) -> &mut Option<StringQuoteType>
+	{
+		&mut self.key_quote_type
+	}
+
+	/// Returns the comment attached to this element, which is printed before
+	/// it. Comments are not read from the source.
+	pub fn comment(&self) -> Option<&String>
+	{
+		self.comment.as_ref()
+	}
+
+	/// Returns the comment attached to this element.
+	pub fn comment_mut(&mut self) -> &mut Option<String>
+	{
+		&mut self.comment
+	}
+
 	/// If this is a table, returns a pointer to its contents.
 	pub fn as_table(&self) -> Option<&IndexMap<String, ConfigElement>>
 	{
-		match self.kind
+		match *self.kind
 		{
 			Table(ref table) | TaggedTable(_, ref table) => Some(table),
 			_ => None,
@@ -177,7 +322,7 @@ impl ConfigElement
 	/// If this is a table, returns its contents.
 	pub fn into_table(self) -> Option<IndexMap<String, ConfigElement>>
 	{
-		match self.kind
+		match Arc::unwrap_or_clone(self.kind)
 		{
 			Table(table) | TaggedTable(_, table) => Some(table),
 			_ => None,
@@ -187,7 +332,9 @@ impl ConfigElement
 	/// If this is a table, returns a pointer to its contents.
 	pub fn as_table_mut(&mut self) -> Option<&mut IndexMap<String, ConfigElement>>
 	{
-		match self.kind
+		// Check first, so shared contents are only copied if they are returned.
+		self.as_table()?;
+		match *Arc::make_mut(&mut self.kind)
 		{
 			Table(ref mut table) | TaggedTable(_, ref mut table) => Some(table),
 			_ => None,
@@ -197,7 +344,7 @@ impl ConfigElement
 	/// If this is a value, returns a pointer to its contents.
 	pub fn as_value(&self) -> Option<&String>
 	{
-		match self.kind
+		match *self.kind
 		{
 			Value(ref value) => Some(value),
 			_ => None,
@@ -207,7 +354,7 @@ impl ConfigElement
 	/// If this is a value, returns its contents.
 	pub fn into_value(self) -> Option<String>
 	{
-		match self.kind
+		match Arc::unwrap_or_clone(self.kind)
 		{
 			Value(value) => Some(value),
 			_ => None,
@@ -217,7 +364,8 @@ impl ConfigElement
 	/// If this is a value, returns a pointer to its contents.
 	pub fn as_value_mut(&mut self) -> Option<&mut String>
 	{
-		match self.kind
+		self.as_value()?;
+		match *Arc::make_mut(&mut self.kind)
 		{
 			Value(ref mut value) => Some(value),
 			_ => None,
@@ -227,12 +375,9 @@ impl ConfigElement
 	/// If this is an array, returns a pointer to its contents.
 	pub fn as_array(&self) -> Option<&Vec<ConfigElement>>
 	{
-		match self.kind
+		match *self.kind
 		{
-			Array(ref array)
-			| TaggedArray(_, ref array)
-			| MultiLineArray(ref array)
-			| MultiLineTaggedArray(_, ref array) => Some(array),
+			Array(ref array) | TaggedArray(_, ref array) => Some(array),
 			_ => None,
 		}
 	}
@@ -240,12 +385,9 @@ impl ConfigElement
 	/// If this is an array, returns its contents.
 	pub fn into_array(self) -> Option<Vec<ConfigElement>>
 	{
-		match self.kind
+		match Arc::unwrap_or_clone(self.kind)
 		{
-			Array(array)
-			| TaggedArray(_, array)
-			| MultiLineArray(array)
-			| MultiLineTaggedArray(_, array) => Some(array),
+			Array(array) | TaggedArray(_, array) => Some(array),
 			_ => None,
 		}
 	}
@@ -253,70 +395,485 @@ impl ConfigElement
 	/// If this is an array, returns a pointer to its contents.
 	pub fn as_array_mut(&mut self) -> Option<&mut Vec<ConfigElement>>
 	{
-		match self.kind
+		self.as_array()?;
+		match *Arc::make_mut(&mut self.kind)
 		{
-			Array(ref mut array)
-			| TaggedArray(_, ref mut array)
-			| MultiLineArray(ref mut array)
-			| MultiLineTaggedArray(_, ref mut array) => Some(array),
+			Array(ref mut array) | TaggedArray(_, ref mut array) => Some(array),
 			_ => None,
 		}
 	}
 
 	pub fn tag(&self) -> Option<&String>
 	{
-		match self.kind
+		match *self.kind
 		{
-			TaggedTable(ref tag, _)
-			| TaggedArray(ref tag, _)
-			| MultiLineTaggedArray(ref tag, _) => Some(tag),
+			TaggedTable(ref tag, _) | TaggedArray(ref tag, _) => Some(tag),
 			_ => None,
 		}
 	}
 
 	pub fn tag_mut(&mut self) -> Option<&mut String>
 	{
-		match self.kind
+		self.tag()?;
+		match *Arc::make_mut(&mut self.kind)
 		{
-			TaggedTable(ref mut tag, _)
-			| TaggedArray(ref mut tag, _)
-			| MultiLineTaggedArray(ref mut tag, _) => Some(tag),
+			TaggedTable(ref mut tag, _) | TaggedArray(ref mut tag, _) => Some(tag),
 			_ => None,
 		}
 	}
 
+	/// Returns the element at `path`, e.g. `a[2].b`. See `ElementPath` for
+	/// the syntax. The error says which segment of the path could not be
+	/// followed, and why.
+	pub fn get_path(&self, path: &str) -> Result<&ConfigElement, Error>
+	{
+		self.get_element_path(&ElementPath::parse(path)?)
+	}
+
+	/// Returns the element at `path`, see `get_path`.
+	pub fn get_path_mut(&mut self, path: &str) -> Result<&mut ConfigElement, Error>
+	{
+		self.get_element_path_mut(&ElementPath::parse(path)?)
+	}
+
+	/// Returns the element at a parsed path, see `get_path`.
+	pub fn get_element_path(&self, path: &ElementPath) -> Result<&ConfigElement, Error>
+	{
+		let mut element = self;
+		for segment in path.segments()
+		{
+			element = element.child(segment).map_err(|reason| {
+				Error::new(
+					ErrorKind::InvalidPath,
+					format!(
+						"error: Can't follow path '{}' at segment '{}': {}.\n",
+						path, segment, reason
+					),
+				)
+			})?;
+		}
+		Ok(element)
+	}
+
+	/// Returns the element at a parsed path, see `get_path`.
+	pub fn get_element_path_mut(&mut self, path: &ElementPath)
+		-> Result<&mut ConfigElement, Error>
+	{
+		// Check the path first, so the errors are only generated in one place.
+		self.get_element_path(path)?;
+		let mut element = self;
+		for segment in path.segments()
+		{
+			element = element.child_mut(segment).unwrap();
+		}
+		Ok(element)
+	}
+
+	fn child(&self, segment: &PathSegment) -> Result<&ConfigElement, String>
+	{
+		match *segment
+		{
+			PathSegment::Key(ref key) =>
+			{
+				if let Some(table) = self.as_table()
+				{
+					return table
+						.get(key)
+						.ok_or_else(|| "there is no element with that key".to_string());
+				}
+				match (self.as_array(), usize::from_str(key))
+				{
+					(Some(_), Ok(idx)) => self.child(&PathSegment::Index(idx)),
+					(Some(_), Err(_)) => Err("expected a table, found an array".to_string()),
+					(None, _) => Err("expected a table, found a value".to_string()),
+				}
+			}
+			PathSegment::Index(idx) => match self.as_array()
+			{
+				Some(array) => array.get(idx).ok_or_else(|| {
+					format!(
+						"index {} is out of bounds, the array has {} elements",
+						idx,
+						array.len()
+					)
+				}),
+				None if self.as_table().is_some() =>
+				{
+					Err("expected an array, found a table".to_string())
+				}
+				None => Err("expected an array, found a value".to_string()),
+			},
+		}
+	}
+
+	fn child_mut(&mut self, segment: &PathSegment) -> Option<&mut ConfigElement>
+	{
+		match *segment
+		{
+			PathSegment::Key(ref key) if self.as_table().is_some() =>
+			{
+				self.as_table_mut().unwrap().get_mut(key)
+			}
+			PathSegment::Key(ref key) => self
+				.as_array_mut()
+				.and_then(|array| array.get_mut(usize::from_str(key).ok()?)),
+			PathSegment::Index(idx) => self.as_array_mut().and_then(|array| array.get_mut(idx)),
+		}
+	}
+
 	/// Insert an element into a table or an array. Panics if self is a value.
 	/// `name` is ignored if self is an array.
 	pub fn insert<T: ToString>(&mut self, name: T, elem: ConfigElement)
 	{
-		match self.kind
+		match *Arc::make_mut(&mut self.kind)
 		{
 			Value(_) => panic!("Trying to insert an element into a value!"),
 			Table(ref mut table) | TaggedTable(_, ref mut table) =>
 			{
 				table.insert(name.to_string(), elem);
 			}
-			Array(ref mut array)
-			| TaggedArray(_, ref mut array)
-			| MultiLineArray(ref mut array)
-			| MultiLineTaggedArray(_, ref mut array) =>
+			Array(ref mut array) | TaggedArray(_, ref mut array) =>
 			{
 				array.push(elem);
 			}
 		}
 	}
 
+	/// Returns the child at `index`: a key for tables, a position for arrays.
+	/// Returns `None` if there is no such child, or if the index doesn't
+	/// apply to this kind of element.
+	pub fn get<I: ElementIndex>(&self, index: I) -> Option<&ConfigElement>
+	{
+		index.index_into(self)
+	}
+
+	/// Returns the child at `index`, see `get`.
+	pub fn get_mut<I: ElementIndex>(&mut self, index: I) -> Option<&mut ConfigElement>
+	{
+		index.index_into_mut(self)
+	}
+
+	/// Removes the child at `index` and returns it, see `get`. The order of
+	/// the remaining children is preserved.
+	pub fn remove<I: ElementIndex>(&mut self, index: I) -> Option<ConfigElement>
+	{
+		index.remove_from(self)
+	}
+
+	/// Parses a value using `FromStr`. If the element is not a value or can't
+	/// be parsed, the error points at the element and includes the `FromStr`
+	/// error, e.g. ``Can't parse `abc`: invalid digit found in string.``. The
+	/// source argument, if set, must be set to the source that was used to
+	/// parse the element.
+	pub fn parse<T: FromStr>(&self, source: Option<&Source>) -> Result<T, Error>
+	where
+		T::Err: Display,
+	{
+		let msg = match *self.kind
+		{
+			Value(ref value) => match T::from_str(value)
+			{
+				Ok(value) => return Ok(value),
+				Err(err) => format!("Can't parse `{}`: {}.", value, err),
+			},
+			_ => format!("Expected a value, found {}.", self.kind_name()),
+		};
+		Err(Error::from_span(
+			self.span,
+			source,
+			ErrorKind::InvalidRepr,
+			&msg,
+		))
+	}
+
+	/// Parses the child at `index` using `FromStr`, see `parse`. If there is
+	/// no such child, the error points at this element.
+	pub fn get_as<T: FromStr, I: ElementIndex>(
+		&self, index: I, source: Option<&Source>,
+	) -> Result<T, Error>
+	where
+		T::Err: Display,
+	{
+		if let Some(child) = self.get(&index)
+		{
+			return child.parse(source);
+		}
+		let msg = match *self.kind
+		{
+			Table(_) | TaggedTable(_, _) => format!("Missing key `{}`.", index),
+			Value(_) => format!("Expected a table or an array, found {}.", self.kind_name()),
+			_ => format!("Missing element at index `{}`.", index),
+		};
+		Err(Error::from_span(
+			self.span,
+			source,
+			ErrorKind::InvalidRepr,
+			&msg,
+		))
+	}
+
+	fn kind_name(&self) -> &'static str
+	{
+		match *self.kind
+		{
+			Value(_) => "a value",
+			Table(_) => "a table",
+			TaggedTable(_, _) => "a tagged table",
+			Array(_) => "an array",
+			TaggedArray(_, _) => "a tagged array",
+		}
+	}
+
+	/// Returns the entry for `key` in a table, for in-place manipulation.
+	/// Panics if self is not a table.
+	pub fn entry<T: ToString>(&mut self, key: T) -> Entry<'_, String, ConfigElement>
+	{
+		match self.as_table_mut()
+		{
+			Some(table) => table.entry(key.to_string()),
+			None => panic!("Trying to get an entry of a value or an array!"),
+		}
+	}
+
+	/// Returns the number of children of a table or an array. Values have no
+	/// children.
+	pub fn len(&self) -> usize
+	{
+		match (self.as_table(), self.as_array())
+		{
+			(Some(table), _) => table.len(),
+			(_, Some(array)) => array.len(),
+			_ => 0,
+		}
+	}
+
+	/// Returns true if this element has no children, see `len`.
+	pub fn is_empty(&self) -> bool
+	{
+		self.len() == 0
+	}
+
+	/// Returns an iterator over the children of a table or an array, together
+	/// with their keys. Array elements have no keys. Values have no children.
+	pub fn iter(&self) -> Iter<'_>
+	{
+		let children = match *self.kind
+		{
+			Value(_) => Children::Array([].iter()),
+			Table(ref table) | TaggedTable(_, ref table) => Children::Table(table.iter()),
+			Array(ref array) | TaggedArray(_, ref array) => Children::Array(array.iter()),
+		};
+		Iter { children }
+	}
+
+	/// Returns an iterator over the children of a table or an array, see
+	/// `iter`.
+	pub fn iter_mut(&mut self) -> IterMut<'_>
+	{
+		if self.as_value().is_some()
+		{
+			return IterMut {
+				children: Children::Array([].iter_mut()),
+			};
+		}
+		let children = match *Arc::make_mut(&mut self.kind)
+		{
+			Value(_) => unreachable!(),
+			Table(ref mut table) | TaggedTable(_, ref mut table) =>
+			{
+				Children::Table(table.iter_mut())
+			}
+			Array(ref mut array) | TaggedArray(_, ref mut array) =>
+			{
+				Children::Array(array.iter_mut())
+			}
+		};
+		IterMut { children }
+	}
+
+	/// Visits this element and all of its descendants, depth first and in
+	/// order. The visitor decides whether the children of each element are
+	/// visited, see `WalkControl`.
+	pub fn walk<V: Visit + ?Sized>(&self, visitor: &mut V)
+	{
+		self.walk_impl(&mut ElementPath::new(), visitor);
+	}
+
+	/// Returns false if the walk was stopped.
+	fn walk_impl<V: Visit + ?Sized>(&self, path: &mut ElementPath, visitor: &mut V) -> bool
+	{
+		match visitor.visit(path, self)
+		{
+			WalkControl::Continue => (),
+			WalkControl::SkipChildren => return true,
+			WalkControl::Stop => return false,
+		}
+		for (idx, (key, child)) in self.iter().enumerate()
+		{
+			path.push(child_segment(key, idx));
+			let done = !child.walk_impl(path, visitor);
+			path.pop();
+			if done
+			{
+				return false;
+			}
+		}
+		true
+	}
+
+	/// Like `walk`, but the visitor can modify the elements as they are
+	/// visited. Contents shared with clones of this element are only copied
+	/// where the visitor modifies them, as long as it only takes mutable
+	/// references to what it changes (e.g. calling `as_table_mut` copies the
+	/// table even if it is left unchanged).
+	pub fn walk_mut<V: VisitMut + ?Sized>(&mut self, visitor: &mut V)
+	{
+		self.walk_mut_impl(&mut ElementPath::new(), visitor);
+	}
+
+	/// Returns false if the walk was stopped.
+	fn walk_mut_impl<V: VisitMut + ?Sized>(
+		&mut self, path: &mut ElementPath, visitor: &mut V,
+	) -> bool
+	{
+		match visitor.visit_mut(path, self)
+		{
+			WalkControl::Continue => (),
+			WalkControl::SkipChildren => return true,
+			WalkControl::Stop => return false,
+		}
+		if Arc::get_mut(&mut self.kind).is_some()
+		{
+			for (idx, (key, child)) in self.iter_mut().enumerate()
+			{
+				path.push(child_segment(key, idx));
+				let done = !child.walk_mut_impl(path, visitor);
+				path.pop();
+				if done
+				{
+					return false;
+				}
+			}
+			return true;
+		}
+		// The contents are shared, so the children are walked as cheap copies
+		// which are only stored (copying the contents once) if they were
+		// changed.
+		let mut changed = vec![];
+		let mut done = false;
+		for (idx, (key, child)) in self.iter().enumerate()
+		{
+			path.push(child_segment(key, idx));
+			let mut new_child = child.clone();
+			done = !new_child.walk_mut_impl(path, visitor);
+			path.pop();
+			if !new_child.is_same(child)
+			{
+				changed.push((idx, new_child));
+			}
+			if done
+			{
+				break;
+			}
+		}
+		if !changed.is_empty()
+		{
+			match *Arc::make_mut(&mut self.kind)
+			{
+				Value(_) => unreachable!(),
+				Table(ref mut table) | TaggedTable(_, ref mut table) =>
+				{
+					for (idx, new_child) in changed
+					{
+						*table.get_index_mut(idx).unwrap().1 = new_child;
+					}
+				}
+				Array(ref mut array) | TaggedArray(_, ref mut array) =>
+				{
+					for (idx, new_child) in changed
+					{
+						array[idx] = new_child;
+					}
+				}
+			}
+		}
+		!done
+	}
+
+	/// Returns true if `other` is an unmodified clone of this element.
+	fn is_same(&self, other: &ConfigElement) -> bool
+	{
+		Arc::ptr_eq(&self.kind, &other.kind)
+			&& self.layout == other.layout
+			&& self.span == other.span
+			&& self.key_span == other.key_span
+			&& self.tag_span == other.tag_span
+			&& self.extent == other.extent
+			&& self.quote_type == other.quote_type
+			&& self.key_quote_type == other.key_quote_type
+			&& self.comment == other.comment
+	}
+
+	/// Calls `f` for every value in this element and its descendants (or this
+	/// element itself, if it is a value) with the path of the value (see
+	/// `walk`) and its contents. If `f` returns a string, it replaces the
+	/// value. Contents shared with clones of this element are only copied
+	/// where values are replaced.
+	pub fn map_values<F: FnMut(&ElementPath, &str) -> Option<String>>(&mut self, mut f: F)
+	{
+		self.walk_mut(&mut |path: &ElementPath, element: &mut ConfigElement| {
+			if let Some(new_value) = element.as_value().and_then(|value| f(path, value))
+			{
+				*element.as_value_mut().unwrap() = new_value;
+			}
+			WalkControl::Continue
+		});
+	}
+
+	/// Like `==`, but compares the entries of tables regardless of their
+	/// order, at every level of nesting.
+	pub fn eq_unordered(&self, other: &ConfigElement) -> bool
+	{
+		match (self.kind.contents(), other.kind.contents())
+		{
+			((tag1, Contents::Table(table1)), (tag2, Contents::Table(table2))) =>
+			{
+				tag1 == tag2
+					&& table1.len() == table2.len()
+					&& table1
+						.iter()
+						.all(|(k, v1)| table2.get(k).is_some_and(|v2| v1.eq_unordered(v2)))
+			}
+			((tag1, Contents::Array(array1)), (tag2, Contents::Array(array2))) =>
+			{
+				tag1 == tag2
+					&& array1.len() == array2.len()
+					&& array1
+						.iter()
+						.zip(array2)
+						.all(|(v1, v2)| v1.eq_unordered(v2))
+			}
+			((_, Contents::Value(value1)), (_, Contents::Value(value2))) => value1 == value2,
+			_ => false,
+		}
+	}
+
 	/// Outputs the string representation of this element into into a printer.
 	pub fn print<W: io::Write>(
 		&self, name: Option<&str>, is_root: bool, printer: &mut Printer<W>,
 	) -> Result<(), io::Error>
 	{
-		match self.kind
+		if let Some(ref comment) = self.comment
+		{
+			printer.comment(comment);
+		}
+		printer.quote_types(self.key_quote_type, self.quote_type);
+		match *self.kind
 		{
 			Value(ref val) => printer.value(name, &val)?,
 			Table(ref table) =>
 			{
-				printer.start_table(name, is_root, table.is_empty())?;
+				printer.start_table(name, is_root, self.one_line())?;
 				for (k, v) in table
 				{
 					v.print(Some(k), false, printer)?;
@@ -325,7 +882,7 @@ impl ConfigElement
 			}
 			TaggedTable(ref tag, ref table) =>
 			{
-				printer.start_tagged_table(name, tag, is_root, table.is_empty())?;
+				printer.start_tagged_table(name, tag, is_root, self.one_line())?;
 				for (k, v) in table
 				{
 					v.print(Some(k), false, printer)?;
@@ -334,23 +891,7 @@ impl ConfigElement
 			}
 			Array(ref array) =>
 			{
-				let mut one_line = true;
-				for v in array
-				{
-					match v.kind
-					{
-						Table(ref table) | TaggedTable(_, ref table) =>
-						{
-							if !table.is_empty()
-							{
-								one_line = false;
-								break;
-							}
-						}
-						_ => (),
-					}
-				}
-				printer.start_array(name, one_line)?;
+				printer.start_array(name, self.one_line())?;
 				for v in array
 				{
 					v.print(None, false, printer)?;
@@ -359,41 +900,7 @@ impl ConfigElement
 			}
 			TaggedArray(ref tag, ref array) =>
 			{
-				let mut one_line = true;
-				for v in array
-				{
-					match v.kind
-					{
-						Table(ref table) | TaggedTable(_, ref table) =>
-						{
-							if !table.is_empty()
-							{
-								one_line = false;
-								break;
-							}
-						}
-						_ => (),
-					}
-				}
-				printer.start_tagged_array(name, tag, one_line)?;
-				for v in array
-				{
-					v.print(None, false, printer)?;
-				}
-				printer.end_array()?;
-			}
-			MultiLineArray(ref array) =>
-			{
-				printer.start_array(name, false)?;
-				for v in array
-				{
-					v.print(None, false, printer)?;
-				}
-				printer.end_array()?;
-			}
-			MultiLineTaggedArray(ref tag, ref array) =>
-			{
-				printer.start_tagged_array(name, tag, false)?;
+				printer.start_tagged_array(name, tag, self.one_line())?;
 				for v in array
 				{
 					v.print(None, false, printer)?;
@@ -403,6 +910,299 @@ impl ConfigElement
 		}
 		Ok(())
 	}
+
+	/// Whether this table or array is printed on one line, see `Layout`.
+	fn one_line(&self) -> bool
+	{
+		match self.layout
+		{
+			Layout::OneLine => true,
+			Layout::MultiLine => false,
+			Layout::Auto => match *self.kind
+			{
+				Value(_) => true,
+				Table(ref table) | TaggedTable(_, ref table) => table.is_empty(),
+				Array(ref array) | TaggedArray(_, ref array) => array
+					.iter()
+					.all(|v| v.as_table().is_none_or(|table| table.is_empty())),
+			},
+		}
+	}
+}
+
+/// Returns the path segment of a child yielded by `ConfigElement::iter`.
+fn child_segment(key: Option<&str>, idx: usize) -> PathSegment
+{
+	match key
+	{
+		Some(key) => PathSegment::Key(key.to_string()),
+		None => PathSegment::Index(idx),
+	}
+}
+
+/// A type that can be used to look up the children of a table or an array,
+/// see `ConfigElement::get`. Strings look up table keys and integers look up
+/// array positions.
+pub trait ElementIndex: Display
+{
+	/// Returns the child at this index.
+	fn index_into<'l>(&self, element: &'l ConfigElement) -> Option<&'l ConfigElement>;
+
+	/// Returns the child at this index.
+	fn index_into_mut<'l>(&self, element: &'l mut ConfigElement) -> Option<&'l mut ConfigElement>;
+
+	/// Removes the child at this index.
+	fn remove_from(&self, element: &mut ConfigElement) -> Option<ConfigElement>;
+}
+
+impl ElementIndex for str
+{
+	fn index_into<'l>(&self, element: &'l ConfigElement) -> Option<&'l ConfigElement>
+	{
+		element.as_table().and_then(|table| table.get(self))
+	}
+
+	fn index_into_mut<'l>(&self, element: &'l mut ConfigElement) -> Option<&'l mut ConfigElement>
+	{
+		element.as_table_mut().and_then(|table| table.get_mut(self))
+	}
+
+	fn remove_from(&self, element: &mut ConfigElement) -> Option<ConfigElement>
+	{
+		element
+			.as_table_mut()
+			.and_then(|table| table.shift_remove(self))
+	}
+}
+
+impl ElementIndex for String
+{
+	fn index_into<'l>(&self, element: &'l ConfigElement) -> Option<&'l ConfigElement>
+	{
+		self[..].index_into(element)
+	}
+
+	fn index_into_mut<'l>(&self, element: &'l mut ConfigElement) -> Option<&'l mut ConfigElement>
+	{
+		self[..].index_into_mut(element)
+	}
+
+	fn remove_from(&self, element: &mut ConfigElement) -> Option<ConfigElement>
+	{
+		self[..].remove_from(element)
+	}
+}
+
+impl ElementIndex for usize
+{
+	fn index_into<'l>(&self, element: &'l ConfigElement) -> Option<&'l ConfigElement>
+	{
+		element.as_array().and_then(|array| array.get(*self))
+	}
+
+	fn index_into_mut<'l>(&self, element: &'l mut ConfigElement) -> Option<&'l mut ConfigElement>
+	{
+		element
+			.as_array_mut()
+			.and_then(|array| array.get_mut(*self))
+	}
+
+	fn remove_from(&self, element: &mut ConfigElement) -> Option<ConfigElement>
+	{
+		match element.as_array_mut()
+		{
+			Some(array) if *self < array.len() => Some(array.remove(*self)),
+			_ => None,
+		}
+	}
+}
+
+/// Path segments behave like they do in `ConfigElement::get_path`, i.e.
+/// arrays also accept numeric keys.
+impl ElementIndex for PathSegment
+{
+	fn index_into<'l>(&self, element: &'l ConfigElement) -> Option<&'l ConfigElement>
+	{
+		element.child(self).ok()
+	}
+
+	fn index_into_mut<'l>(&self, element: &'l mut ConfigElement) -> Option<&'l mut ConfigElement>
+	{
+		element.child_mut(self)
+	}
+
+	fn remove_from(&self, element: &mut ConfigElement) -> Option<ConfigElement>
+	{
+		match *self
+		{
+			PathSegment::Key(ref key) if element.as_table().is_some() => key.remove_from(element),
+			PathSegment::Key(ref key) => usize::from_str(key).ok()?.remove_from(element),
+			PathSegment::Index(idx) => idx.remove_from(element),
+		}
+	}
+}
+
+impl<T: ?Sized + ElementIndex> ElementIndex for &T
+{
+	fn index_into<'l>(&self, element: &'l ConfigElement) -> Option<&'l ConfigElement>
+	{
+		(**self).index_into(element)
+	}
+
+	fn index_into_mut<'l>(&self, element: &'l mut ConfigElement) -> Option<&'l mut ConfigElement>
+	{
+		(**self).index_into_mut(element)
+	}
+
+	fn remove_from(&self, element: &mut ConfigElement) -> Option<ConfigElement>
+	{
+		(**self).remove_from(element)
+	}
+}
+
+/// Panics if there is no child at `index`, see `ConfigElement::get`.
+impl<I: ElementIndex> Index<I> for ConfigElement
+{
+	type Output = ConfigElement;
+
+	fn index(&self, index: I) -> &ConfigElement
+	{
+		match index.index_into(self)
+		{
+			Some(child) => child,
+			None => panic!("No element at '{}'!", index),
+		}
+	}
+}
+
+/// Panics if there is no child at `index`, see `ConfigElement::get`.
+impl<I: ElementIndex> IndexMut<I> for ConfigElement
+{
+	fn index_mut(&mut self, index: I) -> &mut ConfigElement
+	{
+		// Checking first avoids borrowing self for the panic message.
+		if index.index_into(self).is_none()
+		{
+			panic!("No element at '{}'!", index);
+		}
+		index.index_into_mut(self).unwrap()
+	}
+}
+
+enum Children<T, A>
+{
+	Table(T),
+	Array(A),
+}
+
+/// An iterator over the children of an element, see `ConfigElement::iter`.
+pub struct Iter<'l>
+{
+	children:
+		Children<indexmap::map::Iter<'l, String, ConfigElement>, slice::Iter<'l, ConfigElement>>,
+}
+
+impl<'l> Iterator for Iter<'l>
+{
+	type Item = (Option<&'l str>, &'l ConfigElement);
+
+	fn next(&mut self) -> Option<Self::Item>
+	{
+		match self.children
+		{
+			Children::Table(ref mut iter) => iter.next().map(|(k, v)| (Some(&k[..]), v)),
+			Children::Array(ref mut iter) => iter.next().map(|v| (None, v)),
+		}
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>)
+	{
+		match self.children
+		{
+			Children::Table(ref iter) => iter.size_hint(),
+			Children::Array(ref iter) => iter.size_hint(),
+		}
+	}
+}
+
+/// An iterator over the children of an element, see `ConfigElement::iter_mut`.
+pub struct IterMut<'l>
+{
+	children: Children<
+		indexmap::map::IterMut<'l, String, ConfigElement>,
+		slice::IterMut<'l, ConfigElement>,
+	>,
+}
+
+impl<'l> Iterator for IterMut<'l>
+{
+	type Item = (Option<&'l str>, &'l mut ConfigElement);
+
+	fn next(&mut self) -> Option<Self::Item>
+	{
+		match self.children
+		{
+			Children::Table(ref mut iter) => iter.next().map(|(k, v)| (Some(&k[..]), v)),
+			Children::Array(ref mut iter) => iter.next().map(|v| (None, v)),
+		}
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>)
+	{
+		match self.children
+		{
+			Children::Table(ref iter) => iter.size_hint(),
+			Children::Array(ref iter) => iter.size_hint(),
+		}
+	}
+}
+
+/// Collects elements into an array.
+impl FromIterator<ConfigElement> for ConfigElement
+{
+	fn from_iter<I: IntoIterator<Item = ConfigElement>>(iter: I) -> ConfigElement
+	{
+		let mut array = ConfigElement::new_array();
+		array.extend(iter);
+		array
+	}
+}
+
+/// Collects key-element pairs into a table.
+impl<K: ToString> FromIterator<(K, ConfigElement)> for ConfigElement
+{
+	fn from_iter<I: IntoIterator<Item = (K, ConfigElement)>>(iter: I) -> ConfigElement
+	{
+		let mut table = ConfigElement::new_table();
+		table.extend(iter);
+		table
+	}
+}
+
+/// Appends elements to an array. Panics if self is not an array.
+impl Extend<ConfigElement> for ConfigElement
+{
+	fn extend<I: IntoIterator<Item = ConfigElement>>(&mut self, iter: I)
+	{
+		match self.as_array_mut()
+		{
+			Some(array) => array.extend(iter),
+			None => panic!("Trying to extend a value or a table with unnamed elements!"),
+		}
+	}
+}
+
+/// Inserts key-element pairs, like `ConfigElement::insert`. Panics if self is
+/// a value.
+impl<K: ToString> Extend<(K, ConfigElement)> for ConfigElement
+{
+	fn extend<I: IntoIterator<Item = (K, ConfigElement)>>(&mut self, iter: I)
+	{
+		for (k, v) in iter
+		{
+			self.insert(k, v);
+		}
+	}
 }
 
 impl Display for ConfigElement
@@ -420,6 +1220,108 @@ impl Display for ConfigElement
 	}
 }
 
+/// The contents of a `ConfigElementKind`, without the tag.
+enum Contents<'l>
+{
+	Value(&'l String),
+	Table(&'l IndexMap<String, ConfigElement>),
+	Array(&'l Vec<ConfigElement>),
+}
+
+impl ConfigElementKind
+{
+	/// Returns the tag and the contents of this kind.
+	fn contents(&self) -> (Option<&String>, Contents<'_>)
+	{
+		match *self
+		{
+			ConfigElementKind::Value(ref value) => (None, Contents::Value(value)),
+			ConfigElementKind::Table(ref table) => (None, Contents::Table(table)),
+			ConfigElementKind::TaggedTable(ref tag, ref table) =>
+			{
+				(Some(tag), Contents::Table(table))
+			}
+			ConfigElementKind::Array(ref array) => (None, Contents::Array(array)),
+			ConfigElementKind::TaggedArray(ref tag, ref array) =>
+			{
+				(Some(tag), Contents::Array(array))
+			}
+		}
+	}
+}
+
+impl PartialEq for ConfigElementKind
+{
+	fn eq(&self, other: &ConfigElementKind) -> bool
+	{
+		match (self.contents(), other.contents())
+		{
+			((tag1, Contents::Table(table1)), (tag2, Contents::Table(table2))) =>
+			{
+				// IndexMap's own equality ignores the order of the entries.
+				tag1 == tag2 && table1.len() == table2.len() && table1.iter().eq(table2.iter())
+			}
+			((tag1, Contents::Array(array1)), (tag2, Contents::Array(array2))) =>
+			{
+				tag1 == tag2 && array1 == array2
+			}
+			((_, Contents::Value(value1)), (_, Contents::Value(value2))) => value1 == value2,
+			_ => false,
+		}
+	}
+}
+
+impl Eq for ConfigElementKind {}
+
+impl Hash for ConfigElementKind
+{
+	fn hash<H: Hasher>(&self, state: &mut H)
+	{
+		let (tag, contents) = self.contents();
+		tag.hash(state);
+		match contents
+		{
+			Contents::Value(value) =>
+			{
+				0u8.hash(state);
+				value.hash(state);
+			}
+			Contents::Table(table) =>
+			{
+				1u8.hash(state);
+				table.len().hash(state);
+				for entry in table
+				{
+					entry.hash(state);
+				}
+			}
+			Contents::Array(array) =>
+			{
+				2u8.hash(state);
+				array.hash(state);
+			}
+		}
+	}
+}
+
+impl PartialEq for ConfigElement
+{
+	fn eq(&self, other: &ConfigElement) -> bool
+	{
+		self.kind == other.kind
+	}
+}
+
+impl Eq for ConfigElement {}
+
+impl Hash for ConfigElement
+{
+	fn hash<H: Hasher>(&self, state: &mut H)
+	{
+		self.kind.hash(state);
+	}
+}
+
 fn visit_error<'l>(span: Span, source: &Source<'l>, msg: &str) -> Result<(), Error>
 {
 	Err(Error::from_span(
@@ -430,7 +1332,7 @@ fn visit_error<'l>(span: Span, source: &Source<'l>, msg: &str) -> Result<(), Err
 	))
 }
 
-struct ConfigElementVisitor
+pub(crate) struct ConfigElementVisitor
 {
 	// Name, element, initialized
 	stack: Vec<(String, ConfigElement, bool)>,
@@ -438,7 +1340,7 @@ struct ConfigElementVisitor
 
 impl ConfigElementVisitor
 {
-	fn new(root: ConfigElement) -> ConfigElementVisitor
+	pub(crate) fn new(root: ConfigElement) -> ConfigElementVisitor
 	{
 		ConfigElementVisitor {
 			stack: vec![("root".to_string(), root, true)],
@@ -450,26 +1352,46 @@ impl ConfigElementVisitor
 		assert!(self.stack.len() == 1);
 		self.stack.pop().unwrap().1
 	}
+
+	/// Returns the element currently being built.
+	pub(crate) fn top(&self) -> &ConfigElement
+	{
+		&self.stack.last().unwrap().1
+	}
+
+	/// Returns the element currently being built.
+	pub(crate) fn top_mut(&mut self) -> &mut ConfigElement
+	{
+		&mut self.stack.last_mut().unwrap().1
+	}
+
+	/// Finishes the element currently being built and returns it with its
+	/// name, without inserting it into its parent.
+	pub(crate) fn pop_element(&mut self, extent: Span) -> (String, ConfigElement)
+	{
+		let (name, mut elem, _) = self.stack.pop().unwrap();
+		elem.extent = extent;
+		(name, elem)
+	}
 }
 
 impl<'l> Visitor<'l> for ConfigElementVisitor
 {
 	fn start_element(&mut self, _src: &Source<'l>, name: ConfigString<'l>) -> Result<(), Error>
 	{
-		self.stack.push((
-			name.to_string(),
-			ConfigElement::new_value("".to_string()),
-			false,
-		));
+		let mut elem = ConfigElement::new_value("".to_string());
+		elem.key_span = name.span;
+		elem.key_quote_type = name.quote_type();
+		self.stack.push((name.to_string(), elem, false));
 		Ok(())
 	}
 
-	fn end_element(&mut self) -> Result<(), Error>
+	fn end_element(&mut self, _src: &Source<'l>, extent: Span) -> Result<(), Error>
 	{
 		let stack_size = self.stack.len();
 		if stack_size > 1
 		{
-			let (name, elem, _) = self.stack.pop().unwrap();
+			let (name, elem) = self.pop_element(extent);
 			self.stack[stack_size - 2].1.insert(name, elem);
 		}
 		Ok(())
@@ -479,11 +1401,23 @@ impl<'l> Visitor<'l> for ConfigElementVisitor
 	{
 		let stack_size = self.stack.len();
 		{
-			let elem = &mut self.stack[stack_size - 1].1;
+			let (_, ref mut elem, initialized) = self.stack[stack_size - 1];
 			elem.span.combine(string.span);
-			match elem.kind
+			match *Arc::make_mut(&mut elem.kind)
 			{
-				Value(ref mut val) => string.append_to_string(val),
+				Value(ref mut val) =>
+				{
+					string.append_to_string(val);
+					// Values made of several strings have no single quote type.
+					elem.quote_type = if initialized
+					{
+						None
+					}
+					else
+					{
+						string.quote_type()
+					};
+				}
 				Table(_) =>
 				{
 					return visit_error(string.span, src, "Cannot append a string to a table")
@@ -496,11 +1430,11 @@ impl<'l> Visitor<'l> for ConfigElementVisitor
 						"Cannot append a string to a tagged table",
 					)
 				}
-				Array(_) | MultiLineArray(_) =>
+				Array(_) =>
 				{
 					return visit_error(string.span, src, "Cannot append a string to an array")
 				}
-				TaggedArray(_, _) | MultiLineTaggedArray(_, _) =>
+				TaggedArray(_, _) =>
 				{
 					return visit_error(
 						string.span,
@@ -517,7 +1451,7 @@ impl<'l> Visitor<'l> for ConfigElementVisitor
 	fn set_table(&mut self, _src: &Source<'l>, span: Span) -> Result<(), Error>
 	{
 		let stack_size = self.stack.len();
-		self.stack[stack_size - 1].1 = ConfigElement::new_table();
+		self.stack[stack_size - 1].1.kind = Arc::new(Table(IndexMap::new()));
 		self.stack[stack_size - 1].1.span = span;
 		self.stack[stack_size - 1].2 = true;
 		Ok(())
@@ -528,8 +1462,10 @@ impl<'l> Visitor<'l> for ConfigElementVisitor
 	) -> Result<(), Error>
 	{
 		let stack_size = self.stack.len();
-		self.stack[stack_size - 1].1 = ConfigElement::new_tagged_table(tag.to_string());
+		self.stack[stack_size - 1].1.kind = Arc::new(TaggedTable(tag.to_string(), IndexMap::new()));
 		self.stack[stack_size - 1].1.span = span;
+		self.stack[stack_size - 1].1.quote_type = tag.quote_type();
+		self.stack[stack_size - 1].1.tag_span = tag.span;
 		self.stack[stack_size - 1].2 = true;
 		Ok(())
 	}
@@ -537,7 +1473,7 @@ impl<'l> Visitor<'l> for ConfigElementVisitor
 	fn set_array(&mut self, _src: &Source<'l>, span: Span) -> Result<(), Error>
 	{
 		let stack_size = self.stack.len();
-		self.stack[stack_size - 1].1 = ConfigElement::new_array();
+		self.stack[stack_size - 1].1.kind = Arc::new(Array(vec![]));
 		self.stack[stack_size - 1].1.span = span;
 		self.stack[stack_size - 1].2 = true;
 		Ok(())
@@ -548,8 +1484,10 @@ impl<'l> Visitor<'l> for ConfigElementVisitor
 	) -> Result<(), Error>
 	{
 		let stack_size = self.stack.len();
-		self.stack[stack_size - 1].1 = ConfigElement::new_tagged_array(tag.to_string());
+		self.stack[stack_size - 1].1.kind = Arc::new(TaggedArray(tag.to_string(), vec![]));
 		self.stack[stack_size - 1].1.span = span;
+		self.stack[stack_size - 1].1.quote_type = tag.quote_type();
+		self.stack[stack_size - 1].1.tag_span = tag.span;
 		self.stack[stack_size - 1].2 = true;
 		Ok(())
 	}
@@ -567,17 +1505,14 @@ impl<'l> Visitor<'l> for ConfigElementVisitor
 			{
 				continue;
 			}
-			match elem.kind
+			match *elem.kind
 			{
 				Value(_) => continue,
 				Table(ref table) | TaggedTable(_, ref table) =>
 				{
 					found_element = table.get(&name).map(|v| v.clone());
 				}
-				Array(ref array)
-				| TaggedArray(_, ref array)
-				| MultiLineArray(ref array)
-				| MultiLineTaggedArray(_, ref array) =>
+				Array(ref array) | TaggedArray(_, ref array) =>
 				{
 					found_element = <usize>::from_str(&name)
 						.ok()
@@ -605,21 +1540,22 @@ impl<'l> Visitor<'l> for ConfigElementVisitor
 		let lhs_is_initialized = self.stack[stack_size - 1].2;
 		if lhs_is_initialized
 		{
-			match self.stack[stack_size - 1].1.kind
+			match *Arc::make_mut(&mut self.stack[stack_size - 1].1.kind)
 			{
-				Value(ref mut lhs_val) => match found_element.kind
+				Value(ref mut lhs_val) => match *found_element.kind
 				{
-					Value(ref found_val) => lhs_val.push_str(found_val),
+					Value(ref found_val) =>
+					{
+						lhs_val.push_str(found_val);
+						self.stack[stack_size - 1].1.quote_type = None;
+					}
 					Table(_) => return visit_error(span, src, "Cannot append a table to a value"),
 					TaggedTable(_, _) =>
 					{
 						return visit_error(span, src, "Cannot append a tagged table to a value")
 					}
-					Array(_) | MultiLineArray(_) =>
-					{
-						return visit_error(span, src, "Cannot append an array to a value")
-					}
-					TaggedArray(_, _) | MultiLineTaggedArray(_, _) =>
+					Array(_) => return visit_error(span, src, "Cannot append an array to a value"),
+					TaggedArray(_, _) =>
 					{
 						return visit_error(span, src, "Cannot append an tagged array to a value")
 					}
@@ -629,11 +1565,8 @@ impl<'l> Visitor<'l> for ConfigElementVisitor
 				{
 					return visit_error(span, src, "Cannot append to a tagged table")
 				}
-				Array(_) | MultiLineArray(_) =>
-				{
-					return visit_error(span, src, "Cannot append to an array")
-				}
-				TaggedArray(_, _) | MultiLineTaggedArray(_, _) =>
+				Array(_) => return visit_error(span, src, "Cannot append to an array"),
+				TaggedArray(_, _) =>
 				{
 					return visit_error(span, src, "Cannot append to a tagged array")
 				}
@@ -641,7 +1574,11 @@ impl<'l> Visitor<'l> for ConfigElementVisitor
 		}
 		else
 		{
+			let key_span = self.stack[stack_size - 1].1.key_span;
+			let key_quote_type = self.stack[stack_size - 1].1.key_quote_type;
 			self.stack[stack_size - 1].1 = found_element;
+			self.stack[stack_size - 1].1.key_span = key_span;
+			self.stack[stack_size - 1].1.key_quote_type = key_quote_type;
 			self.stack[stack_size - 1].2 = true;
 		}
 		self.stack[stack_size - 1].1.span = span;
@@ -0,0 +1,598 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+use crate::config_element::ConfigElement;
+use crate::de::parse_integer;
+use slr_parser::{Error, ErrorKind, Source};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::error;
+use std::hash::{BuildHasher, Hash};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A type that can be created directly from a configuration element, without
+/// going through serde. Unlike `Deserialize`, the implementation has access to
+/// the whole element, so it can e.g. accept several alternative
+/// representations, and report errors with the span of the exact element that
+/// caused them (see `Error::from_span`).
+///
+/// This can be derived with `#[derive(FromElement)]`. Structs are read from
+/// tables, tuple structs from arrays, newtype structs from their contents,
+/// unit variants from values and other variants from tagged arrays and tables.
+/// Fields can be marked with `#[slr(rename = "name")]` and `#[slr(default)]`.
+pub trait FromElement: Sized
+{
+	/// Creates a value from an element. The source argument, if set, must be
+	/// set to the source that was used to parse the element.
+	fn from_element(element: &ConfigElement, source: Option<&Source>) -> Result<Self, Error>;
+
+	/// Returns the value to use when a struct field is absent. By default,
+	/// absent fields are an error, `Option` returns `None`.
+	fn from_missing() -> Option<Self>
+	{
+		None
+	}
+
+	/// Reads an element on top of this value, keeping what the element
+	/// doesn't mention. By default the value is replaced. Derived structs
+	/// merge the fields present in the table and keep the others, `Option`
+	/// and `Box` merge into their contents.
+	fn merge_element(
+		&mut self, element: &ConfigElement, source: Option<&Source>,
+	) -> Result<(), Error>
+	{
+		*self = Self::from_element(element, source)?;
+		Ok(())
+	}
+}
+
+/// A type that can be converted directly to a configuration element, without
+/// going through serde. This can be derived with `#[derive(ToElement)]`, which
+/// uses the same representation as `#[derive(FromElement)]`.
+pub trait ToElement
+{
+	/// Creates an element from this value.
+	fn to_element(&self) -> ConfigElement;
+}
+
+fn repr_error(element: &ConfigElement, source: Option<&Source>, text: &str) -> Error
+{
+	Error::from_span(element.span(), source, ErrorKind::InvalidRepr, text)
+}
+
+fn primitive<T: FromStr>(
+	element: &ConfigElement, source: Option<&Source>, name: &str,
+) -> Result<T, Error>
+where
+	T::Err: error::Error,
+{
+	match element.as_value()
+	{
+		Some(value) => T::from_str(value).map_err(|e| repr_error(element, source, &e.to_string())),
+		None => Err(repr_error(
+			element,
+			source,
+			&format!("Can't parse array/table as {}.", name),
+		)),
+	}
+}
+
+fn array<'l>(
+	element: &'l ConfigElement, source: Option<&Source>,
+) -> Result<&'l Vec<ConfigElement>, Error>
+{
+	element
+		.as_array()
+		.ok_or_else(|| repr_error(element, source, "Expected an array."))
+}
+
+fn elements<T: FromElement, C: FromIterator<T>>(
+	element: &ConfigElement, source: Option<&Source>,
+) -> Result<C, Error>
+{
+	array(element, source)?
+		.iter()
+		.map(|e| T::from_element(e, source))
+		.collect()
+}
+
+fn entries<K: FromElement, V: FromElement, C: FromIterator<(K, V)>>(
+	element: &ConfigElement, source: Option<&Source>,
+) -> Result<C, Error>
+{
+	let table = element
+		.as_table()
+		.ok_or_else(|| repr_error(element, source, "Expected a table."))?;
+	table
+		.iter()
+		.map(|(k, v)| {
+			let key = ConfigElement::new_value(k).with_span(v.key_span());
+			Ok((K::from_element(&key, source)?, V::from_element(v, source)?))
+		})
+		.collect()
+}
+
+fn array_element<'l, T: ToElement + 'l, I: IntoIterator<Item = &'l T>>(elements: I)
+	-> ConfigElement
+{
+	let mut array = ConfigElement::new_array();
+	for element in elements
+	{
+		array.insert("", element.to_element());
+	}
+	array
+}
+
+fn table_element<'l, K: ToString + 'l, V: ToElement + 'l, I: IntoIterator<Item = (&'l K, &'l V)>>(
+	entries: I,
+) -> ConfigElement
+{
+	let mut table = ConfigElement::new_table();
+	for (k, v) in entries
+	{
+		table.insert(k.to_string(), v.to_element());
+	}
+	table
+}
+
+macro_rules! impl_integer {
+	($($t: ident)*) => {
+		$(
+			impl FromElement for $t
+			{
+				fn from_element(element: &ConfigElement, source: Option<&Source>)
+					-> Result<$t, Error>
+				{
+					match element.as_value()
+					{
+						Some(value) =>
+						{
+							parse_integer(value).map_err(|e| repr_error(element, source, &e))
+						}
+						None => Err(repr_error(
+							element,
+							source,
+							concat!("Can't parse array/table as ", stringify!($t), "."),
+						)),
+					}
+				}
+			}
+		)*
+	};
+}
+
+impl_integer!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize);
+
+macro_rules! impl_primitive {
+	($($t: ident)*) => {
+		$(
+			impl FromElement for $t
+			{
+				fn from_element(element: &ConfigElement, source: Option<&Source>)
+					-> Result<$t, Error>
+				{
+					primitive(element, source, stringify!($t))
+				}
+			}
+		)*
+	};
+}
+
+impl_primitive!(bool f32 f64);
+
+macro_rules! impl_to_value {
+	($($t: ty)*) => {
+		$(
+			impl ToElement for $t
+			{
+				fn to_element(&self) -> ConfigElement
+				{
+					ConfigElement::new_value(self)
+				}
+			}
+		)*
+	};
+}
+
+impl_to_value!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize bool f32 f64 char String str);
+
+impl FromElement for char
+{
+	fn from_element(element: &ConfigElement, source: Option<&Source>) -> Result<char, Error>
+	{
+		let value = element
+			.as_value()
+			.ok_or_else(|| repr_error(element, source, "Can't parse array/table as char."))?;
+		let mut chars = value.chars();
+		match (chars.next(), chars.next())
+		{
+			(Some(c), None) => Ok(c),
+			_ => Err(repr_error(
+				element,
+				source,
+				&format!("Can't parse '{}' as a char.", value),
+			)),
+		}
+	}
+}
+
+impl FromElement for String
+{
+	fn from_element(element: &ConfigElement, source: Option<&Source>) -> Result<String, Error>
+	{
+		element
+			.as_value()
+			.cloned()
+			.ok_or_else(|| repr_error(element, source, "Can't parse array/table as a string."))
+	}
+}
+
+impl FromElement for PathBuf
+{
+	fn from_element(element: &ConfigElement, source: Option<&Source>) -> Result<PathBuf, Error>
+	{
+		String::from_element(element, source).map(PathBuf::from)
+	}
+}
+
+impl ToElement for PathBuf
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		ConfigElement::new_value(self.display())
+	}
+}
+
+impl FromElement for ()
+{
+	fn from_element(element: &ConfigElement, source: Option<&Source>) -> Result<(), Error>
+	{
+		match element.as_value()
+		{
+			Some(value) if value.is_empty() => Ok(()),
+			_ => Err(repr_error(element, source, "Expected an empty value.")),
+		}
+	}
+}
+
+impl ToElement for ()
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		ConfigElement::new_value("")
+	}
+}
+
+impl FromElement for ConfigElement
+{
+	fn from_element(
+		element: &ConfigElement, _source: Option<&Source>,
+	) -> Result<ConfigElement, Error>
+	{
+		Ok(element.clone())
+	}
+}
+
+impl ToElement for ConfigElement
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		self.clone()
+	}
+}
+
+/// An empty value is read as `None`, anything else as `Some`. Absent struct
+/// fields are also read as `None`.
+impl<T: FromElement> FromElement for Option<T>
+{
+	fn from_element(element: &ConfigElement, source: Option<&Source>) -> Result<Option<T>, Error>
+	{
+		match element.as_value()
+		{
+			Some(value) if value.is_empty() => Ok(None),
+			_ => T::from_element(element, source).map(Some),
+		}
+	}
+
+	fn from_missing() -> Option<Option<T>>
+	{
+		Some(None)
+	}
+
+	fn merge_element(
+		&mut self, element: &ConfigElement, source: Option<&Source>,
+	) -> Result<(), Error>
+	{
+		match (self.as_mut(), element.as_value())
+		{
+			(Some(_), Some(value)) if value.is_empty() => *self = None,
+			(Some(inner), _) => inner.merge_element(element, source)?,
+			(None, _) => *self = Self::from_element(element, source)?,
+		}
+		Ok(())
+	}
+}
+
+impl<T: ToElement> ToElement for Option<T>
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		match *self
+		{
+			Some(ref v) => v.to_element(),
+			None => ConfigElement::new_value(""),
+		}
+	}
+}
+
+impl<T: FromElement> FromElement for Box<T>
+{
+	fn from_element(element: &ConfigElement, source: Option<&Source>) -> Result<Box<T>, Error>
+	{
+		T::from_element(element, source).map(Box::new)
+	}
+
+	fn from_missing() -> Option<Box<T>>
+	{
+		T::from_missing().map(Box::new)
+	}
+
+	fn merge_element(
+		&mut self, element: &ConfigElement, source: Option<&Source>,
+	) -> Result<(), Error>
+	{
+		(**self).merge_element(element, source)
+	}
+}
+
+impl<T: ?Sized + ToElement> ToElement for Box<T>
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		(**self).to_element()
+	}
+}
+
+impl<T: ?Sized + ToElement> ToElement for &T
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		(**self).to_element()
+	}
+}
+
+impl<T: FromElement> FromElement for Vec<T>
+{
+	fn from_element(element: &ConfigElement, source: Option<&Source>) -> Result<Vec<T>, Error>
+	{
+		elements(element, source)
+	}
+}
+
+impl<T: ToElement> ToElement for Vec<T>
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		array_element(self)
+	}
+}
+
+impl<T: ToElement> ToElement for [T]
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		array_element(self)
+	}
+}
+
+impl<T: FromElement> FromElement for VecDeque<T>
+{
+	fn from_element(element: &ConfigElement, source: Option<&Source>)
+		-> Result<VecDeque<T>, Error>
+	{
+		elements(element, source)
+	}
+}
+
+impl<T: ToElement> ToElement for VecDeque<T>
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		array_element(self)
+	}
+}
+
+impl<T: FromElement + Ord> FromElement for BTreeSet<T>
+{
+	fn from_element(element: &ConfigElement, source: Option<&Source>)
+		-> Result<BTreeSet<T>, Error>
+	{
+		elements(element, source)
+	}
+}
+
+impl<T: ToElement> ToElement for BTreeSet<T>
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		array_element(self)
+	}
+}
+
+impl<T: FromElement + Eq + Hash, S: BuildHasher + Default> FromElement for HashSet<T, S>
+{
+	fn from_element(
+		element: &ConfigElement, source: Option<&Source>,
+	) -> Result<HashSet<T, S>, Error>
+	{
+		elements(element, source)
+	}
+}
+
+impl<T: ToElement, S> ToElement for HashSet<T, S>
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		array_element(self)
+	}
+}
+
+impl<T: FromElement, const N: usize> FromElement for [T; N]
+{
+	fn from_element(element: &ConfigElement, source: Option<&Source>) -> Result<[T; N], Error>
+	{
+		let array = array(element, source)?;
+		if array.len() != N
+		{
+			return Err(repr_error(
+				element,
+				source,
+				&format!("Expected an array with {} elements.", N),
+			));
+		}
+		let elements: Vec<T> = elements(element, source)?;
+		Ok(elements.try_into().ok().unwrap())
+	}
+}
+
+impl<T: ToElement, const N: usize> ToElement for [T; N]
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		array_element(self)
+	}
+}
+
+/// Keys are read from the table keys, like values. Errors in the keys point at
+/// the keys, see `ConfigElement::key_span`.
+impl<K: FromElement + Ord, V: FromElement> FromElement for BTreeMap<K, V>
+{
+	fn from_element(
+		element: &ConfigElement, source: Option<&Source>,
+	) -> Result<BTreeMap<K, V>, Error>
+	{
+		entries(element, source)
+	}
+}
+
+impl<K: ToString, V: ToElement> ToElement for BTreeMap<K, V>
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		table_element(self)
+	}
+}
+
+/// Keys are read from the table keys, like values. Errors in the keys point at
+/// the keys, see `ConfigElement::key_span`.
+impl<K: FromElement + Eq + Hash, V: FromElement, S: BuildHasher + Default> FromElement
+	for HashMap<K, V, S>
+{
+	fn from_element(
+		element: &ConfigElement, source: Option<&Source>,
+	) -> Result<HashMap<K, V, S>, Error>
+	{
+		entries(element, source)
+	}
+}
+
+impl<K: ToString, V: ToElement, S> ToElement for HashMap<K, V, S>
+{
+	fn to_element(&self) -> ConfigElement
+	{
+		table_element(self)
+	}
+}
+
+macro_rules! impl_tuple {
+	($len: expr => $($t: ident $idx: tt)*) => {
+		impl<$($t: FromElement),*> FromElement for ($($t,)*)
+		{
+			fn from_element(element: &ConfigElement, source: Option<&Source>)
+				-> Result<($($t,)*), Error>
+			{
+				let array = array(element, source)?;
+				if array.len() != $len
+				{
+					return Err(repr_error(
+						element,
+						source,
+						&format!("Expected an array with {} elements.", $len),
+					));
+				}
+				Ok(($($t::from_element(&array[$idx], source)?,)*))
+			}
+		}
+
+		impl<$($t: ToElement),*> ToElement for ($($t,)*)
+		{
+			fn to_element(&self) -> ConfigElement
+			{
+				let mut array = ConfigElement::new_array();
+				$(array.insert("", self.$idx.to_element());)*
+				array
+			}
+		}
+	};
+}
+
+impl_tuple!(1 => A 0);
+impl_tuple!(2 => A 0 B 1);
+impl_tuple!(3 => A 0 B 1 C 2);
+impl_tuple!(4 => A 0 B 1 C 2 D 3);
+impl_tuple!(5 => A 0 B 1 C 2 D 3 E 4);
+impl_tuple!(6 => A 0 B 1 C 2 D 3 E 4 F 5);
+impl_tuple!(7 => A 0 B 1 C 2 D 3 E 4 F 5 G 6);
+impl_tuple!(8 => A 0 B 1 C 2 D 3 E 4 F 5 G 6 H 7);
+
+/// Reads a ConfigElement on top of an existing value, with
+/// `FromElement::merge_element`.
+///
+/// Elements present in `element` overwrite the current values, derived
+/// structs are merged recursively and everything absent from `element`,
+/// including skipped fields, keeps its current value. If an error occurs, the
+/// fields read before it keep their new values.
+pub fn from_element_into<T: FromElement>(
+	value: &mut T, element: &ConfigElement, source: Option<&Source>,
+) -> Result<(), Error>
+{
+	value.merge_element(element, source)
+}
+
+/// Used by `#[derive(FromElement)]`.
+#[doc(hidden)]
+pub fn __field<T: FromElement>(
+	element: &ConfigElement, source: Option<&Source>, name: &str, default: Option<fn() -> T>,
+) -> Result<T, Error>
+{
+	let table = element.as_table().unwrap();
+	match table.get(name)
+	{
+		Some(field) => T::from_element(field, source),
+		None => default
+			.map(|f| f())
+			.or_else(T::from_missing)
+			.ok_or_else(|| repr_error(element, source, &format!("Missing field '{}'.", name))),
+	}
+}
+
+/// Used by `#[derive(FromElement)]`.
+#[doc(hidden)]
+pub fn __merge_field<T: FromElement>(
+	value: &mut T, element: &ConfigElement, source: Option<&Source>, name: &str,
+) -> Result<(), Error>
+{
+	match element.as_table().unwrap().get(name)
+	{
+		Some(field) => value.merge_element(field, source),
+		None => Ok(()),
+	}
+}
+
+/// Used by `#[derive(FromElement)]`.
+#[doc(hidden)]
+pub fn __error(element: &ConfigElement, source: Option<&Source>, text: &str) -> Error
+{
+	repr_error(element, source, text)
+}
@@ -1,9 +1,22 @@
+use crate::bytes;
 use crate::config_element::{ConfigElement, ConfigElementKind};
-use indexmap::IndexMap;
-use serde::de::{self, Deserialize, Visitor};
+use crate::element_serde;
+use crate::ser::{BytesEncoding, OptionEncoding};
+use crate::spanned;
+use serde::de::value::SeqDeserializer;
+use serde::de::{self, Deserialize, DeserializeOwned, IntoDeserializer, Visitor};
 use slr_parser::{Error, ErrorKind, Source, Span};
+use std::array;
+use std::borrow::Cow;
 use std::error;
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::num::ParseIntError;
+use std::path::Path;
+use std::slice;
 use std::str::FromStr;
+use std::vec;
 
 /// Deserialize a value to a ConfigElement.
 pub fn from_element<'de, 'src: 'de, T>(
@@ -12,25 +25,208 @@ pub fn from_element<'de, 'src: 'de, T>(
 where
 	T: Deserialize<'de>,
 {
-	let d = Deserializer::new(element, source);
-	T::deserialize(d)
+	Deserializer::new(element, source).deserialize_seed(PhantomData)
+}
+
+/// Deserialize a value from a ConfigElement, using the specified options.
+pub fn from_element_with_options<'de, 'src: 'de, T>(
+	element: &'de ConfigElement, source: Option<&'de Source<'src>>, options: DeserializerOptions,
+) -> Result<T, Error>
+where
+	T: Deserialize<'de>,
+{
+	Deserializer::new(element, source)
+		.with_options(options)
+		.deserialize_seed(PhantomData)
+}
+
+/// Deserialize a value from an owned ConfigElement. Unlike `from_element`,
+/// this moves the strings and arrays out of the element instead of copying
+/// them.
+pub fn from_owned_element<'src, T>(
+	element: ConfigElement, source: Option<&Source<'src>>,
+) -> Result<T, Error>
+where
+	T: DeserializeOwned,
+{
+	Deserializer::new_owned(element, source).deserialize_seed(PhantomData)
+}
+
+/// Deserialize a value from a string. Errors refer to the source as `<anon>`.
+pub fn from_str<T>(src: &str) -> Result<T, Error>
+where
+	T: DeserializeOwned,
+{
+	from_named_str(Path::new("<anon>"), src)
+}
+
+/// Deserialize a value from a file.
+pub fn from_file<T, P>(path: P) -> Result<T, Error>
+where
+	T: DeserializeOwned,
+	P: AsRef<Path>,
+{
+	let path = path.as_ref();
+	let src = fs::read_to_string(path)
+		.map_err(|e| Error::new(ErrorKind::Io, format!("{}: error: {}\n", path.display(), e)))?;
+	from_named_str(path, &src)
+}
+
+/// Deserialize a value from a reader. Errors, including those from reading and
+/// invalid UTF-8, refer to the source as `<reader>`.
+pub fn from_reader<T, R>(mut reader: R) -> Result<T, Error>
+where
+	T: DeserializeOwned,
+	R: io::Read,
+{
+	let path = Path::new("<reader>");
+	let mut src = String::new();
+	reader
+		.read_to_string(&mut src)
+		.map_err(|e| Error::new(ErrorKind::Io, format!("{}: error: {}\n", path.display(), e)))?;
+	from_named_str(path, &src)
+}
+
+fn from_named_str<T>(path: &Path, src: &str) -> Result<T, Error>
+where
+	T: DeserializeOwned,
+{
+	let mut source = Source::new(path, src);
+	let root = ConfigElement::from_source(&mut source)?;
+	from_owned_element(root, Some(&source))
+}
+
+/// Options that control how values are deserialized. These need to match the
+/// `SerializerOptions` used to serialize the values. Enums encoded using
+/// either `EnumEncoding` are always accepted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DeserializerOptions
+{
+	pub(crate) transparent_newtypes: bool,
+	pub(crate) option_encoding: OptionEncoding,
+	pub(crate) bytes_encoding: BytesEncoding,
+}
+
+impl DeserializerOptions
+{
+	/// Creates the default options, which match what `from_element` does.
+	pub fn new() -> Self
+	{
+		Self {
+			transparent_newtypes: false,
+			option_encoding: OptionEncoding::Empty,
+			bytes_encoding: BytesEncoding::Array,
+		}
+	}
+
+	/// Whether newtype structs are expected to be encoded as the contained
+	/// value, rather than as a single element array. Defaults to `false`.
+	pub fn transparent_newtypes(mut self, transparent_newtypes: bool) -> Self
+	{
+		self.transparent_newtypes = transparent_newtypes;
+		self
+	}
+
+	/// How options are expected to be encoded. Defaults to
+	/// `OptionEncoding::Empty`.
+	pub fn option_encoding(mut self, option_encoding: OptionEncoding) -> Self
+	{
+		self.option_encoding = option_encoding;
+		self
+	}
+
+	/// How byte buffers are expected to be encoded. Defaults to
+	/// `BytesEncoding::Array`. With the other encodings, both the string and
+	/// the array forms are accepted.
+	pub fn bytes_encoding(mut self, bytes_encoding: BytesEncoding) -> Self
+	{
+		self.bytes_encoding = bytes_encoding;
+		self
+	}
+}
+
+impl Default for DeserializerOptions
+{
+	fn default() -> Self
+	{
+		Self::new()
+	}
+}
+
+/// Iterator over the elements of a borrowed or an owned array.
+enum Elements<'de>
+{
+	Borrowed(slice::Iter<'de, ConfigElement>),
+	Owned(vec::IntoIter<ConfigElement>),
+}
+
+impl<'de> Iterator for Elements<'de>
+{
+	type Item = Cow<'de, ConfigElement>;
+
+	fn next(&mut self) -> Option<Cow<'de, ConfigElement>>
+	{
+		match self
+		{
+			Elements::Borrowed(iter) => iter.next().map(Cow::Borrowed),
+			Elements::Owned(iter) => iter.next().map(Cow::Owned),
+		}
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>)
+	{
+		match self
+		{
+			Elements::Borrowed(iter) => iter.size_hint(),
+			Elements::Owned(iter) => iter.size_hint(),
+		}
+	}
+}
+
+impl<'de> ExactSizeIterator for Elements<'de> {}
+
+/// Iterator over the entries of a borrowed or an owned table.
+enum Entries<'de>
+{
+	Borrowed(indexmap::map::Iter<'de, String, ConfigElement>),
+	Owned(indexmap::map::IntoIter<String, ConfigElement>),
+}
+
+impl<'de> Iterator for Entries<'de>
+{
+	type Item = (Cow<'de, str>, Cow<'de, ConfigElement>);
+
+	fn next(&mut self) -> Option<(Cow<'de, str>, Cow<'de, ConfigElement>)>
+	{
+		match self
+		{
+			Entries::Borrowed(iter) => iter
+				.next()
+				.map(|(k, v)| (Cow::Borrowed(&k[..]), Cow::Borrowed(v))),
+			Entries::Owned(iter) => iter.next().map(|(k, v)| (Cow::Owned(k), Cow::Owned(v))),
+		}
+	}
 }
 
 struct SeqHelper<'de, 'src: 'de>
 {
-	elements: &'de Vec<ConfigElement>,
+	elements: Elements<'de>,
+	value: Option<Cow<'de, ConfigElement>>,
 	source: Option<&'de Source<'src>>,
-	idx: usize,
+	options: DeserializerOptions,
 }
 
 impl<'de, 'src> SeqHelper<'de, 'src>
 {
-	fn new(elements: &'de Vec<ConfigElement>, source: Option<&'de Source<'src>>) -> Self
+	fn new(
+		elements: Elements<'de>, source: Option<&'de Source<'src>>, options: DeserializerOptions,
+	) -> Self
 	{
 		Self {
-			elements: elements,
-			source: source,
-			idx: 0,
+			elements,
+			value: None,
+			source,
+			options,
 		}
 	}
 }
@@ -43,18 +239,12 @@ impl<'de, 'src> de::SeqAccess<'de> for SeqHelper<'de, 'src>
 	where
 		T: de::DeserializeSeed<'de>,
 	{
-		if self.idx < self.elements.len()
-		{
-			let elem = &self.elements[self.idx];
-			let ret = seed
-				.deserialize(Deserializer::new(elem, self.source))
-				.map(Some);
-			self.idx += 1;
-			ret
-		}
-		else
+		match self.elements.next()
 		{
-			Ok(None)
+			Some(elem) => Deserializer::from_cow(elem, self.source, self.options)
+				.deserialize_seed(seed)
+				.map(Some),
+			None => Ok(None),
 		}
 	}
 
@@ -72,39 +262,32 @@ impl<'de, 'src> de::MapAccess<'de> for SeqHelper<'de, 'src>
 	where
 		K: de::DeserializeSeed<'de>,
 	{
-		if self.idx < self.elements.len()
+		match self.elements.next()
 		{
-			let elem = &self.elements[self.idx];
-			if let Some(array) = elem.as_array()
+			Some(elem) =>
 			{
-				if array.len() == 2
-				{
-					seed.deserialize(Deserializer::new(&array[0], self.source))
-						.map(Some)
-				}
-				else
+				let pair_span = elem.span();
+				let pair = Deserializer::from_cow(elem, self.source, self.options);
+				match pair.into_elements()
 				{
-					Err(Error::from_span(
-						elem.span(),
+					Ok(mut pair_elements) if pair_elements.len() == 2 =>
+					{
+						let key = pair_elements.next().unwrap();
+						self.value = pair_elements.next();
+						Deserializer::from_cow(key, self.source, self.options)
+							.deserialize_seed(seed)
+							.map(Some)
+					}
+					Ok(_) => Err(Error::from_span(
+						pair_span,
 						self.source,
 						ErrorKind::InvalidRepr,
 						"Expected a 2 element array.",
-					))
+					)),
+					Err(pair) => Err(pair.error("Expected a 2 element array.")),
 				}
 			}
-			else
-			{
-				Err(Error::from_span(
-					elem.span(),
-					self.source,
-					ErrorKind::InvalidRepr,
-					"Expected a 2 element array.",
-				))
-			}
-		}
-		else
-		{
-			Ok(None)
+			None => Ok(None),
 		}
 	}
 
@@ -112,57 +295,48 @@ impl<'de, 'src> de::MapAccess<'de> for SeqHelper<'de, 'src>
 	where
 		V: de::DeserializeSeed<'de>,
 	{
-		let elem = &self.elements[self.idx];
-		if let Some(array) = elem.as_array()
-		{
-			if array.len() == 2
-			{
-				let ret = seed.deserialize(Deserializer::new(&array[1], self.source));
-				self.idx += 1;
-				ret
-			}
-			else
-			{
-				Err(Error::from_span(
-					elem.span(),
-					self.source,
-					ErrorKind::InvalidRepr,
-					"Expected a 2 element array.",
-				))
-			}
-		}
-		else
-		{
-			Err(Error::from_span(
-				elem.span(),
-				self.source,
-				ErrorKind::InvalidRepr,
-				"Expected a 2 element array.",
-			))
-		}
+		let value = self.value.take().unwrap();
+		Deserializer::from_cow(value, self.source, self.options).deserialize_seed(seed)
 	}
 }
 
 struct MapHelper<'de, 'src: 'de>
 {
-	iter: indexmap::map::Iter<'de, String, ConfigElement>,
-	value: Option<&'de ConfigElement>,
+	entries: Entries<'de>,
+	value: Option<Cow<'de, ConfigElement>>,
 	source: Option<&'de Source<'src>>,
-	fields: &'static [&'static str],
+	/// If set, entries with keys not in this list are skipped.
+	fields: Option<&'static [&'static str]>,
+	/// If set, keys are deserialized like values, so that they can be parsed
+	/// as e.g. numbers. Otherwise they can only be deserialized as strings.
+	typed_keys: bool,
+	options: DeserializerOptions,
 }
 
 impl<'de, 'src> MapHelper<'de, 'src>
 {
 	fn new(
-		elements: &'de IndexMap<String, ConfigElement>, fields: &'static [&'static str],
-		source: Option<&'de Source<'src>>,
+		entries: Entries<'de>, fields: Option<&'static [&'static str]>,
+		source: Option<&'de Source<'src>>, options: DeserializerOptions,
 	) -> Self
 	{
 		Self {
-			iter: elements.iter(),
+			entries,
 			value: None,
-			fields: fields,
-			source: source,
+			fields,
+			typed_keys: false,
+			source,
+			options,
+		}
+	}
+
+	fn new_typed_keys(
+		entries: Entries<'de>, source: Option<&'de Source<'src>>, options: DeserializerOptions,
+	) -> Self
+	{
+		Self {
+			typed_keys: true,
+			..Self::new(entries, None, source, options)
 		}
 	}
 }
@@ -177,13 +351,27 @@ impl<'de, 'src> de::MapAccess<'de> for MapHelper<'de, 'src>
 	{
 		loop
 		{
-			let next = self.iter.next();
+			let next = self.entries.next();
 			if let Some((k, v)) = next
 			{
-				if self.fields.contains(&k.as_str())
+				let wanted = match self.fields
+				{
+					Some(fields) => fields.contains(&&k[..]),
+					None => true,
+				};
+				if wanted && self.typed_keys
+				{
+					let key = ConfigElement::new_value(k).with_span(v.key_span());
+					self.value = Some(v);
+					return Deserializer::new_owned(key, self.source)
+						.with_options(self.options)
+						.deserialize_seed(seed)
+						.map(Some);
+				}
+				else if wanted
 				{
 					self.value = Some(v);
-					return seed.deserialize(HackStringDeserializer::new(&*k)).map(Some);
+					return seed.deserialize(HackStringDeserializer::new(k)).map(Some);
 				}
 			}
 			else
@@ -197,43 +385,281 @@ impl<'de, 'src> de::MapAccess<'de> for MapHelper<'de, 'src>
 	where
 		V: de::DeserializeSeed<'de>,
 	{
-		let v = self.value.unwrap();
-		seed.deserialize(Deserializer::new(v, self.source))
+		let v = self.value.take().unwrap();
+		Deserializer::from_cow(v, self.source, self.options).deserialize_seed(seed)
 	}
 }
 
-struct HackStringDeserializer<'de>
+struct SpannedHelper<'de, 'src: 'de>
 {
-	string: &'de str,
+	deserializer: Option<Deserializer<'de, 'src>>,
+	span: Span,
+	source: Option<&'de Source<'src>>,
+	fields: &'static [&'static str],
+	idx: usize,
+}
+
+impl<'de, 'src> SpannedHelper<'de, 'src>
+{
+	fn new(deserializer: Deserializer<'de, 'src>) -> Self
+	{
+		// The filename is only known if we have a source.
+		let fields = if deserializer.source.is_some()
+		{
+			spanned::FIELDS
+		}
+		else
+		{
+			&[
+				spanned::START_FIELD,
+				spanned::END_FIELD,
+				spanned::VALUE_FIELD,
+			]
+		};
+		Self {
+			span: deserializer.element.span(),
+			source: deserializer.source,
+			deserializer: Some(deserializer),
+			fields,
+			idx: 0,
+		}
+	}
+}
+
+impl<'de, 'src> de::MapAccess<'de> for SpannedHelper<'de, 'src>
+{
+	type Error = Error;
+
+	fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+	where
+		K: de::DeserializeSeed<'de>,
+	{
+		if self.idx < self.fields.len()
+		{
+			seed.deserialize(HackStringDeserializer::new(Cow::Borrowed(
+				self.fields[self.idx],
+			)))
+			.map(Some)
+		}
+		else
+		{
+			Ok(None)
+		}
+	}
+
+	fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+	where
+		V: de::DeserializeSeed<'de>,
+	{
+		let field = self.fields[self.idx];
+		self.idx += 1;
+		match field
+		{
+			spanned::START_FIELD => seed.deserialize(self.span.start().into_deserializer()),
+			spanned::END_FIELD => seed.deserialize(self.span.end().into_deserializer()),
+			spanned::FILENAME_FIELD =>
+			{
+				let filename = self.source.unwrap().filename();
+				seed.deserialize(filename.to_string_lossy().into_owned().into_deserializer())
+			}
+			_ => seed.deserialize(self.deserializer.take().unwrap()),
+		}
+	}
+}
+
+/// Passes an element to `ConfigElement`'s `Deserialize` implementation, see
+/// `element_serde::NAME`.
+struct ElementHelper<'de, 'src: 'de>
+{
+	deserializer: Option<Deserializer<'de, 'src>>,
+	fields: Vec<&'static str>,
+	idx: usize,
+}
+
+impl<'de, 'src> ElementHelper<'de, 'src>
+{
+	fn new(deserializer: Deserializer<'de, 'src>) -> Self
+	{
+		let mut fields = vec![element_serde::START_FIELD, element_serde::END_FIELD];
+		if deserializer.element.key_span().is_valid()
+		{
+			fields.push(element_serde::KEY_SPAN_FIELD);
+		}
+		if deserializer.element.tag_span().is_valid()
+		{
+			fields.push(element_serde::TAG_SPAN_FIELD);
+		}
+		if deserializer.element.extent().is_valid()
+		{
+			fields.push(element_serde::EXTENT_FIELD);
+		}
+		if deserializer.element.tag().is_some()
+		{
+			fields.push(element_serde::TAG_FIELD);
+		}
+		if deserializer.element.quote_type().is_some()
+		{
+			fields.push(element_serde::QUOTE_TYPE_FIELD);
+		}
+		if deserializer.element.key_quote_type().is_some()
+		{
+			fields.push(element_serde::KEY_QUOTE_TYPE_FIELD);
+		}
+		fields.push(match *deserializer.element.kind()
+		{
+			ConfigElementKind::Value(_) => element_serde::VALUE_FIELD,
+			ConfigElementKind::Table(_) | ConfigElementKind::TaggedTable(_, _) =>
+			{
+				element_serde::TABLE_FIELD
+			}
+			_ => element_serde::ARRAY_FIELD,
+		});
+		Self {
+			deserializer: Some(deserializer),
+			fields,
+			idx: 0,
+		}
+	}
+}
+
+impl<'de, 'src> de::MapAccess<'de> for ElementHelper<'de, 'src>
+{
+	type Error = Error;
+
+	fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+	where
+		K: de::DeserializeSeed<'de>,
+	{
+		if self.idx < self.fields.len()
+		{
+			seed.deserialize(HackStringDeserializer::new(Cow::Borrowed(
+				self.fields[self.idx],
+			)))
+			.map(Some)
+		}
+		else
+		{
+			Ok(None)
+		}
+	}
+
+	fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+	where
+		V: de::DeserializeSeed<'de>,
+	{
+		let field = self.fields[self.idx];
+		self.idx += 1;
+		let element = &self.deserializer.as_ref().unwrap().element;
+		match field
+		{
+			element_serde::START_FIELD =>
+			{
+				seed.deserialize(element.span().start().into_deserializer())
+			}
+			element_serde::END_FIELD => seed.deserialize(element.span().end().into_deserializer()),
+			element_serde::KEY_SPAN_FIELD =>
+			{
+				seed.deserialize(span_deserializer(element.key_span()))
+			}
+			element_serde::TAG_SPAN_FIELD =>
+			{
+				seed.deserialize(span_deserializer(element.tag_span()))
+			}
+			element_serde::EXTENT_FIELD => seed.deserialize(span_deserializer(element.extent())),
+			element_serde::TAG_FIELD =>
+			{
+				let tag = element.tag().unwrap().clone();
+				seed.deserialize(tag.into_deserializer())
+			}
+			element_serde::QUOTE_TYPE_FIELD =>
+			{
+				let quote_type = element_serde::encode_quote_type(element.quote_type().unwrap());
+				seed.deserialize(quote_type.into_deserializer())
+			}
+			element_serde::KEY_QUOTE_TYPE_FIELD =>
+			{
+				let quote_type =
+					element_serde::encode_quote_type(element.key_quote_type().unwrap());
+				seed.deserialize(quote_type.into_deserializer())
+			}
+			_ => seed.deserialize(self.deserializer.take().unwrap()),
+		}
+	}
+}
+
+/// Passes a span as a `(start, end)` pair.
+fn span_deserializer(span: Span) -> SeqDeserializer<array::IntoIter<usize, 2>, Error>
+{
+	SeqDeserializer::new([span.start(), span.end()].into_iter())
+}
+
+pub(crate) struct HackStringDeserializer<'de>
+{
+	string: Cow<'de, str>,
 }
 
 impl<'de> HackStringDeserializer<'de>
 {
-	fn new(string: &'de str) -> Self
+	pub(crate) fn new(string: Cow<'de, str>) -> Self
 	{
-		Self { string: string }
+		Self { string }
 	}
 }
 
 struct VariantHelper<'de, 'src: 'de>
 {
-	element: Option<&'de ConfigElement>,
+	element: Option<Cow<'de, ConfigElement>>,
 	source: Option<&'de Source<'src>>,
+	options: DeserializerOptions,
 	span: Span,
+	/// Whether the variant is encoded as a single entry table (see
+	/// `EnumEncoding::Table`), in which case the contents are not wrapped in
+	/// a tagged array/table.
+	table_encoding: bool,
 }
 
 impl<'de, 'src> VariantHelper<'de, 'src>
 {
 	fn new(
-		element: Option<&'de ConfigElement>, source: Option<&'de Source<'src>>, span: Span,
+		element: Option<Cow<'de, ConfigElement>>, source: Option<&'de Source<'src>>,
+		options: DeserializerOptions, span: Span, table_encoding: bool,
 	) -> Self
 	{
 		Self {
-			element: element,
-			source: source,
-			span: span,
+			element,
+			source,
+			options,
+			span,
+			table_encoding,
 		}
 	}
+
+	fn error(&self, text: &str) -> Error
+	{
+		Error::from_span(self.span, self.source, ErrorKind::InvalidRepr, text)
+	}
+
+	fn take_elements(&mut self) -> Option<Elements<'de>>
+	{
+		let source = self.source;
+		let options = self.options;
+		self.element.take().and_then(|elem| {
+			Deserializer::from_cow(elem, source, options)
+				.into_elements()
+				.ok()
+		})
+	}
+
+	fn take_entries(&mut self) -> Option<Entries<'de>>
+	{
+		let source = self.source;
+		let options = self.options;
+		self.element.take().and_then(|elem| {
+			Deserializer::from_cow(elem, source, options)
+				.into_entries()
+				.ok()
+		})
+	}
 }
 
 impl<'de, 'src> de::VariantAccess<'de> for VariantHelper<'de, 'src>
@@ -243,12 +669,7 @@ impl<'de, 'src> de::VariantAccess<'de> for VariantHelper<'de, 'src>
 	{
 		if self.element.is_some()
 		{
-			Err(Error::from_span(
-				self.span,
-				self.source,
-				ErrorKind::InvalidRepr,
-				"Expected a value.",
-			))
+			Err(self.error("Expected a value."))
 		}
 		else
 		{
@@ -256,98 +677,58 @@ impl<'de, 'src> de::VariantAccess<'de> for VariantHelper<'de, 'src>
 		}
 	}
 
-	fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+	fn newtype_variant_seed<T>(mut self, seed: T) -> Result<T::Value, Error>
 	where
 		T: de::DeserializeSeed<'de>,
 	{
-		if let Some(elem) = self.element
+		if self.table_encoding
 		{
-			if let Some([elem]) = elem.as_array().map(|a| &a[..])
-			{
-				seed.deserialize(Deserializer::new(elem, self.source))
-			}
-			else
-			{
-				Err(Error::from_span(
-					self.span,
-					self.source,
-					ErrorKind::InvalidRepr,
-					"Expected a tagged array with a single element.",
-				))
-			}
+			let element = self.element.take().unwrap();
+			return Deserializer::from_cow(element, self.source, self.options)
+				.deserialize_seed(seed);
 		}
-		else
+		match self.take_elements()
 		{
-			Err(Error::from_span(
-				self.span,
-				self.source,
-				ErrorKind::InvalidRepr,
-				"Expected a tagged array with a single element.",
-			))
+			Some(mut elements) if elements.len() == 1 =>
+			{
+				Deserializer::from_cow(elements.next().unwrap(), self.source, self.options)
+					.deserialize_seed(seed)
+			}
+			_ => Err(self.error("Expected a tagged array with a single element.")),
 		}
 	}
 
-	fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+	fn tuple_variant<V>(mut self, _len: usize, visitor: V) -> Result<V::Value, Error>
 	where
 		V: Visitor<'de>,
 	{
-		if let Some(elem) = self.element
+		match self.take_elements()
 		{
-			if let Some(array) = elem.as_array()
-			{
-				visitor.visit_seq(SeqHelper::new(array, self.source))
-			}
-			else
+			Some(elements) =>
 			{
-				Err(Error::from_span(
-					self.span,
-					self.source,
-					ErrorKind::InvalidRepr,
-					"Expected a tagged array.",
-				))
+				visitor.visit_seq(SeqHelper::new(elements, self.source, self.options))
 			}
-		}
-		else
-		{
-			Err(Error::from_span(
-				self.span,
-				self.source,
-				ErrorKind::InvalidRepr,
-				"Expected a tagged array.",
-			))
+			None if self.table_encoding => Err(self.error("Expected an array.")),
+			None => Err(self.error("Expected a tagged array.")),
 		}
 	}
 
 	fn struct_variant<V>(
-		self, fields: &'static [&'static str], visitor: V,
+		mut self, fields: &'static [&'static str], visitor: V,
 	) -> Result<V::Value, Error>
 	where
 		V: Visitor<'de>,
 	{
-		if let Some(elem) = self.element
+		match self.take_entries()
 		{
-			if let Some(table) = elem.as_table()
-			{
-				visitor.visit_map(MapHelper::new(table, fields, self.source))
-			}
-			else
-			{
-				Err(Error::from_span(
-					self.span,
-					self.source,
-					ErrorKind::InvalidRepr,
-					"Expected a tagged table.",
-				))
-			}
-		}
-		else
-		{
-			Err(Error::from_span(
-				self.span,
+			Some(entries) => visitor.visit_map(MapHelper::new(
+				entries,
+				Some(fields),
 				self.source,
-				ErrorKind::InvalidRepr,
-				"Expected a tagged table.",
-			))
+				self.options,
+			)),
+			None if self.table_encoding => Err(self.error("Expected a table.")),
+			None => Err(self.error("Expected a tagged table.")),
 		}
 	}
 }
@@ -367,7 +748,11 @@ impl<'de> de::Deserializer<'de> for HackStringDeserializer<'de>
 	where
 		V: Visitor<'de>,
 	{
-		visitor.visit_borrowed_str(self.string)
+		match self.string
+		{
+			Cow::Borrowed(string) => visitor.visit_borrowed_str(string),
+			Cow::Owned(string) => visitor.visit_string(string),
+		}
 	}
 
 	serde::forward_to_deserialize_any! {
@@ -377,31 +762,145 @@ impl<'de> de::Deserializer<'de> for HackStringDeserializer<'de>
 	}
 }
 
-#[derive(Copy, Clone)]
-struct Deserializer<'de, 'src: 'de>
+pub(crate) trait Integer: Sized
 {
-	element: &'de ConfigElement,
+	fn from_str_radix(src: &str, radix: u32) -> Result<Self, ParseIntError>;
+}
+
+macro_rules! impl_integer {
+	($($t: ty)*) => {
+		$(
+			impl Integer for $t
+			{
+				fn from_str_radix(src: &str, radix: u32) -> Result<$t, ParseIntError>
+				{
+					<$t>::from_str_radix(src, radix)
+				}
+			}
+		)*
+	};
+}
+
+impl_integer!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize);
+
+/// Parses an integer, allowing a leading sign, a `0x`, `0o` or `0b` radix
+/// prefix and `_` digit separators.
+pub(crate) fn parse_integer<T: Integer>(value: &str) -> Result<T, String>
+{
+	let (negative, rest) = match value.as_bytes().first()
+	{
+		Some(b'-') => (true, &value[1..]),
+		Some(b'+') => (false, &value[1..]),
+		_ => (false, value),
+	};
+	let (radix, digits) = if let Some(digits) = rest.strip_prefix("0x")
+	{
+		(16, digits)
+	}
+	else if let Some(digits) = rest.strip_prefix("0o")
+	{
+		(8, digits)
+	}
+	else if let Some(digits) = rest.strip_prefix("0b")
+	{
+		(2, digits)
+	}
+	else
+	{
+		(10, rest)
+	};
+	if digits.starts_with(['_', '+', '-'])
+	{
+		return Err(format!("Can't parse '{}' as an integer.", value));
+	}
+	let mut cleaned = String::with_capacity(digits.len() + 1);
+	if negative
+	{
+		cleaned.push('-');
+	}
+	cleaned.extend(digits.chars().filter(|&c| c != '_'));
+	T::from_str_radix(&cleaned, radix)
+		.map_err(|e| format!("Can't parse '{}' as an integer: {}.", value, e))
+}
+
+fn repr_error(span: Span, source: Option<&Source>, text: &str) -> Error
+{
+	Error::from_span(span, source, ErrorKind::InvalidRepr, text)
+}
+
+/// Gives a location to an error made by a visitor with `de::Error::custom`,
+/// which unlike the errors made here doesn't end with a newline.
+pub(crate) fn locate_error(error: Error, span: Span, source: Option<&Source>) -> Error
+{
+	if error.text.ends_with('\n')
+	{
+		return error;
+	}
+	Error::from_span(span, source, error.kind, &error.text)
+}
+
+/// A deserializer of a ConfigElement. It can either borrow the element, or
+/// take ownership of it, in which case the strings and arrays are moved out of
+/// the element instead of being copied.
+pub struct Deserializer<'de, 'src: 'de>
+{
+	element: Cow<'de, ConfigElement>,
 	source: Option<&'de Source<'src>>,
+	options: DeserializerOptions,
 }
 
 impl<'de, 'src> Deserializer<'de, 'src>
 {
-	fn new(element: &'de ConfigElement, source: Option<&'de Source<'src>>) -> Self
+	/// Creates a deserializer that borrows the element. The source argument,
+	/// if set, must be set to the source that was used to parse the element.
+	pub fn new(element: &'de ConfigElement, source: Option<&'de Source<'src>>) -> Self
+	{
+		Self::from_cow(Cow::Borrowed(element), source, DeserializerOptions::new())
+	}
+
+	/// Creates a deserializer that takes ownership of the element. The source
+	/// argument, if set, must be set to the source that was used to parse the
+	/// element.
+	pub fn new_owned(element: ConfigElement, source: Option<&'de Source<'src>>) -> Self
+	{
+		Self::from_cow(Cow::Owned(element), source, DeserializerOptions::new())
+	}
+
+	/// Sets the options used by this deserializer.
+	pub fn with_options(mut self, options: DeserializerOptions) -> Self
+	{
+		self.options = options;
+		self
+	}
+
+	fn from_cow(
+		element: Cow<'de, ConfigElement>, source: Option<&'de Source<'src>>,
+		options: DeserializerOptions,
+	) -> Self
 	{
 		Self {
-			element: element,
-			source: source,
+			element,
+			source,
+			options,
 		}
 	}
 
 	fn error(&self, text: &str) -> Error
 	{
-		Error::from_span(
-			self.element.span(),
-			self.source,
-			ErrorKind::InvalidRepr,
-			text,
-		)
+		repr_error(self.element.span(), self.source, text)
+	}
+
+	/// Deserializes `seed` from this element. Errors without a location, e.g.
+	/// those that serde's buffered enum representations report after
+	/// `deserialize_any` returns, point at this element.
+	fn deserialize_seed<T>(self, seed: T) -> Result<T::Value, Error>
+	where
+		T: de::DeserializeSeed<'de>,
+	{
+		let span = self.element.span();
+		let source = self.source;
+		seed.deserialize(self)
+			.map_err(|e| locate_error(e, span, source))
 	}
 
 	fn primitive<T: FromStr>(&self, name: &str) -> Result<T, Error>
@@ -417,9 +916,161 @@ impl<'de, 'src> Deserializer<'de, 'src>
 			Err(self.error(&format!("Can't parse array/table as {}.", name)))
 		}
 	}
+
+	fn integer<T: Integer>(&self, name: &str) -> Result<T, Error>
+	{
+		if let Some(value) = self.element.as_value()
+		{
+			parse_integer(value).map_err(|e| self.error(&e))
+		}
+		else
+		{
+			Err(self.error(&format!("Can't parse array/table as {}.", name)))
+		}
+	}
+
+	/// Decodes a byte buffer, either from an array or from a string.
+	fn bytes(self) -> Result<Vec<u8>, Error>
+	{
+		if let Some(value) = self.element.as_value()
+		{
+			let decoded = match self.options.bytes_encoding
+			{
+				BytesEncoding::Array => Err("Can't parse value/table as byte array.".to_string()),
+				BytesEncoding::Hex => bytes::decode_hex(value),
+				BytesEncoding::Base64 => bytes::decode_base64(value),
+			};
+			return decoded.map_err(|e| self.error(&e));
+		}
+		let source = self.source;
+		let options = self.options;
+		match self.into_elements()
+		{
+			Ok(elements) =>
+			{
+				let mut bytes = vec![];
+				for element in elements
+				{
+					bytes.push(u8::deserialize(Deserializer::from_cow(
+						element, source, options,
+					))?);
+				}
+				Ok(bytes)
+			}
+			Err(d) => Err(d.error("Can't parse value/table as byte array.")),
+		}
+	}
+
+	/// If this is an array, returns its elements. Otherwise, gives back self.
+	#[allow(clippy::result_large_err)]
+	fn into_elements(self) -> Result<Elements<'de>, Self>
+	{
+		match self.element
+		{
+			Cow::Borrowed(elem) => match elem.as_array()
+			{
+				Some(array) => Ok(Elements::Borrowed(array.iter())),
+				None => Err(self),
+			},
+			Cow::Owned(elem) =>
+			{
+				if elem.as_array().is_some()
+				{
+					Ok(Elements::Owned(elem.into_array().unwrap().into_iter()))
+				}
+				else
+				{
+					Err(Self::from_cow(Cow::Owned(elem), self.source, self.options))
+				}
+			}
+		}
+	}
+
+	/// If this is a table, returns its entries. Otherwise, gives back self.
+	#[allow(clippy::result_large_err)]
+	fn into_entries(self) -> Result<Entries<'de>, Self>
+	{
+		match self.element
+		{
+			Cow::Borrowed(elem) => match elem.as_table()
+			{
+				Some(table) => Ok(Entries::Borrowed(table.iter())),
+				None => Err(self),
+			},
+			Cow::Owned(elem) =>
+			{
+				if elem.as_table().is_some()
+				{
+					Ok(Entries::Owned(elem.into_table().unwrap().into_iter()))
+				}
+				else
+				{
+					Err(Self::from_cow(Cow::Owned(elem), self.source, self.options))
+				}
+			}
+		}
+	}
+
+	/// If this is a value, passes it to the visitor, avoiding a copy if possible.
+	fn visit_value<V>(self, visitor: V) -> Result<V::Value, Error>
+	where
+		V: Visitor<'de>,
+	{
+		let span = self.element.span();
+		let source = self.source;
+		let ret = match self.element
+		{
+			Cow::Borrowed(elem) => match elem.as_value()
+			{
+				Some(value) => visitor.visit_borrowed_str(value),
+				None => return Err(self.error("Can't parse array/table as a string.")),
+			},
+			Cow::Owned(elem) =>
+			{
+				if elem.as_value().is_some()
+				{
+					visitor.visit_string(elem.into_value().unwrap())
+				}
+				else
+				{
+					return Err(repr_error(
+						span,
+						source,
+						"Can't parse array/table as a string.",
+					));
+				}
+			}
+		};
+		ret.map_err(|e: Error| repr_error(span, source, &e.to_string()))
+	}
 }
 
-impl<'de, 'src> de::EnumAccess<'de> for Deserializer<'de, 'src>
+impl<'de> IntoDeserializer<'de, Error> for ConfigElement
+{
+	type Deserializer = Deserializer<'de, 'de>;
+
+	fn into_deserializer(self) -> Deserializer<'de, 'de>
+	{
+		Deserializer::new_owned(self, None)
+	}
+}
+
+impl<'de> IntoDeserializer<'de, Error> for &'de ConfigElement
+{
+	type Deserializer = Deserializer<'de, 'de>;
+
+	fn into_deserializer(self) -> Deserializer<'de, 'de>
+	{
+		Deserializer::new(self, None)
+	}
+}
+
+struct EnumHelper<'de, 'src: 'de>
+{
+	deserializer: Deserializer<'de, 'src>,
+}
+
+impl<'de, 'src> de::EnumAccess<'de> for EnumHelper<'de, 'src>
 {
 	type Error = Error;
 	type Variant = VariantHelper<'de, 'src>;
@@ -428,22 +1079,38 @@ impl<'de, 'src> de::EnumAccess<'de> for Deserializer<'de, 'src>
 	where
 		V: de::DeserializeSeed<'de>,
 	{
-		let span = self.element.span();
-		match *self.element.kind()
+		let deserializer = self.deserializer;
+		let span = deserializer.element.span();
+		let source = deserializer.source;
+		let options = deserializer.options;
+		let tag = match deserializer.element
+		{
+			Cow::Borrowed(elem) => elem.tag().map(|tag| Cow::Borrowed(&tag[..])),
+			Cow::Owned(ref elem) => elem.tag().map(|tag| Cow::Owned(tag.clone())),
+		};
+		match *deserializer.element.kind()
 		{
 			ConfigElementKind::Value(_) => Ok((
-				seed.deserialize(self)?,
-				VariantHelper::new(None, self.source, span),
+				seed.deserialize(deserializer)?,
+				VariantHelper::new(None, source, options, span, false),
 			)),
-			ConfigElementKind::TaggedTable(ref tag, _) => Ok((
-				seed.deserialize(HackStringDeserializer::new(&*tag))?,
-				VariantHelper::new(Some(self.element), self.source, span),
+			ConfigElementKind::TaggedTable(_, _) | ConfigElementKind::TaggedArray(_, _) => Ok((
+				seed.deserialize(HackStringDeserializer::new(tag.unwrap()))?,
+				VariantHelper::new(Some(deserializer.element), source, options, span, false),
 			)),
-			ConfigElementKind::TaggedArray(ref tag, _) => Ok((
-				seed.deserialize(HackStringDeserializer::new(&*tag))?,
-				VariantHelper::new(Some(self.element), self.source, span),
+			// A table with a single entry, see `EnumEncoding::Table`.
+			ConfigElementKind::Table(ref table) if table.len() == 1 =>
+			{
+				let (variant, contents) = deserializer.into_entries().ok().unwrap().next().unwrap();
+				let span = contents.span();
+				Ok((
+					seed.deserialize(HackStringDeserializer::new(variant))?,
+					VariantHelper::new(Some(contents), source, options, span, true),
+				))
+			}
+			_ => Err(deserializer.error(
+				"Expected value, tagged array, tagged table or a table with a single entry.",
 			)),
-			_ => Err(self.error(&format!("Expected value, tagged array or tagged table."))),
 		}
 	}
 }
@@ -452,11 +1119,27 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 {
 	type Error = Error;
 
-	fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Error>
+	fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
 	where
 		V: Visitor<'de>,
 	{
-		Err(self.error("deserialize_any unimplemented"))
+		// Untyped values are strings, whatever they look like, since e.g. `007`
+		// or `true` might well be meant as one.
+		if self.element.as_value().is_some()
+		{
+			return self.visit_value(visitor);
+		}
+		let source = self.source;
+		let options = self.options;
+		match self.into_entries()
+		{
+			Ok(entries) => visitor.visit_map(MapHelper::new(entries, None, source, options)),
+			Err(d) => match d.into_elements()
+			{
+				Ok(elements) => visitor.visit_seq(SeqHelper::new(elements, source, options)),
+				Err(_) => unreachable!(),
+			},
+		}
 	}
 
 	fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Error>
@@ -473,7 +1156,7 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 		V: Visitor<'de>,
 	{
 		visitor
-			.visit_i8(self.primitive("i8")?)
+			.visit_i8(self.integer("i8")?)
 			.map_err(|e: Error| self.error(&e.to_string()))
 	}
 
@@ -482,7 +1165,7 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 		V: Visitor<'de>,
 	{
 		visitor
-			.visit_i16(self.primitive("i16")?)
+			.visit_i16(self.integer("i16")?)
 			.map_err(|e: Error| self.error(&e.to_string()))
 	}
 
@@ -491,7 +1174,7 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 		V: Visitor<'de>,
 	{
 		visitor
-			.visit_i32(self.primitive("i32")?)
+			.visit_i32(self.integer("i32")?)
 			.map_err(|e: Error| self.error(&e.to_string()))
 	}
 
@@ -500,7 +1183,16 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 		V: Visitor<'de>,
 	{
 		visitor
-			.visit_i64(self.primitive("i64")?)
+			.visit_i64(self.integer("i64")?)
+			.map_err(|e: Error| self.error(&e.to_string()))
+	}
+
+	fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Error>
+	where
+		V: Visitor<'de>,
+	{
+		visitor
+			.visit_i128(self.integer("i128")?)
 			.map_err(|e: Error| self.error(&e.to_string()))
 	}
 
@@ -509,7 +1201,7 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 		V: Visitor<'de>,
 	{
 		visitor
-			.visit_u8(self.primitive("u8")?)
+			.visit_u8(self.integer("u8")?)
 			.map_err(|e: Error| self.error(&e.to_string()))
 	}
 
@@ -518,7 +1210,7 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 		V: Visitor<'de>,
 	{
 		visitor
-			.visit_u16(self.primitive("u16")?)
+			.visit_u16(self.integer("u16")?)
 			.map_err(|e: Error| self.error(&e.to_string()))
 	}
 
@@ -527,7 +1219,7 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 		V: Visitor<'de>,
 	{
 		visitor
-			.visit_u32(self.primitive("u32")?)
+			.visit_u32(self.integer("u32")?)
 			.map_err(|e: Error| self.error(&e.to_string()))
 	}
 
@@ -536,7 +1228,16 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 		V: Visitor<'de>,
 	{
 		visitor
-			.visit_u64(self.primitive("u64")?)
+			.visit_u64(self.integer("u64")?)
+			.map_err(|e: Error| self.error(&e.to_string()))
+	}
+
+	fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Error>
+	where
+		V: Visitor<'de>,
+	{
+		visitor
+			.visit_u128(self.integer("u128")?)
 			.map_err(|e: Error| self.error(&e.to_string()))
 	}
 
@@ -587,16 +1288,7 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 	where
 		V: Visitor<'de>,
 	{
-		if let Some(value) = self.element.as_value()
-		{
-			visitor
-				.visit_borrowed_str(value)
-				.map_err(|e: Error| self.error(&e.to_string()))
-		}
-		else
-		{
-			Err(self.error(&format!("Can't parse array/table as a string.")))
-		}
+		self.visit_value(visitor)
 	}
 
 	fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Error>
@@ -610,52 +1302,47 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 	where
 		V: Visitor<'de>,
 	{
-		if let Some(array) = self.element.as_array()
-		{
-			let mut bytes = vec![];
-
-			for element in array
-			{
-				bytes.push(from_element(element, self.source)?);
-			}
-
-			visitor
-				.visit_bytes(&bytes)
-				.map_err(|e: Error| self.error(&e.to_string()))
-		}
-		else
-		{
-			Err(self.error(&format!("Can't parse value/table as byte array.")))
-		}
+		let span = self.element.span();
+		let source = self.source;
+		visitor
+			.visit_bytes(&self.bytes()?)
+			.map_err(|e: Error| repr_error(span, source, &e.to_string()))
 	}
 
 	fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Error>
 	where
 		V: Visitor<'de>,
 	{
-		if let Some(array) = self.element.as_array()
-		{
-			let mut bytes = vec![];
-
-			for element in array
-			{
-				bytes.push(from_element(element, self.source)?);
-			}
-
-			visitor
-				.visit_byte_buf(bytes)
-				.map_err(|e: Error| self.error(&e.to_string()))
-		}
-		else
-		{
-			Err(self.error(&format!("Can't parse value/table as byte array.")))
-		}
+		let span = self.element.span();
+		let source = self.source;
+		visitor
+			.visit_byte_buf(self.bytes()?)
+			.map_err(|e: Error| repr_error(span, source, &e.to_string()))
 	}
 
 	fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
 	where
 		V: Visitor<'de>,
 	{
+		if self.options.option_encoding == OptionEncoding::Tagged
+		{
+			let span = self.element.span();
+			let source = self.source;
+			let options = self.options;
+			let tag = self.element.tag().cloned();
+			return match (tag.as_deref(), self.into_elements())
+			{
+				(Some("None"), Ok(elements)) if elements.len() == 0 => visitor.visit_none(),
+				(Some("Some"), Ok(mut elements)) if elements.len() == 1 => visitor.visit_some(
+					Deserializer::from_cow(elements.next().unwrap(), source, options),
+				),
+				_ => Err(repr_error(
+					span,
+					source,
+					"Expected 'None []' or 'Some [value]'.",
+				)),
+			};
+		}
 		if let Some(value) = self.element.as_value()
 		{
 			if value.is_empty()
@@ -724,23 +1411,41 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 	where
 		V: Visitor<'de>,
 	{
+		if name == element_serde::NAME
+		{
+			return visitor.visit_map(ElementHelper::new(self));
+		}
+		if self.options.transparent_newtypes
+		{
+			return visitor.visit_newtype_struct(self);
+		}
 		if let Some(tag) = self.element.tag()
 		{
 			if name != tag
 			{
-				return Err(self.error(&format!(
-					"Cannot deserialize struct '{}' from a table with tag '{}'.",
-					name, tag,
-				)));
+				return Err(repr_error(
+					self.element.tag_span(),
+					self.source,
+					&format!(
+						"Cannot deserialize struct '{}' from a table with tag '{}'.",
+						name, tag,
+					),
+				));
 			}
 		}
-		if let Some([elem]) = self.element.as_array().map(|a| &a[..])
-		{
-			visitor.visit_newtype_struct(Deserializer::new(elem, self.source))
-		}
-		else
+		let span = self.element.span();
+		let source = self.source;
+		let options = self.options;
+		match self.into_elements()
 		{
-			Err(self.error(&format!("Expected an array with 1 element.")))
+			Ok(mut elements) if elements.len() == 1 => visitor.visit_newtype_struct(
+				Deserializer::from_cow(elements.next().unwrap(), source, options),
+			),
+			_ => Err(repr_error(
+				span,
+				source,
+				&format!("Expected an array with 1 element."),
+			)),
 		}
 	}
 
@@ -748,13 +1453,12 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 	where
 		V: Visitor<'de>,
 	{
-		if let Some(array) = self.element.as_array()
-		{
-			visitor.visit_seq(SeqHelper::new(array, self.source))
-		}
-		else
+		let source = self.source;
+		let options = self.options;
+		match self.into_elements()
 		{
-			Err(self.error(&format!("Expected an array.")))
+			Ok(elements) => visitor.visit_seq(SeqHelper::new(elements, source, options)),
+			Err(d) => Err(d.error(&format!("Expected an array."))),
 		}
 	}
 
@@ -762,20 +1466,27 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 	where
 		V: Visitor<'de>,
 	{
-		if let Some(array) = self.element.as_array()
+		let span = self.element.span();
+		let source = self.source;
+		let options = self.options;
+		match self.into_elements()
 		{
-			if array.len() == len
-			{
-				visitor.visit_seq(SeqHelper::new(array, self.source))
-			}
-			else
+			Ok(elements) =>
 			{
-				Err(self.error(&format!("Expected an array with {} elements.", len)))
+				if elements.len() == len
+				{
+					visitor.visit_seq(SeqHelper::new(elements, source, options))
+				}
+				else
+				{
+					Err(repr_error(
+						span,
+						source,
+						&format!("Expected an array with {} elements.", len),
+					))
+				}
 			}
-		}
-		else
-		{
-			Err(self.error(&format!("Expected an array.")))
+			Err(d) => Err(d.error(&format!("Expected an array."))),
 		}
 	}
 
@@ -789,10 +1500,14 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 		{
 			if name != tag
 			{
-				return Err(self.error(&format!(
-					"Cannot deserialize struct '{}' from a table with tag '{}'.",
-					name, tag,
-				)));
+				return Err(repr_error(
+					self.element.tag_span(),
+					self.source,
+					&format!(
+						"Cannot deserialize struct '{}' from a table with tag '{}'.",
+						name, tag,
+					),
+				));
 			}
 		}
 		self.deserialize_tuple(len, visitor)
@@ -802,13 +1517,16 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 	where
 		V: Visitor<'de>,
 	{
-		if let Some(array) = self.element.as_array()
-		{
-			visitor.visit_map(SeqHelper::new(array, self.source))
-		}
-		else
+		let source = self.source;
+		let options = self.options;
+		match self.into_entries()
 		{
-			Err(self.error(&format!("Expected an array.")))
+			Ok(entries) => visitor.visit_map(MapHelper::new_typed_keys(entries, source, options)),
+			Err(d) => match d.into_elements()
+			{
+				Ok(elements) => visitor.visit_map(SeqHelper::new(elements, source, options)),
+				Err(d) => Err(d.error("Expected a table or an array.")),
+			},
 		}
 	}
 
@@ -818,23 +1536,33 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 	where
 		V: Visitor<'de>,
 	{
+		if name == spanned::NAME
+		{
+			return visitor.visit_map(SpannedHelper::new(self));
+		}
 		if let Some(tag) = self.element.tag()
 		{
 			if tag != name
 			{
-				return Err(self.error(&format!(
-					"Cannot deserialize struct '{}' from a table with tag '{}'.",
-					name, tag,
-				)));
+				return Err(repr_error(
+					self.element.tag_span(),
+					self.source,
+					&format!(
+						"Cannot deserialize struct '{}' from a table with tag '{}'.",
+						name, tag,
+					),
+				));
 			}
 		}
-		if let Some(table) = self.element.as_table()
+		let source = self.source;
+		let options = self.options;
+		match self.into_entries()
 		{
-			visitor.visit_map(MapHelper::new(table, fields, self.source))
-		}
-		else
-		{
-			Err(self.error(&format!("Expected a table.")))
+			Ok(entries) =>
+			{
+				visitor.visit_map(MapHelper::new(entries, Some(fields), source, options))
+			}
+			Err(d) => Err(d.error(&format!("Expected a table."))),
 		}
 	}
 
@@ -844,7 +1572,7 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 	where
 		V: Visitor<'de>,
 	{
-		visitor.visit_enum(self)
+		visitor.visit_enum(EnumHelper { deserializer: self })
 	}
 
 	fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Error>
@@ -854,10 +1582,10 @@ impl<'de, 'src> de::Deserializer<'de> for Deserializer<'de, 'src>
 		self.deserialize_str(visitor)
 	}
 
-	fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value, Error>
+	fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Error>
 	where
 		V: Visitor<'de>,
 	{
-		Err(self.error("deserialize_ignored_any unimplemented"))
+		visitor.visit_unit()
 	}
 }
@@ -0,0 +1,220 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+use crate::config_element::ConfigElement;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::str::FromStr;
+
+/// A type whose documentation can be attached to its serialized form as
+/// comments. This is usually implemented via `#[derive(SlrDocumented)]`, see
+/// `to_element_documented`.
+pub trait SlrDocumented
+{
+	/// Returns the documentation of this type.
+	fn docs() -> Docs;
+}
+
+/// The documentation of a type, mirroring the structure of its serialized
+/// form.
+#[derive(Clone, Debug, Default)]
+pub struct Docs
+{
+	doc: Option<String>,
+	fields: Vec<(String, Docs)>,
+	variants: Vec<(String, Docs)>,
+	elements: Option<Box<Docs>>,
+	newtype: Option<Box<Docs>>,
+}
+
+impl Docs
+{
+	/// Creates empty documentation.
+	pub fn new() -> Docs
+	{
+		Docs::default()
+	}
+
+	/// Sets the comment attached to the element itself.
+	pub fn doc<T: ToString>(mut self, doc: T) -> Docs
+	{
+		self.doc = Some(doc.to_string());
+		self
+	}
+
+	/// Documents a struct field. For tables, `name` is the key of the entry,
+	/// for arrays (tuple structs) it is the index of the element.
+	pub fn field<T: ToString>(mut self, name: T, docs: Docs) -> Docs
+	{
+		self.fields.push((name.to_string(), docs));
+		self
+	}
+
+	/// Documents the fields of a flattened struct.
+	pub fn flatten(mut self, docs: Docs) -> Docs
+	{
+		self.fields.extend(docs.fields);
+		self
+	}
+
+	/// Documents an enum variant. The variant is identified by the tag of the
+	/// element, the value of the element (for unit variants), or the key of a
+	/// single entry table.
+	pub fn variant<T: ToString>(mut self, name: T, docs: Docs) -> Docs
+	{
+		self.variants.push((name.to_string(), docs));
+		self
+	}
+
+	/// Documents every element of an array, or every value of a table.
+	pub fn elements(mut self, docs: Docs) -> Docs
+	{
+		self.elements = Some(Box::new(docs));
+		self
+	}
+
+	/// Documents the contents of a newtype struct.
+	pub fn newtype(mut self, docs: Docs) -> Docs
+	{
+		self.newtype = Some(Box::new(docs));
+		self
+	}
+
+	/// Attaches the documentation as comments to an element serialized from
+	/// the documented type. Elements that already have a comment keep it.
+	pub(crate) fn apply(&self, element: &mut ConfigElement, transparent_newtypes: bool)
+	{
+		if let Some(ref doc) = self.doc
+		{
+			if element.comment().is_none()
+			{
+				*element.comment_mut() = Some(doc.clone());
+			}
+		}
+		if let Some(ref newtype) = self.newtype
+		{
+			if transparent_newtypes
+			{
+				newtype.apply(element, transparent_newtypes);
+			}
+			else if let Some(inner) = element.as_array_mut().and_then(|a| a.first_mut())
+			{
+				newtype.apply(inner, transparent_newtypes);
+			}
+		}
+		for (name, docs) in &self.fields
+		{
+			let child = match (element.as_table().is_some(), usize::from_str(name))
+			{
+				(true, _) => element.as_table_mut().and_then(|t| t.get_mut(name)),
+				(false, Ok(idx)) => element.as_array_mut().and_then(|a| a.get_mut(idx)),
+				_ => None,
+			};
+			if let Some(child) = child
+			{
+				docs.apply(child, transparent_newtypes);
+			}
+		}
+		if !self.variants.is_empty()
+		{
+			self.apply_variant(element, transparent_newtypes);
+		}
+		if let Some(ref elements) = self.elements
+		{
+			if let Some(table) = element.as_table_mut()
+			{
+				for child in table.values_mut()
+				{
+					elements.apply(child, transparent_newtypes);
+				}
+			}
+			else if let Some(array) = element.as_array_mut()
+			{
+				for child in array
+				{
+					elements.apply(child, transparent_newtypes);
+				}
+			}
+		}
+	}
+
+	fn apply_variant(&self, element: &mut ConfigElement, transparent_newtypes: bool)
+	{
+		let find = |name: &str| self.variants.iter().find(|v| v.0 == name).map(|v| &v.1);
+		if let Some(docs) = element.tag().and_then(|tag| find(tag))
+		{
+			docs.apply(element, transparent_newtypes);
+		}
+		else if let Some(docs) = element.as_value().and_then(|value| find(value))
+		{
+			docs.apply(element, transparent_newtypes);
+		}
+		else if let Some(table) = element.as_table_mut()
+		{
+			if table.len() == 1
+			{
+				let (name, contents) = table.iter_mut().next().unwrap();
+				if let Some(docs) = find(name)
+				{
+					docs.apply(contents, transparent_newtypes);
+				}
+			}
+		}
+	}
+}
+
+impl<T: SlrDocumented> SlrDocumented for Option<T>
+{
+	fn docs() -> Docs
+	{
+		T::docs()
+	}
+}
+
+impl<T: SlrDocumented> SlrDocumented for Box<T>
+{
+	fn docs() -> Docs
+	{
+		T::docs()
+	}
+}
+
+impl<T: SlrDocumented> SlrDocumented for Vec<T>
+{
+	fn docs() -> Docs
+	{
+		Docs::new().elements(T::docs())
+	}
+}
+
+impl<T: SlrDocumented> SlrDocumented for VecDeque<T>
+{
+	fn docs() -> Docs
+	{
+		Docs::new().elements(T::docs())
+	}
+}
+
+impl<T: SlrDocumented, const N: usize> SlrDocumented for [T; N]
+{
+	fn docs() -> Docs
+	{
+		Docs::new().elements(T::docs())
+	}
+}
+
+impl<K, V: SlrDocumented> SlrDocumented for BTreeMap<K, V>
+{
+	fn docs() -> Docs
+	{
+		Docs::new().elements(V::docs())
+	}
+}
+
+impl<K, V: SlrDocumented, S> SlrDocumented for HashMap<K, V, S>
+{
+	fn docs() -> Docs
+	{
+		Docs::new().elements(V::docs())
+	}
+}
@@ -0,0 +1,399 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+use crate::config_element::{ConfigElement, ConfigElementKind, Layout};
+use crate::spanned::span_from_start_end;
+use indexmap::IndexMap;
+use serde::de::{self, Deserialize, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use slr_parser::{Span, StringQuoteType};
+use std::cell::RefCell;
+use std::fmt;
+
+// When the deserializer sees this name, it passes the element as a map with the
+// fields below, which preserves the tags, the spans and the quote types. The
+// other spans are passed as a `(start, end)` pair, only when valid.
+pub(crate) const NAME: &str = "$__slr_config_private_ConfigElement";
+pub(crate) const START_FIELD: &str = "$__slr_config_private_element_start";
+pub(crate) const END_FIELD: &str = "$__slr_config_private_element_end";
+pub(crate) const KEY_SPAN_FIELD: &str = "$__slr_config_private_element_key_span";
+pub(crate) const TAG_SPAN_FIELD: &str = "$__slr_config_private_element_tag_span";
+pub(crate) const EXTENT_FIELD: &str = "$__slr_config_private_element_extent";
+pub(crate) const TAG_FIELD: &str = "$__slr_config_private_element_tag";
+pub(crate) const QUOTE_TYPE_FIELD: &str = "$__slr_config_private_element_quote_type";
+pub(crate) const KEY_QUOTE_TYPE_FIELD: &str = "$__slr_config_private_element_key_quote_type";
+pub(crate) const VALUE_FIELD: &str = "$__slr_config_private_element_value";
+pub(crate) const TABLE_FIELD: &str = "$__slr_config_private_element_table";
+pub(crate) const ARRAY_FIELD: &str = "$__slr_config_private_element_array";
+
+// Tagged tables and arrays are serialized as a newtype struct with this name,
+// containing a map with a single entry from the tag to the contents. The
+// serializer turns these back into tagged elements, other serializers see a
+// single entry map.
+pub(crate) const TAGGED_NAME: &str = "$__slr_config_private_Tagged";
+
+// Elements with a comment or a layout other than `Layout::Auto` are serialized
+// as a newtype struct with this name around the element. A name can't carry the
+// comment, so while the element is serialized its layout and comment are
+// stored in `METADATA`, where the serializers of this crate take them from.
+// Other serializers see the element itself.
+pub(crate) const METADATA_NAME: &str = "$__slr_config_private_Metadata";
+
+thread_local! {
+	static METADATA: RefCell<Option<(Layout, Option<String>)>> = const { RefCell::new(None) };
+}
+
+/// Takes the layout and the comment of the element being serialized, see
+/// `METADATA_NAME`.
+pub(crate) fn take_metadata() -> Option<(Layout, Option<String>)>
+{
+	METADATA.with(|metadata| metadata.borrow_mut().take())
+}
+
+/// Encodes a quote type as a number: 0 for naked strings, and the number of
+/// braces plus one for quoted strings.
+pub(crate) fn encode_quote_type(quote_type: StringQuoteType) -> usize
+{
+	match quote_type
+	{
+		StringQuoteType::Naked => 0,
+		StringQuoteType::Quoted(num_braces) => num_braces + 1,
+	}
+}
+
+fn decode_quote_type(quote_type: usize) -> StringQuoteType
+{
+	match quote_type
+	{
+		0 => StringQuoteType::Naked,
+		_ => StringQuoteType::Quoted(quote_type - 1),
+	}
+}
+
+/// Serializes the contents of a table or an array, ignoring the tag.
+struct Contents<'l>(&'l ConfigElement);
+
+impl<'l> Serialize for Contents<'l>
+{
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		match (self.0.as_table(), self.0.as_array())
+		{
+			(Some(table), _) => serializer.collect_map(table),
+			(_, Some(array)) => serializer.collect_seq(array),
+			_ => self.0.serialize(serializer),
+		}
+	}
+}
+
+struct Tagged<'l>(&'l str, &'l ConfigElement);
+
+impl<'l> Serialize for Tagged<'l>
+{
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		let mut map = serializer.serialize_map(Some(1))?;
+		map.serialize_entry(self.0, &Contents(self.1))?;
+		map.end()
+	}
+}
+
+/// Serializes an element without its layout and comment.
+struct Bare<'l>(&'l ConfigElement);
+
+impl<'l> Serialize for Bare<'l>
+{
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		match *self.0.kind()
+		{
+			ConfigElementKind::Value(ref value) => serializer.serialize_str(value),
+			ConfigElementKind::TaggedTable(ref tag, _)
+			| ConfigElementKind::TaggedArray(ref tag, _) =>
+			{
+				serializer.serialize_newtype_struct(TAGGED_NAME, &Tagged(tag, self.0))
+			}
+			_ => Contents(self.0).serialize(serializer),
+		}
+	}
+}
+
+/// Values are serialized as strings, tables as maps and arrays as sequences.
+/// Tagged tables and arrays are serialized as a map with a single entry from
+/// the tag to the contents, unless they are serialized to a `ConfigElement`
+/// (or printed with `to_string` and `to_writer`), which keeps the tags, the
+/// layouts and the comments.
+impl Serialize for ConfigElement
+{
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		if self.layout() == Layout::Auto && self.comment().is_none()
+		{
+			return Bare(self).serialize(serializer);
+		}
+		let metadata = Some((self.layout(), self.comment().cloned()));
+		let outer = METADATA.with(|cell| cell.replace(metadata));
+		let ret = serializer.serialize_newtype_struct(METADATA_NAME, &Bare(self));
+		METADATA.with(|cell| *cell.borrow_mut() = outer);
+		ret
+	}
+}
+
+/// When deserialized from a `ConfigElement` (or directly from a source), this
+/// is an exact copy of the element, including its tags and spans. Otherwise,
+/// maps become tables, sequences become arrays and everything else becomes a
+/// value.
+impl<'de> Deserialize<'de> for ConfigElement
+{
+	fn deserialize<D>(deserializer: D) -> Result<ConfigElement, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		deserializer.deserialize_newtype_struct(NAME, ElementVisitor)
+	}
+}
+
+fn pair_span<E: de::Error>((start, end): (usize, usize)) -> Result<Span, E>
+{
+	span_from_start_end(start, end)
+}
+
+fn key_string<E: de::Error>(key: ConfigElement) -> Result<String, E>
+{
+	key.into_value()
+		.ok_or_else(|| E::custom("Expected a value as a table key."))
+}
+
+struct ElementVisitor;
+
+impl<'de> Visitor<'de> for ElementVisitor
+{
+	type Value = ConfigElement;
+
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+	{
+		formatter.write_str("a configuration element")
+	}
+
+	fn visit_bool<E>(self, v: bool) -> Result<ConfigElement, E>
+	{
+		Ok(ConfigElement::new_value(v))
+	}
+
+	fn visit_i64<E>(self, v: i64) -> Result<ConfigElement, E>
+	{
+		Ok(ConfigElement::new_value(v))
+	}
+
+	fn visit_i128<E>(self, v: i128) -> Result<ConfigElement, E>
+	{
+		Ok(ConfigElement::new_value(v))
+	}
+
+	fn visit_u64<E>(self, v: u64) -> Result<ConfigElement, E>
+	{
+		Ok(ConfigElement::new_value(v))
+	}
+
+	fn visit_u128<E>(self, v: u128) -> Result<ConfigElement, E>
+	{
+		Ok(ConfigElement::new_value(v))
+	}
+
+	fn visit_f64<E>(self, v: f64) -> Result<ConfigElement, E>
+	{
+		Ok(ConfigElement::new_value(v))
+	}
+
+	fn visit_str<E>(self, v: &str) -> Result<ConfigElement, E>
+	{
+		Ok(ConfigElement::new_value(v))
+	}
+
+	fn visit_string<E>(self, v: String) -> Result<ConfigElement, E>
+	{
+		Ok(ConfigElement::new_value(v))
+	}
+
+	fn visit_bytes<E>(self, v: &[u8]) -> Result<ConfigElement, E>
+	{
+		let mut array = ConfigElement::new_array();
+		for b in v
+		{
+			array.insert("", ConfigElement::new_value(b));
+		}
+		Ok(array)
+	}
+
+	fn visit_none<E>(self) -> Result<ConfigElement, E>
+	{
+		Ok(ConfigElement::new_value(""))
+	}
+
+	fn visit_some<D>(self, deserializer: D) -> Result<ConfigElement, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		ConfigElement::deserialize(deserializer)
+	}
+
+	fn visit_unit<E>(self) -> Result<ConfigElement, E>
+	{
+		Ok(ConfigElement::new_value(""))
+	}
+
+	fn visit_newtype_struct<D>(self, deserializer: D) -> Result<ConfigElement, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		deserializer.deserialize_any(ElementVisitor)
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<ConfigElement, A::Error>
+	where
+		A: SeqAccess<'de>,
+	{
+		let mut array = ConfigElement::new_array();
+		while let Some(element) = seq.next_element()?
+		{
+			array.insert("", element);
+		}
+		Ok(array)
+	}
+
+	fn visit_map<A>(self, mut map: A) -> Result<ConfigElement, A::Error>
+	where
+		A: MapAccess<'de>,
+	{
+		let mut table = ConfigElement::new_table();
+		let first = match map.next_key()?
+		{
+			Some(key) => key_string(key)?,
+			None => return Ok(table),
+		};
+		if first != START_FIELD
+		{
+			table.insert(&first, map.next_value()?);
+			while let Some(key) = map.next_key()?
+			{
+				table.insert(key_string::<A::Error>(key)?, map.next_value()?);
+			}
+			return Ok(table);
+		}
+
+		let start = map.next_value()?;
+		let mut end = start;
+		let mut key_span = Span::new();
+		let mut tag_span = Span::new();
+		let mut extent = Span::new();
+		let mut tag = None;
+		let mut quote_type = None;
+		let mut key_quote_type = None;
+		let mut kind = None;
+		while let Some(key) = map.next_key::<String>()?
+		{
+			match &key[..]
+			{
+				END_FIELD => end = map.next_value()?,
+				KEY_SPAN_FIELD => key_span = pair_span(map.next_value()?)?,
+				TAG_SPAN_FIELD => tag_span = pair_span(map.next_value()?)?,
+				EXTENT_FIELD => extent = pair_span(map.next_value()?)?,
+				TAG_FIELD => tag = Some(map.next_value::<String>()?),
+				QUOTE_TYPE_FIELD => quote_type = Some(decode_quote_type(map.next_value()?)),
+				KEY_QUOTE_TYPE_FIELD => key_quote_type = Some(decode_quote_type(map.next_value()?)),
+				VALUE_FIELD => kind = Some(ConfigElementKind::Value(map.next_value()?)),
+				TABLE_FIELD =>
+				{
+					let table = map.next_value_seed(TableSeed)?;
+					kind = Some(match tag.take()
+					{
+						Some(tag) => ConfigElementKind::TaggedTable(tag, table),
+						None => ConfigElementKind::Table(table),
+					});
+				}
+				ARRAY_FIELD =>
+				{
+					let array = map.next_value()?;
+					kind = Some(match tag.take()
+					{
+						Some(tag) => ConfigElementKind::TaggedArray(tag, array),
+						None => ConfigElementKind::Array(array),
+					});
+				}
+				_ =>
+				{
+					return Err(de::Error::custom(format!(
+						"Unexpected field '{}' in a configuration element.",
+						key
+					)))
+				}
+			}
+		}
+		let span = span_from_start_end(start, end)?;
+		match kind
+		{
+			Some(kind) =>
+			{
+				let mut element = ConfigElement::new_value("");
+				*element.kind_mut() = kind;
+				*element.quote_type_mut() = quote_type;
+				*element.key_quote_type_mut() = key_quote_type;
+				Ok(element
+					.with_span(span)
+					.with_key_span(key_span)
+					.with_tag_span(tag_span)
+					.with_extent(extent))
+			}
+			None => Err(de::Error::custom(
+				"Configuration element is missing its contents.",
+			)),
+		}
+	}
+}
+
+struct TableSeed;
+
+impl<'de> DeserializeSeed<'de> for TableSeed
+{
+	type Value = IndexMap<String, ConfigElement>;
+
+	fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		deserializer.deserialize_map(TableVisitor)
+	}
+}
+
+struct TableVisitor;
+
+impl<'de> Visitor<'de> for TableVisitor
+{
+	type Value = IndexMap<String, ConfigElement>;
+
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+	{
+		formatter.write_str("a table")
+	}
+
+	fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+	where
+		A: MapAccess<'de>,
+	{
+		let mut table = IndexMap::new();
+		while let Some(key) = map.next_key()?
+		{
+			table.insert(key, map.next_value()?);
+		}
+		Ok(table)
+	}
+}
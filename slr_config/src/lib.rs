@@ -18,7 +18,7 @@ fn main()
 {
 	// Parse config element from value.
 	let root = ConfigElement::from_str("key = value").unwrap();
-	assert_eq!(root.as_table().unwrap()["key"].as_value().unwrap(), "value");
+	assert_eq!(root["key"].as_value().unwrap(), "value");
 
 	// Create a new table and print it to a string.
 	let mut root = ConfigElement::new_table();
@@ -45,21 +45,53 @@ fn main()
 	assert_eq!(schema.arr[1], 2);
 
 	let elem = to_element(&schema).unwrap();
-	assert_eq!(elem.as_table().unwrap()["key"].as_value().unwrap(), "5");
-	assert_eq!(elem.as_table().unwrap()["arr"].as_array().unwrap()[0].as_value().unwrap(), "1");
+	assert_eq!(elem["key"].as_value().unwrap(), "5");
+	assert_eq!(elem["arr"][0].as_value().unwrap(), "1");
 }
 ~~~
 */
 
 pub use crate::config_element::*;
-pub use crate::de::from_element;
-pub use crate::ser::to_element;
+#[doc(hidden)]
+pub use crate::convert::{__error, __field, __merge_field};
+pub use crate::convert::{from_element_into, FromElement, ToElement};
+pub use crate::de::{
+	from_element, from_element_with_options, from_file, from_owned_element, from_reader, from_str,
+	Deserializer, DeserializerOptions,
+};
+pub use crate::documented::{Docs, SlrDocumented};
+#[doc(hidden)]
+pub use crate::macros::{__Interpolated, __Literal, __ViaInto, __ViaSerialize, __literal};
+pub use crate::path::{ElementPath, PathSegment};
+pub use crate::ser::{
+	to_element, to_element_documented, to_element_documented_with_options, to_element_with_options,
+	to_string, to_string_with_options, BytesEncoding, EnumEncoding, OptionEncoding,
+	SerializerOptions,
+};
+pub use crate::spanned::Spanned;
+pub use crate::stream_de::{from_source, from_source_with_options};
+pub use crate::stream_ser::{to_writer, to_writer_with_options};
+pub use crate::visit::{Visit, VisitMut, WalkControl};
+pub use slr_config_derive::{FromElement, SlrDocumented, ToElement};
 pub use slr_parser::{Error, ErrorKind, Source};
 
+// Lets the derive macros refer to this crate as `::slr_config` in the tests.
+extern crate self as slr_config;
+
+mod bytes;
 mod config_element;
+mod convert;
 
 #[cfg(test)]
 mod test;
 
 mod de;
+mod documented;
+mod element_serde;
+mod macros;
+mod path;
 mod ser;
+mod spanned;
+mod stream_de;
+mod stream_ser;
+mod visit;
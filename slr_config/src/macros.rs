@@ -0,0 +1,266 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+use crate::config_element::ConfigElement;
+use crate::ser::to_element;
+use serde::ser::Serialize;
+
+/// Creates a `ConfigElement` using a syntax that mirrors SLRConfig.
+///
+/// The contents of the macro are the entries of the root table. Keys and
+/// values are identifiers or literals. Unlike in SLRConfig, entries must be
+/// separated by commas, and values that aren't valid Rust tokens (e.g. `a.b`)
+/// must be written as string literals. A value in parentheses is a Rust
+/// expression: if its type implements `Into<ConfigElement>` it is converted
+/// with that, otherwise it is serialized with `to_element`, panicking if
+/// serialization fails. Keys and tags in parentheses are Rust expressions
+/// implementing `ToString`.
+///
+/// The element is built directly, without parsing any text at runtime.
+///
+/// ~~~
+/// # #[macro_use]
+/// # extern crate slr_config;
+/// # fn main()
+/// # {
+/// let port = 8080;
+/// let root = slr! {
+///     name = "my server",
+///     port = (port),
+///     hosts = [localhost, "127.0.0.1"],
+///     limits = tag { files = 100, memory = 1.5 },
+///     log { level = debug },
+/// };
+/// assert_eq!(root["port"].as_value().unwrap(), "8080");
+/// assert_eq!(root["limits"].tag().unwrap(), "tag");
+/// assert_eq!(
+///     root,
+///     slr_config::ConfigElement::from_str(
+///         r#"
+///         name = "my server"
+///         port = 8080
+///         hosts = [localhost, 127.0.0.1]
+///         limits = tag { files = 100, memory = 1.5 }
+///         log { level = debug }
+///         "#
+///     )
+///     .unwrap()
+/// );
+/// # }
+/// ~~~
+#[macro_export]
+macro_rules! slr {
+	// Table entries.
+	(@table $table:ident) => {};
+	(@table $table:ident , $($rest:tt)*) => {
+		$crate::slr!(@table $table $($rest)*);
+	};
+	(@table $table:ident $key:tt { $($contents:tt)* } $($rest:tt)*) => {
+		$table.insert($crate::slr!(@string $key), $crate::slr!(@value { $($contents)* }));
+		$crate::slr!(@table $table $($rest)*);
+	};
+	(@table $table:ident $key:tt = $tag:tt [ $($contents:tt)* ] $($rest:tt)*) => {
+		$table.insert($crate::slr!(@string $key), $crate::slr!(@value $tag [ $($contents)* ]));
+		$crate::slr!(@table $table $($rest)*);
+	};
+	(@table $table:ident $key:tt = $tag:tt { $($contents:tt)* } $($rest:tt)*) => {
+		$table.insert($crate::slr!(@string $key), $crate::slr!(@value $tag { $($contents)* }));
+		$crate::slr!(@table $table $($rest)*);
+	};
+	(@table $table:ident $key:tt = - $value:literal $($rest:tt)*) => {
+		$table.insert($crate::slr!(@string $key), $crate::slr!(@value - $value));
+		$crate::slr!(@table $table $($rest)*);
+	};
+	(@table $table:ident $key:tt = $value:tt $($rest:tt)*) => {
+		$table.insert($crate::slr!(@string $key), $crate::slr!(@value $value));
+		$crate::slr!(@table $table $($rest)*);
+	};
+	(@table $table:ident $($rest:tt)*) => {
+		compile_error!(concat!("Expected a table entry, found: ", stringify!($($rest)*)));
+	};
+
+	// Array elements.
+	(@array $array:ident) => {};
+	(@array $array:ident , $($rest:tt)*) => {
+		$crate::slr!(@array $array $($rest)*);
+	};
+	(@array $array:ident $tag:tt [ $($contents:tt)* ] $($rest:tt)*) => {
+		$array.insert("", $crate::slr!(@value $tag [ $($contents)* ]));
+		$crate::slr!(@array $array $($rest)*);
+	};
+	(@array $array:ident $tag:tt { $($contents:tt)* } $($rest:tt)*) => {
+		$array.insert("", $crate::slr!(@value $tag { $($contents)* }));
+		$crate::slr!(@array $array $($rest)*);
+	};
+	(@array $array:ident - $value:literal $($rest:tt)*) => {
+		$array.insert("", $crate::slr!(@value - $value));
+		$crate::slr!(@array $array $($rest)*);
+	};
+	(@array $array:ident $value:tt $($rest:tt)*) => {
+		$array.insert("", $crate::slr!(@value $value));
+		$crate::slr!(@array $array $($rest)*);
+	};
+	(@array $array:ident $($rest:tt)*) => {
+		compile_error!(concat!("Expected an array element, found: ", stringify!($($rest)*)));
+	};
+
+	// Single elements.
+	(@value [ $($contents:tt)* ]) => {{
+		#[allow(unused_mut)]
+		let mut array = $crate::ConfigElement::new_array();
+		$crate::slr!(@array array $($contents)*);
+		array
+	}};
+	(@value { $($contents:tt)* }) => {{
+		#[allow(unused_mut)]
+		let mut table = $crate::ConfigElement::new_table();
+		$crate::slr!(@table table $($contents)*);
+		table
+	}};
+	(@value $tag:tt [ $($contents:tt)* ]) => {{
+		#[allow(unused_mut)]
+		let mut array = $crate::ConfigElement::new_tagged_array(
+			$crate::slr!(@string $tag).to_string()
+		);
+		$crate::slr!(@array array $($contents)*);
+		array
+	}};
+	(@value $tag:tt { $($contents:tt)* }) => {{
+		#[allow(unused_mut)]
+		let mut table = $crate::ConfigElement::new_tagged_table(
+			$crate::slr!(@string $tag).to_string()
+		);
+		$crate::slr!(@table table $($contents)*);
+		table
+	}};
+	(@value ( $value:expr )) => {{
+		#[allow(unused_imports)]
+		use $crate::{__ViaInto, __ViaSerialize};
+		$crate::__Interpolated($value).__slr_element()
+	}};
+	(@value - $value:literal) => {
+		$crate::ConfigElement::new_value(
+			format!("-{}", $crate::slr!(@string $value))
+		)
+	};
+	(@value $value:tt) => {
+		$crate::ConfigElement::new_value($crate::slr!(@string $value))
+	};
+
+	// Keys, tags and values.
+	(@string ( $value:expr )) => {
+		$value.to_string()
+	};
+	(@string $value:ident) => {
+		stringify!($value)
+	};
+	(@string $value:literal) => {{
+		// Numbers are taken from their text, so they don't need to fit the type
+		// they are inferred as.
+		#[allow(overflowing_literals)]
+		let value = $value;
+		$crate::__literal(stringify!($value), value)
+	}};
+	(@string $($value:tt)*) => {
+		compile_error!(concat!("Expected a string, found: ", stringify!($($value)*)))
+	};
+
+	($($contents:tt)*) => {
+		$crate::slr!(@value { $($contents)* })
+	};
+}
+
+#[doc(hidden)]
+pub struct __Interpolated<T>(pub T);
+
+/// Interpolates values that can be converted into elements directly.
+#[doc(hidden)]
+pub trait __ViaInto
+{
+	fn __slr_element(self) -> ConfigElement;
+}
+
+impl<T: Into<ConfigElement>> __ViaInto for __Interpolated<T>
+{
+	fn __slr_element(self) -> ConfigElement
+	{
+		self.0.into()
+	}
+}
+
+/// Interpolates the remaining values by serializing them. This is implemented
+/// for a reference so that method resolution prefers `__ViaInto`.
+#[doc(hidden)]
+pub trait __ViaSerialize
+{
+	fn __slr_element(self) -> ConfigElement;
+}
+
+impl<T: Serialize> __ViaSerialize for &__Interpolated<T>
+{
+	fn __slr_element(self) -> ConfigElement
+	{
+		match to_element(&self.0)
+		{
+			Ok(element) => element,
+			Err(err) => panic!("Could not interpolate a value: {}", err.text),
+		}
+	}
+}
+
+/// A literal that can be used as a key, a tag or a value.
+#[doc(hidden)]
+pub trait __Literal
+{
+	/// Returns the string represented by the literal, given its source text.
+	fn __slr_string(&self, text: &str) -> String;
+}
+
+impl __Literal for &str
+{
+	fn __slr_string(&self, _text: &str) -> String
+	{
+		self.to_string()
+	}
+}
+
+impl __Literal for bool
+{
+	fn __slr_string(&self, _text: &str) -> String
+	{
+		self.to_string()
+	}
+}
+
+impl __Literal for char
+{
+	fn __slr_string(&self, _text: &str) -> String
+	{
+		self.to_string()
+	}
+}
+
+macro_rules! impl_number_literal {
+	($($t:ty),*) => {
+		$(
+			impl __Literal for $t
+			{
+				fn __slr_string(&self, text: &str) -> String
+				{
+					// Keep the number as written, e.g. `1.50` rather than `1.5` and
+					// `0xff` rather than `255`.
+					text.trim_end_matches(stringify!($t)).replace('_', "")
+				}
+			}
+		)*
+	};
+}
+
+impl_number_literal!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+#[doc(hidden)]
+pub fn __literal<T: __Literal>(text: &str, literal: T) -> String
+{
+	literal.__slr_string(text)
+}
@@ -0,0 +1,222 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+use slr_parser::{Error, ErrorKind};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// A segment of an `ElementPath`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathSegment
+{
+	/// A table key, e.g. `a` or `"key with spaces"`. Arrays also accept
+	/// numeric keys, like in the `$a.0` expansions.
+	Key(String),
+	/// An array index, e.g. `[2]`.
+	Index(usize),
+}
+
+impl Display for PathSegment
+{
+	fn fmt(&self, formatter: &mut Formatter) -> fmt::Result
+	{
+		match *self
+		{
+			PathSegment::Key(ref key) =>
+			{
+				let is_naked =
+					!key.is_empty() && key.chars().all(|c| !c.is_whitespace() && !is_special(c));
+				if is_naked
+				{
+					formatter.write_str(key)
+				}
+				else
+				{
+					formatter.write_str("\"")?;
+					for c in key.chars()
+					{
+						if c == '"' || c == '\\'
+						{
+							formatter.write_str("\\")?;
+						}
+						write!(formatter, "{}", c)?;
+					}
+					formatter.write_str("\"")
+				}
+			}
+			PathSegment::Index(idx) => write!(formatter, "[{}]", idx),
+		}
+	}
+}
+
+fn is_special(c: char) -> bool
+{
+	c == '.' || c == '[' || c == ']' || c == '"' || c == '\\'
+}
+
+/// A path to an element nested inside tables and arrays, e.g. `a[2].b`.
+///
+/// Keys are separated by `.` and indices are written in brackets. Keys that
+/// are empty or contain whitespace, `.`, `[`, `]`, `"` or `\` must be quoted,
+/// e.g. `servers."example.com".port`. Inside quotes, `"` and `\` are escaped
+/// with a `\`. An empty path refers to the element itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ElementPath
+{
+	segments: Vec<PathSegment>,
+}
+
+impl ElementPath
+{
+	/// Creates an empty path.
+	pub fn new() -> ElementPath
+	{
+		ElementPath::default()
+	}
+
+	/// Parses a path.
+	pub fn parse(path: &str) -> Result<ElementPath, Error>
+	{
+		let error = |pos: usize, msg: &str| {
+			Error::new(
+				ErrorKind::InvalidPath,
+				format!(
+					"error: Invalid path '{}': {} at position {}.\n",
+					path, msg, pos
+				),
+			)
+		};
+		let mut segments = vec![];
+		let mut chars = path.char_indices().peekable();
+		// Whether a key is allowed without a preceding `.`.
+		let mut at_start = true;
+		while let Some(&(pos, c)) = chars.peek()
+		{
+			match c
+			{
+				'[' =>
+				{
+					chars.next();
+					let mut digits = String::new();
+					loop
+					{
+						match chars.next()
+						{
+							Some((_, ']')) => break,
+							Some((_, c)) if c.is_ascii_digit() => digits.push(c),
+							Some((pos, _)) => return Err(error(pos, "expected a digit or ']'")),
+							None => return Err(error(path.len(), "expected ']'")),
+						}
+					}
+					let idx = usize::from_str(&digits).map_err(|_| error(pos, "invalid index"))?;
+					segments.push(PathSegment::Index(idx));
+					at_start = false;
+					continue;
+				}
+				'.' if !at_start =>
+				{
+					chars.next();
+				}
+				_ if at_start => (),
+				_ => return Err(error(pos, "expected '.' or '['")),
+			}
+			at_start = false;
+			match chars.peek()
+			{
+				Some(&(_, '"')) =>
+				{
+					chars.next();
+					let mut key = String::new();
+					loop
+					{
+						match chars.next()
+						{
+							Some((_, '"')) => break,
+							Some((_, '\\')) => match chars.next()
+							{
+								Some((_, c)) => key.push(c),
+								None => return Err(error(path.len(), "unterminated quoted key")),
+							},
+							Some((_, c)) => key.push(c),
+							None => return Err(error(path.len(), "unterminated quoted key")),
+						}
+					}
+					segments.push(PathSegment::Key(key));
+				}
+				Some(&(pos, c)) if c.is_whitespace() || is_special(c) =>
+				{
+					return Err(error(pos, "expected a key"))
+				}
+				Some(_) =>
+				{
+					let mut key = String::new();
+					while let Some(&(_, c)) = chars.peek()
+					{
+						if c.is_whitespace() || is_special(c)
+						{
+							break;
+						}
+						key.push(c);
+						chars.next();
+					}
+					segments.push(PathSegment::Key(key));
+				}
+				None => return Err(error(path.len(), "expected a key")),
+			}
+		}
+		Ok(ElementPath { segments })
+	}
+
+	/// Returns the segments of this path.
+	pub fn segments(&self) -> &[PathSegment]
+	{
+		&self.segments
+	}
+
+	/// Appends a segment to this path.
+	pub fn push(&mut self, segment: PathSegment)
+	{
+		self.segments.push(segment);
+	}
+
+	/// Removes the last segment of this path.
+	pub fn pop(&mut self) -> Option<PathSegment>
+	{
+		self.segments.pop()
+	}
+}
+
+impl FromStr for ElementPath
+{
+	type Err = Error;
+
+	fn from_str(path: &str) -> Result<ElementPath, Error>
+	{
+		ElementPath::parse(path)
+	}
+}
+
+impl From<Vec<PathSegment>> for ElementPath
+{
+	fn from(segments: Vec<PathSegment>) -> ElementPath
+	{
+		ElementPath { segments }
+	}
+}
+
+impl Display for ElementPath
+{
+	fn fmt(&self, formatter: &mut Formatter) -> fmt::Result
+	{
+		for (i, segment) in self.segments.iter().enumerate()
+		{
+			if i > 0 && matches!(*segment, PathSegment::Key(_))
+			{
+				formatter.write_str(".")?;
+			}
+			write!(formatter, "{}", segment)?;
+		}
+		Ok(())
+	}
+}
@@ -1,39 +1,371 @@
-use crate::config_element::ConfigElement;
+use crate::bytes;
+use crate::config_element::{ConfigElement, ConfigElementKind, Layout};
+use crate::documented::SlrDocumented;
+use crate::element_serde;
 use serde;
 use serde::ser::{self, Serialize};
-use slr_parser::Error;
+use slr_parser::{Error, ErrorKind};
+use std::mem;
 
 /// Serialize a value to a ConfigElement.
-pub fn to_element<T: Serialize>(value: &T) -> Result<ConfigElement, Error>
+pub fn to_element<T: ?Sized + Serialize>(value: &T) -> Result<ConfigElement, Error>
 {
-	value.serialize(Serializer)
+	to_element_with_options(value, SerializerOptions::new())
 }
 
+/// Serialize a value to a ConfigElement, using the specified options.
+pub fn to_element_with_options<T: ?Sized + Serialize>(
+	value: &T, options: SerializerOptions,
+) -> Result<ConfigElement, Error>
+{
+	value.serialize(Serializer { options })
+}
+
+/// Serialize a value to a ConfigElement, attaching the documentation of its
+/// type as comments. See `SlrDocumented`.
+pub fn to_element_documented<T: ?Sized + Serialize + SlrDocumented>(
+	value: &T,
+) -> Result<ConfigElement, Error>
+{
+	to_element_documented_with_options(value, SerializerOptions::new())
+}
+
+/// Serialize a value to a ConfigElement, attaching the documentation of its
+/// type as comments, using the specified options.
+pub fn to_element_documented_with_options<T: ?Sized + Serialize + SlrDocumented>(
+	value: &T, options: SerializerOptions,
+) -> Result<ConfigElement, Error>
+{
+	let mut element = to_element_with_options(value, options)?;
+	T::docs().apply(&mut element, options.transparent_newtypes);
+	Ok(element)
+}
+
+/// Serialize a value to a string. The value must serialize to a table (e.g. a
+/// struct or a map with string keys).
+pub fn to_string<T: ?Sized + Serialize>(value: &T) -> Result<String, Error>
+{
+	to_string_with_options(value, SerializerOptions::new())
+}
+
+/// Serialize a value to a string, using the specified options.
+pub fn to_string_with_options<T: ?Sized + Serialize>(
+	value: &T, options: SerializerOptions,
+) -> Result<String, Error>
+{
+	let element = to_element_with_options(value, options)?;
+	if element.as_table().is_none()
+	{
+		return Err(Error::new(
+			ErrorKind::InvalidRepr,
+			"error: Expected a table.\n".to_string(),
+		));
+	}
+	Ok(element.to_string())
+}
+
+/// Serializes the contents of a tagged table or array, which are passed as a
+/// table with a single entry, see `element_serde::TAGGED_NAME`.
+pub(crate) fn tagged_element<T: ?Sized + Serialize>(
+	value: &T, options: SerializerOptions,
+) -> Result<ConfigElement, Error>
+{
+	let element = value.serialize(Serializer { options })?;
+	let (tag, contents) = match element.into_table()
+	{
+		Some(table) if table.len() == 1 => table.into_iter().next().unwrap(),
+		_ => return Err(ser::Error::custom("Expected a table with a single entry.")),
+	};
+	Ok(retag(contents, tag))
+}
+
+/// Serializes an element with a comment or a layout, see
+/// `element_serde::METADATA_NAME`.
+pub(crate) fn metadata_element<T: ?Sized + Serialize>(
+	value: &T, options: SerializerOptions,
+) -> Result<ConfigElement, Error>
+{
+	let metadata = element_serde::take_metadata();
+	let mut element = value.serialize(Serializer { options })?;
+	if let Some((layout, comment)) = metadata
+	{
+		if layout != Layout::Auto
+		{
+			*element.layout_mut() = layout;
+		}
+		*element.comment_mut() = comment;
+	}
+	Ok(element)
+}
+
+/// Turns an untagged table or array into a tagged one with the given tag.
+/// Other elements, including already tagged ones, are returned unchanged.
+fn retag(element: ConfigElement, tag: String) -> ConfigElement
+{
+	let mut element = element;
+	let kind = mem::replace(element.kind_mut(), ConfigElementKind::Array(vec![]));
+	*element.kind_mut() = match kind
+	{
+		ConfigElementKind::Table(table) => ConfigElementKind::TaggedTable(tag, table),
+		ConfigElementKind::Array(array) => ConfigElementKind::TaggedArray(tag, array),
+		kind => kind,
+	};
+	element
+}
+
+/// How enum variants with contents are encoded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EnumEncoding
+{
+	/// Newtype and tuple variants are encoded as tagged arrays, struct variants
+	/// as tagged tables, e.g. `Move [1, 2]` and `Rect { w = 1, h = 2 }`.
+	Tagged,
+	/// Variants are encoded as a table with a single entry, whose key is the
+	/// variant name, e.g. `{ Move = [1, 2] }` and `{ Rect { w = 1, h = 2 } }`.
+	/// Newtype variants are encoded as `{ Scale = 2 }`.
+	Table,
+}
+
+/// How `Option`s are encoded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OptionEncoding
+{
+	/// `None` is encoded as an empty value, `Some(v)` as the encoding of `v`.
+	/// This is ambiguous when `v` is itself encoded as an empty value, e.g.
+	/// `Some(String::new())`, `Some(())` and `Some(None)` are all read back as
+	/// `None`.
+	Empty,
+	/// `None` is encoded as `None []`, `Some(v)` as `Some [v]`. This
+	/// round-trips every value, including nested options.
+	Tagged,
+}
+
+/// How byte buffers (values serialized with `serialize_bytes`) are encoded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BytesEncoding
+{
+	/// An array of numbers, e.g. `[222, 173, 190, 239]`.
+	Array,
+	/// A string of lower case hex digits, e.g. `deadbeef`.
+	Hex,
+	/// A base64 string using the standard alphabet, e.g. `3q2+7w==`.
+	Base64,
+}
+
+/// Options that control how values are serialized.
+///
+/// ~~~
+/// use slr_config::{
+///     to_element_with_options, BytesEncoding, EnumEncoding, OptionEncoding, SerializerOptions,
+/// };
+///
+/// let options = SerializerOptions::new()
+///     .struct_tags(false)
+///     .transparent_newtypes(true)
+///     .multi_line_threshold(Some(4))
+///     .enum_encoding(EnumEncoding::Table)
+///     .option_encoding(OptionEncoding::Tagged)
+///     .bytes_encoding(BytesEncoding::Base64);
+/// let elem = to_element_with_options(&vec![1, 2, 3], options).unwrap();
+/// assert_eq!(elem.as_array().unwrap().len(), 3);
+/// ~~~
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SerializerOptions
+{
+	pub(crate) struct_tags: bool,
+	pub(crate) transparent_newtypes: bool,
+	pub(crate) multi_line_threshold: Option<usize>,
+	pub(crate) enum_encoding: EnumEncoding,
+	pub(crate) option_encoding: OptionEncoding,
+	pub(crate) bytes_encoding: BytesEncoding,
+}
+
+impl SerializerOptions
+{
+	/// Creates the default options, which match what `to_element` does.
+	pub fn new() -> Self
+	{
+		Self {
+			struct_tags: true,
+			transparent_newtypes: false,
+			multi_line_threshold: None,
+			enum_encoding: EnumEncoding::Tagged,
+			option_encoding: OptionEncoding::Empty,
+			bytes_encoding: BytesEncoding::Array,
+		}
+	}
+
+	/// Whether structs, tuple structs and newtype structs are tagged with
+	/// their names. Defaults to `true`.
+	pub fn struct_tags(mut self, struct_tags: bool) -> Self
+	{
+		self.struct_tags = struct_tags;
+		self
+	}
+
+	/// Whether newtype structs are encoded as the contained value, rather
+	/// than as a single element array. Values serialized this way must be
+	/// deserialized with `DeserializerOptions::transparent_newtypes` set.
+	/// Defaults to `false`.
+	pub fn transparent_newtypes(mut self, transparent_newtypes: bool) -> Self
+	{
+		self.transparent_newtypes = transparent_newtypes;
+		self
+	}
+
+	/// If set, arrays with more elements than the threshold are printed on
+	/// multiple lines, and all other arrays on a single line. If not set,
+	/// maps and tuple structs are printed on multiple lines and all other
+	/// arrays on a single line. Defaults to `None`.
+	pub fn multi_line_threshold(mut self, multi_line_threshold: Option<usize>) -> Self
+	{
+		self.multi_line_threshold = multi_line_threshold;
+		self
+	}
+
+	/// How enum variants with contents are encoded. Defaults to
+	/// `EnumEncoding::Tagged`. The deserializer accepts either encoding.
+	pub fn enum_encoding(mut self, enum_encoding: EnumEncoding) -> Self
+	{
+		self.enum_encoding = enum_encoding;
+		self
+	}
+
+	/// How options are encoded. Defaults to `OptionEncoding::Empty`. Values
+	/// serialized with `OptionEncoding::Tagged` must be deserialized with the
+	/// same `DeserializerOptions::option_encoding`.
+	pub fn option_encoding(mut self, option_encoding: OptionEncoding) -> Self
+	{
+		self.option_encoding = option_encoding;
+		self
+	}
+
+	/// How byte buffers are encoded. Defaults to `BytesEncoding::Array`.
+	/// Values serialized as strings must be deserialized with the same
+	/// `DeserializerOptions::bytes_encoding`.
+	pub fn bytes_encoding(mut self, bytes_encoding: BytesEncoding) -> Self
+	{
+		self.bytes_encoding = bytes_encoding;
+		self
+	}
+
+	/// Encodes a byte buffer as a string, unless arrays are used.
+	pub(crate) fn bytes_string(&self, bytes: &[u8]) -> Option<String>
+	{
+		match self.bytes_encoding
+		{
+			BytesEncoding::Array => None,
+			BytesEncoding::Hex => Some(bytes::encode_hex(bytes)),
+			BytesEncoding::Base64 => Some(bytes::encode_base64(bytes)),
+		}
+	}
+
+	fn struct_element(&self, name: &str, element: ConfigElement) -> ConfigElement
+	{
+		if !self.struct_tags
+		{
+			return element;
+		}
+		retag(element, name.to_string())
+	}
+
+	/// Picks the layout of an array.
+	fn array_layout(&self, element: ConfigElement, default_multi_line: bool) -> ConfigElement
+	{
+		let len = match element.as_array()
+		{
+			Some(array) => array.len(),
+			None => return element,
+		};
+		let multi_line = match self.multi_line_threshold
+		{
+			Some(threshold) => len > threshold,
+			None => default_multi_line,
+		};
+		element.with_layout(
+			if multi_line
+			{
+				Layout::MultiLine
+			}
+			else
+			{
+				Layout::Auto
+			},
+		)
+	}
+
+	/// Encodes the contents of an enum variant.
+	fn variant_element(&self, variant: &str, contents: ConfigElement) -> ConfigElement
+	{
+		match self.enum_encoding
+		{
+			EnumEncoding::Tagged => retag(contents, variant.to_string()),
+			EnumEncoding::Table =>
+			{
+				let mut ret = ConfigElement::new_table();
+				ret.insert(variant, contents);
+				ret
+			}
+		}
+	}
+}
+
+impl Default for SerializerOptions
+{
+	fn default() -> Self
+	{
+		Self::new()
+	}
+}
+
+/// Used for sequences, tuples, tuple structs and structs.
 struct SeqHelper
 {
 	element: ConfigElement,
+	/// Set for tuple structs and structs.
+	name: Option<&'static str>,
+	options: SerializerOptions,
 }
 
 impl SeqHelper
 {
-	fn new_array() -> Self
+	fn new_array(name: Option<&'static str>, options: SerializerOptions) -> Self
 	{
 		Self {
 			element: ConfigElement::new_array(),
+			name,
+			options,
 		}
 	}
 
-	fn new_multi_line_tagged_array(tag: &str) -> Self
+	fn new_table(name: &'static str, options: SerializerOptions) -> Self
 	{
 		Self {
-			element: ConfigElement::new_multi_line_tagged_array(tag.to_string()),
+			element: ConfigElement::new_table(),
+			name: Some(name),
+			options,
 		}
 	}
 
-	fn new_tagged_table(name: &str) -> Self
+	fn push<T>(&mut self, key: &str, value: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
 	{
-		Self {
-			element: ConfigElement::new_tagged_table(name.to_string()),
+		let value = value.serialize(Serializer {
+			options: self.options,
+		})?;
+		self.element.insert(key, value);
+		Ok(())
+	}
+
+	fn finish(self) -> Result<ConfigElement, Error>
+	{
+		// Tuple structs default to multiple lines.
+		let element = self.options.array_layout(self.element, self.name.is_some());
+		match self.name
+		{
+			Some(name) => Ok(self.options.struct_element(name, element)),
+			None => Ok(element),
 		}
 	}
 }
@@ -47,13 +379,12 @@ impl ser::SerializeSeq for SeqHelper
 	where
 		T: ?Sized + Serialize,
 	{
-		self.element.insert("", value.serialize(Serializer)?);
-		Ok(())
+		self.push("", value)
 	}
 
 	fn end(self) -> Result<ConfigElement, Error>
 	{
-		Ok(self.element)
+		self.finish()
 	}
 }
 
@@ -66,13 +397,12 @@ impl ser::SerializeTuple for SeqHelper
 	where
 		T: ?Sized + Serialize,
 	{
-		self.element.insert("", value.serialize(Serializer)?);
-		Ok(())
+		self.push("", value)
 	}
 
 	fn end(self) -> Result<ConfigElement, Error>
 	{
-		Ok(self.element)
+		self.finish()
 	}
 }
 
@@ -85,13 +415,12 @@ impl ser::SerializeTupleStruct for SeqHelper
 	where
 		T: ?Sized + Serialize,
 	{
-		self.element.insert("", value.serialize(Serializer)?);
-		Ok(())
+		self.push("", value)
 	}
 
 	fn end(self) -> Result<ConfigElement, Error>
 	{
-		Ok(self.element)
+		self.finish()
 	}
 }
 
@@ -104,31 +433,42 @@ impl ser::SerializeStruct for SeqHelper
 	where
 		T: ?Sized + Serialize,
 	{
-		self.element.insert(key, value.serialize(Serializer)?);
-		Ok(())
+		self.push(key, value)
 	}
 
 	fn end(self) -> Result<ConfigElement, Error>
 	{
-		Ok(self.element)
+		self.finish()
 	}
 }
 
-struct MapHelper
+/// Maps are serialized as tables if all their keys serialize to distinct
+/// values, and as arrays of key/value pairs otherwise.
+pub(crate) struct MapHelper
 {
 	key: Option<ConfigElement>,
 	element: ConfigElement,
+	options: SerializerOptions,
 }
 
 impl MapHelper
 {
-	fn new() -> Self
+	pub(crate) fn new(options: SerializerOptions) -> Self
 	{
 		Self {
 			key: None,
-			element: ConfigElement::new_multi_line_array(),
+			element: ConfigElement::new_table(),
+			options,
 		}
 	}
+
+	fn make_pair(&self, key: ConfigElement, value: ConfigElement) -> ConfigElement
+	{
+		let mut pair = ConfigElement::new_array();
+		pair.insert("", key);
+		pair.insert("", value);
+		self.options.array_layout(pair, false)
+	}
 }
 
 impl ser::SerializeMap for MapHelper
@@ -140,7 +480,9 @@ impl ser::SerializeMap for MapHelper
 	where
 		T: ?Sized + Serialize,
 	{
-		self.key = Some(key.serialize(Serializer)?);
+		self.key = Some(key.serialize(Serializer {
+			options: self.options,
+		})?);
 		Ok(())
 	}
 
@@ -149,11 +491,27 @@ impl ser::SerializeMap for MapHelper
 		T: ?Sized + Serialize,
 	{
 		let key = self.key.take().unwrap();
-		let value = value.serialize(Serializer)?;
+		let value = value.serialize(Serializer {
+			options: self.options,
+		})?;
 
-		let mut pair = ConfigElement::new_array();
-		pair.insert("", key);
-		pair.insert("", value);
+		if let Some(table) = self.element.as_table()
+		{
+			if key.as_value().is_some_and(|key| !table.contains_key(key))
+			{
+				self.element.insert(key.into_value().unwrap(), value);
+				return Ok(());
+			}
+			// This key can't be used in a table (or is already used by a
+			// different key with the same representation), so switch to pairs.
+			let table = mem::replace(&mut self.element, ConfigElement::new_array());
+			for (k, v) in table.into_table().unwrap()
+			{
+				let pair = self.make_pair(ConfigElement::new_value(k), v);
+				self.element.insert("", pair);
+			}
+		}
+		let pair = self.make_pair(key, value);
 		self.element.insert("", pair);
 
 		Ok(())
@@ -161,30 +519,53 @@ impl ser::SerializeMap for MapHelper
 
 	fn end(self) -> Result<ConfigElement, Error>
 	{
-		Ok(self.element)
+		Ok(self.options.array_layout(self.element, true))
 	}
 }
 
 struct VariantHelper
 {
 	element: ConfigElement,
+	variant: &'static str,
+	options: SerializerOptions,
 }
 
 impl VariantHelper
 {
-	fn new_tagged_array(variant: &'static str) -> Self
+	fn new_array(variant: &'static str, options: SerializerOptions) -> Self
 	{
 		Self {
-			element: ConfigElement::new_tagged_array(variant.to_string()),
+			element: ConfigElement::new_array(),
+			variant,
+			options,
 		}
 	}
 
-	fn new_tagged_table(variant: &'static str) -> Self
+	fn new_table(variant: &'static str, options: SerializerOptions) -> Self
 	{
 		Self {
-			element: ConfigElement::new_tagged_table(variant.to_string()),
+			element: ConfigElement::new_table(),
+			variant,
+			options,
 		}
 	}
+
+	fn push<T>(&mut self, key: &str, value: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		let value = value.serialize(Serializer {
+			options: self.options,
+		})?;
+		self.element.insert(key, value);
+		Ok(())
+	}
+
+	fn finish(self) -> Result<ConfigElement, Error>
+	{
+		let element = self.options.array_layout(self.element, false);
+		Ok(self.options.variant_element(self.variant, element))
+	}
 }
 
 impl ser::SerializeTupleVariant for VariantHelper
@@ -196,13 +577,12 @@ impl ser::SerializeTupleVariant for VariantHelper
 	where
 		T: ?Sized + Serialize,
 	{
-		self.element.insert("", value.serialize(Serializer)?);
-		Ok(())
+		self.push("", value)
 	}
 
 	fn end(self) -> Result<ConfigElement, Error>
 	{
-		Ok(self.element)
+		self.finish()
 	}
 }
 
@@ -215,17 +595,20 @@ impl ser::SerializeStructVariant for VariantHelper
 	where
 		T: ?Sized + Serialize,
 	{
-		self.element.insert(key, value.serialize(Serializer)?);
-		Ok(())
+		self.push(key, value)
 	}
 
 	fn end(self) -> Result<ConfigElement, Error>
 	{
-		Ok(self.element)
+		self.finish()
 	}
 }
 
-struct Serializer;
+#[derive(Copy, Clone)]
+struct Serializer
+{
+	options: SerializerOptions,
+}
 
 impl serde::Serializer for Serializer
 {
@@ -270,6 +653,11 @@ impl serde::Serializer for Serializer
 		Ok(ConfigElement::new_value(&v))
 	}
 
+	fn serialize_i128(self, v: i128) -> Result<ConfigElement, Error>
+	{
+		Ok(ConfigElement::new_value(v))
+	}
+
 	fn serialize_u8(self, v: u8) -> Result<ConfigElement, Error>
 	{
 		Ok(ConfigElement::new_value(&v))
@@ -290,6 +678,11 @@ impl serde::Serializer for Serializer
 		Ok(ConfigElement::new_value(&v))
 	}
 
+	fn serialize_u128(self, v: u128) -> Result<ConfigElement, Error>
+	{
+		Ok(ConfigElement::new_value(v))
+	}
+
 	fn serialize_f32(self, v: f32) -> Result<ConfigElement, Error>
 	{
 		Ok(ConfigElement::new_value(&v))
@@ -307,6 +700,10 @@ impl serde::Serializer for Serializer
 
 	fn serialize_bytes(self, v: &[u8]) -> Result<ConfigElement, Error>
 	{
+		if let Some(string) = self.options.bytes_string(v)
+		{
+			return Ok(ConfigElement::new_value(string));
+		}
 		let mut ret = ConfigElement::new_array();
 		for e in v
 		{
@@ -317,14 +714,27 @@ impl serde::Serializer for Serializer
 
 	fn serialize_none(self) -> Result<ConfigElement, Error>
 	{
-		Ok(ConfigElement::new_value(""))
+		match self.options.option_encoding
+		{
+			OptionEncoding::Empty => Ok(ConfigElement::new_value("")),
+			OptionEncoding::Tagged => Ok(ConfigElement::new_tagged_array("None".to_string())),
+		}
 	}
 
 	fn serialize_some<T>(self, v: &T) -> Result<ConfigElement, Error>
 	where
 		T: ?Sized + Serialize,
 	{
-		v.serialize(self)
+		match self.options.option_encoding
+		{
+			OptionEncoding::Empty => v.serialize(self),
+			OptionEncoding::Tagged =>
+			{
+				let mut ret = ConfigElement::new_tagged_array("Some".to_string());
+				ret.insert("", v.serialize(self)?);
+				Ok(self.options.array_layout(ret, false))
+			}
+		}
 	}
 
 	fn serialize_unit(self) -> Result<ConfigElement, Error>
@@ -348,9 +758,22 @@ impl serde::Serializer for Serializer
 	where
 		T: ?Sized + Serialize,
 	{
-		let mut ret = ConfigElement::new_tagged_array(name.to_string());
-		ret.insert("", v.serialize(Serializer)?);
-		Ok(ret)
+		if name == element_serde::TAGGED_NAME
+		{
+			return tagged_element(v, self.options);
+		}
+		if name == element_serde::METADATA_NAME
+		{
+			return metadata_element(v, self.options);
+		}
+		if self.options.transparent_newtypes
+		{
+			return v.serialize(self);
+		}
+		let mut ret = ConfigElement::new_array();
+		ret.insert("", v.serialize(self)?);
+		let ret = self.options.array_layout(ret, false);
+		Ok(self.options.struct_element(name, ret))
 	}
 
 	fn serialize_newtype_variant<T>(
@@ -359,47 +782,56 @@ impl serde::Serializer for Serializer
 	where
 		T: ?Sized + Serialize,
 	{
-		let mut ret = ConfigElement::new_tagged_array(variant.to_string());
-		ret.insert("", value.serialize(Serializer)?);
-		Ok(ret)
+		let value = value.serialize(self)?;
+		match self.options.enum_encoding
+		{
+			EnumEncoding::Tagged =>
+			{
+				let mut ret = ConfigElement::new_array();
+				ret.insert("", value);
+				let ret = self.options.array_layout(ret, false);
+				Ok(self.options.variant_element(variant, ret))
+			}
+			EnumEncoding::Table => Ok(self.options.variant_element(variant, value)),
+		}
 	}
 
 	fn serialize_seq(self, _len: Option<usize>) -> Result<SeqHelper, Error>
 	{
-		Ok(SeqHelper::new_array())
+		Ok(SeqHelper::new_array(None, self.options))
 	}
 
 	fn serialize_tuple(self, _len: usize) -> Result<SeqHelper, Error>
 	{
-		Ok(SeqHelper::new_array())
+		Ok(SeqHelper::new_array(None, self.options))
 	}
 
 	fn serialize_tuple_struct(self, name: &'static str, _len: usize) -> Result<SeqHelper, Error>
 	{
-		Ok(SeqHelper::new_multi_line_tagged_array(name))
+		Ok(SeqHelper::new_array(Some(name), self.options))
 	}
 
 	fn serialize_tuple_variant(
 		self, _name: &'static str, _variant_index: u32, variant: &'static str, _len: usize,
 	) -> Result<VariantHelper, Error>
 	{
-		Ok(VariantHelper::new_tagged_array(variant))
+		Ok(VariantHelper::new_array(variant, self.options))
 	}
 
 	fn serialize_map(self, _len: Option<usize>) -> Result<MapHelper, Error>
 	{
-		Ok(MapHelper::new())
+		Ok(MapHelper::new(self.options))
 	}
 
 	fn serialize_struct(self, name: &'static str, _len: usize) -> Result<SeqHelper, Error>
 	{
-		Ok(SeqHelper::new_tagged_table(name))
+		Ok(SeqHelper::new_table(name, self.options))
 	}
 
 	fn serialize_struct_variant(
 		self, _name: &'static str, _variant_index: u32, variant: &'static str, _len: usize,
 	) -> Result<VariantHelper, Error>
 	{
-		Ok(VariantHelper::new_tagged_table(variant))
+		Ok(VariantHelper::new_table(variant, self.options))
 	}
 }
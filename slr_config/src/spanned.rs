@@ -0,0 +1,183 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+use serde::de::{self, Deserialize, MapAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+use slr_parser::{Error, ErrorKind, Source, Span};
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+// These names are recognized by the deserializer, which then passes the span
+// information in addition to the value.
+pub(crate) const NAME: &str = "$__slr_config_private_Spanned";
+pub(crate) const START_FIELD: &str = "$__slr_config_private_start";
+pub(crate) const END_FIELD: &str = "$__slr_config_private_end";
+pub(crate) const FILENAME_FIELD: &str = "$__slr_config_private_filename";
+pub(crate) const VALUE_FIELD: &str = "$__slr_config_private_value";
+pub(crate) const FIELDS: &[&str] = &[START_FIELD, END_FIELD, FILENAME_FIELD, VALUE_FIELD];
+
+/// Creates the span sent through the private fields, rejecting one that ends
+/// before it starts.
+pub(crate) fn span_from_start_end<E: de::Error>(start: usize, end: usize) -> Result<Span, E>
+{
+	Span::from_start_end(start, end).ok_or_else(|| {
+		E::custom(format!(
+			"Invalid span: the start ({}) is after the end ({}).",
+			start, end
+		))
+	})
+}
+
+/// A value together with the location of the element it was deserialized
+/// from. This allows reporting errors found after deserialization (e.g. during
+/// validation) with the same quality as the errors produced during
+/// deserialization.
+///
+/// `Spanned` can only be deserialized using `from_element`. It is serialized
+/// as the contained value.
+#[derive(Clone, Debug)]
+pub struct Spanned<T>
+{
+	value: T,
+	span: Span,
+	filename: Option<PathBuf>,
+}
+
+impl<T> Spanned<T>
+{
+	/// Creates a new spanned value with an invalid span and no filename.
+	pub fn new(value: T) -> Spanned<T>
+	{
+		Spanned {
+			value,
+			span: Span::new(),
+			filename: None,
+		}
+	}
+
+	/// Returns the span of the element this value was deserialized from.
+	pub fn span(&self) -> Span
+	{
+		self.span
+	}
+
+	/// Returns the filename of the source this value was deserialized from,
+	/// if a source was passed to `from_element`.
+	pub fn filename(&self) -> Option<&Path>
+	{
+		self.filename.as_deref()
+	}
+
+	/// Returns a reference to the contained value.
+	pub fn get_ref(&self) -> &T
+	{
+		&self.value
+	}
+
+	/// Returns a mutable reference to the contained value.
+	pub fn get_mut(&mut self) -> &mut T
+	{
+		&mut self.value
+	}
+
+	/// Returns the contained value.
+	pub fn into_inner(self) -> T
+	{
+		self.value
+	}
+
+	/// Creates an error pointing to the element this value was deserialized
+	/// from. The source argument, if set, must be set to the source that was
+	/// used when deserializing this value. If it is not set, the error will
+	/// still mention the filename.
+	pub fn error(&self, source: Option<&Source>, kind: ErrorKind, msg: &str) -> Error
+	{
+		match (source, &self.filename)
+		{
+			(None, Some(filename)) =>
+			{
+				Error::new(kind, format!("{}: error: {}\n", filename.display(), msg))
+			}
+			_ => Error::from_span(self.span, source, kind, msg),
+		}
+	}
+}
+
+impl<T: PartialEq> PartialEq for Spanned<T>
+{
+	fn eq(&self, other: &Spanned<T>) -> bool
+	{
+		self.value == other.value
+	}
+}
+
+impl<T: Serialize> Serialize for Spanned<T>
+{
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		self.value.serialize(serializer)
+	}
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Spanned<T>
+{
+	fn deserialize<D>(deserializer: D) -> Result<Spanned<T>, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		deserializer.deserialize_struct(NAME, FIELDS, SpannedVisitor(PhantomData))
+	}
+}
+
+struct SpannedVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for SpannedVisitor<T>
+{
+	type Value = Spanned<T>;
+
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+	{
+		formatter.write_str("a spanned value")
+	}
+
+	fn visit_map<A>(self, mut map: A) -> Result<Spanned<T>, A::Error>
+	where
+		A: MapAccess<'de>,
+	{
+		let mut start = None;
+		let mut end = None;
+		let mut filename = None;
+		let mut value = None;
+		while let Some(key) = map.next_key::<String>()?
+		{
+			match &key[..]
+			{
+				START_FIELD => start = Some(map.next_value()?),
+				END_FIELD => end = Some(map.next_value()?),
+				FILENAME_FIELD => filename = Some(map.next_value::<String>()?),
+				VALUE_FIELD => value = Some(map.next_value()?),
+				_ =>
+				{
+					return Err(de::Error::custom(
+						"Spanned can only be deserialized from a ConfigElement.",
+					))
+				}
+			}
+		}
+		match (start, end, value)
+		{
+			(Some(start), Some(end), Some(value)) => Ok(Spanned {
+				value,
+				span: span_from_start_end(start, end)?,
+				filename: filename.map(PathBuf::from),
+			}),
+			_ => Err(de::Error::custom(
+				"Spanned can only be deserialized from a ConfigElement.",
+			)),
+		}
+	}
+}
@@ -0,0 +1,592 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+use crate::config_element::{ConfigElement, ConfigElementVisitor};
+use crate::de::{locate_error, Deserializer, DeserializerOptions, HackStringDeserializer};
+use crate::element_serde;
+use crate::ser::OptionEncoding;
+use crate::spanned;
+use serde::de::{self, DeserializeOwned, Visitor};
+use slr_parser::{
+	ConfigString, Error, ErrorKind, Event, EventParser, Lexer, Source, Span, TokenKind,
+	Visitor as _,
+};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// Deserialize a value directly from a source, without building a
+/// ConfigElement first. The value must deserialize from a table (e.g. a struct
+/// or a map).
+///
+/// The result is the same as deserializing the result of
+/// `ConfigElement::from_source` with `from_element`, with the following
+/// differences:
+///
+/// - Elements are only kept in memory if they are referenced by a `$`
+///   expansion, or if the type being deserialized needs to see the whole
+///   element at once (e.g. enums, tuples and `Spanned`).
+/// - A key that appears twice in a table is passed to the deserializer twice,
+///   instead of the latter value replacing the former. For structs this
+///   results in a duplicate field error pointing at the second key.
+/// - Errors are reported in the order they are encountered, so a
+///   deserialization error may be reported instead of a parse error later in
+///   the source.
+pub fn from_source<'src, T>(source: &mut Source<'src>) -> Result<T, Error>
+where
+	T: DeserializeOwned,
+{
+	from_source_with_options(source, DeserializerOptions::new())
+}
+
+/// Deserialize a value directly from a source, using the specified options.
+/// See `from_source` for details.
+pub fn from_source_with_options<'src, T>(
+	source: &mut Source<'src>, options: DeserializerOptions,
+) -> Result<T, Error>
+where
+	T: DeserializeOwned,
+{
+	let referenced = referenced_names(source);
+	// The names are collected by lexing the entire source, so it can now be
+	// used to report errors anywhere in it, while a copy is being parsed.
+	let mut parse_source = source.clone();
+	let mut state = State {
+		parser: EventParser::new(&mut parse_source),
+		peeked: None,
+		scope: ConfigElementVisitor::new(ConfigElement::new_table()),
+		keep_children: vec![false],
+		referenced_indices: referenced
+			.iter()
+			.filter_map(|name| usize::from_str(name).ok())
+			.collect(),
+		referenced,
+		source,
+		options,
+	};
+	T::deserialize(ElementDeserializer {
+		state: &mut state,
+		is_root: true,
+	})
+}
+
+/// Returns the names used in `$` expansions. Lexing errors are ignored, the
+/// parser will report them.
+fn referenced_names(source: &mut Source) -> HashSet<String>
+{
+	let mut names = HashSet::new();
+	let mut lexer = Lexer::new(source);
+	let mut after_dollar = false;
+	while let Some(Ok(token)) = lexer.next()
+	{
+		if after_dollar && token.kind.is_string()
+		{
+			names.insert(ConfigString::from_token(token).to_string());
+		}
+		after_dollar = token.kind == TokenKind::Dollar;
+	}
+	names
+}
+
+struct State<'a, 'p, 'src>
+{
+	parser: EventParser<'p, 'src>,
+	peeked: Option<Event<'src>>,
+	/// Builds the elements that might be needed later. Elements that are not
+	/// needed are dropped from their parents once they end.
+	scope: ConfigElementVisitor,
+	/// For each element being built, whether its children are kept.
+	keep_children: Vec<bool>,
+	referenced: HashSet<String>,
+	referenced_indices: HashSet<usize>,
+	source: &'a Source<'src>,
+	options: DeserializerOptions,
+}
+
+impl<'a, 'p, 'src> State<'a, 'p, 'src>
+{
+	fn peek(&mut self) -> Result<Option<Event<'src>>, Error>
+	{
+		if self.peeked.is_none()
+		{
+			self.peeked = self.parser.next_event()?;
+		}
+		Ok(self.peeked)
+	}
+
+	/// Consumes the next event. If it ends an element, the element is not
+	/// returned.
+	fn next(&mut self) -> Result<Option<Event<'src>>, Error>
+	{
+		let event = match self.peeked.take()
+		{
+			Some(event) => Some(event),
+			None => self.parser.next_event()?,
+		};
+		let src = self.parser.get_source();
+		match event
+		{
+			Some(Event::StartElement(name)) =>
+			{
+				let parent = self.scope.top();
+				let referenced = match parent.as_array()
+				{
+					Some(array) => self.referenced_indices.contains(&array.len()),
+					None => self.referenced.contains(&name.to_string()),
+				};
+				let keep_children = *self.keep_children.last().unwrap() || referenced;
+				self.keep_children.push(keep_children);
+				self.scope.start_element(src, name)?;
+			}
+			Some(Event::EndElement(extent)) =>
+			{
+				self.end_element(extent, false);
+			}
+			Some(Event::Table(span)) => self.scope.set_table(src, span)?,
+			Some(Event::TaggedTable(span, tag)) => self.scope.set_tagged_table(src, span, tag)?,
+			Some(Event::Array(span)) => self.scope.set_array(src, span)?,
+			Some(Event::TaggedArray(span, tag)) => self.scope.set_tagged_array(src, span, tag)?,
+			Some(Event::String(string)) => self.scope.append_string(src, string)?,
+			Some(Event::Expand(name)) => self.scope.expand(src, name)?,
+			None => (),
+		}
+		Ok(event)
+	}
+
+	/// Finishes the current element, inserting it into its parent if it might
+	/// be needed later. Returns the element if `want` is set.
+	fn end_element(&mut self, extent: Span, want: bool) -> Option<ConfigElement>
+	{
+		let (name, elem) = self.scope.pop_element(extent);
+		self.keep_children.pop();
+		let parent_keeps_children = *self.keep_children.last().unwrap();
+		let parent = self.scope.top_mut();
+		let is_array = parent.as_array().is_some();
+		let referenced = match parent.as_array()
+		{
+			Some(array) => self.referenced_indices.contains(&array.len()),
+			None => self.referenced.contains(&name),
+		};
+		if parent_keeps_children || referenced
+		{
+			if want
+			{
+				parent.insert(name, elem.clone());
+				return Some(elem);
+			}
+			parent.insert(name, elem);
+			return None;
+		}
+		if is_array
+		{
+			// Keep the indices of the later elements intact.
+			parent.insert(name, ConfigElement::new_value(""));
+		}
+		if want
+		{
+			Some(elem)
+		}
+		else
+		{
+			None
+		}
+	}
+}
+
+/// The contents of an element, as determined from its first event.
+enum Head
+{
+	/// A table, with a flag whether it is tagged.
+	Table(bool),
+	Array,
+	Value,
+}
+
+/// A deserializer of the element whose start was just consumed.
+struct ElementDeserializer<'b, 'a, 'p, 'src>
+{
+	state: &'b mut State<'a, 'p, 'src>,
+	is_root: bool,
+}
+
+impl<'b, 'a, 'p, 'src> ElementDeserializer<'b, 'a, 'p, 'src>
+{
+	/// Deserializes `seed` from this element, giving errors without a
+	/// location the span of the element. See `Deserializer::deserialize_seed`.
+	fn deserialize_seed<T>(self, seed: T) -> Result<T::Value, Error>
+	where
+		T: de::DeserializeSeed<'a>,
+	{
+		let span = match self.state.peek()?
+		{
+			Some(Event::Table(span))
+			| Some(Event::TaggedTable(span, _))
+			| Some(Event::Array(span))
+			| Some(Event::TaggedArray(span, _)) => span,
+			Some(Event::String(string)) | Some(Event::Expand(string)) => string.span,
+			_ => Span::new(),
+		};
+		let source = self.state.source;
+		seed.deserialize(self)
+			.map_err(|e| locate_error(e, span, Some(source)))
+	}
+
+	fn head(&mut self) -> Result<Head, Error>
+	{
+		if self.is_root
+		{
+			return Ok(Head::Table(false));
+		}
+		Ok(match self.state.peek()?
+		{
+			Some(Event::Table(_)) => Head::Table(false),
+			Some(Event::TaggedTable(_, _)) => Head::Table(true),
+			Some(Event::Array(_)) | Some(Event::TaggedArray(_, _)) => Head::Array,
+			_ => Head::Value,
+		})
+	}
+
+	/// Consumes the events of this element, up to its end.
+	fn skip(&mut self) -> Result<(), Error>
+	{
+		let mut depth = 0;
+		loop
+		{
+			match self.state.peek()?
+			{
+				None => return Ok(()),
+				Some(Event::EndElement(_)) if depth == 0 => return Ok(()),
+				Some(Event::StartElement(_)) => depth += 1,
+				Some(Event::EndElement(_)) => depth -= 1,
+				_ => (),
+			}
+			self.state.next()?;
+		}
+	}
+
+	/// Consumes the end of this element, returning the element if `want` is
+	/// set.
+	fn end(self, want: bool) -> Option<ConfigElement>
+	{
+		if self.is_root
+		{
+			if want
+			{
+				Some(self.state.scope.pop_element(Span::new()).1)
+			}
+			else
+			{
+				None
+			}
+		}
+		else
+		{
+			let extent = match self.state.peeked.take()
+			{
+				Some(Event::EndElement(extent)) => extent,
+				_ => Span::new(),
+			};
+			self.state.end_element(extent, want)
+		}
+	}
+
+	/// Consumes the rest of this element after its contents were streamed.
+	fn finish(mut self) -> Result<(), Error>
+	{
+		self.skip()?;
+		self.end(false);
+		Ok(())
+	}
+
+	/// Builds this element in full, and returns a deserializer for it.
+	fn buffer(mut self) -> Result<Deserializer<'a, 'src>, Error>
+	{
+		*self.state.keep_children.last_mut().unwrap() = true;
+		self.skip()?;
+		let source = self.state.source;
+		let options = self.state.options;
+		let elem = self.end(true).unwrap();
+		Ok(Deserializer::new_owned(elem, Some(source)).with_options(options))
+	}
+
+	fn visit_table<V>(
+		self, fields: Option<&'static [&'static str]>, typed_keys: bool, visitor: V,
+	) -> Result<V::Value, Error>
+	where
+		V: Visitor<'a>,
+	{
+		if !self.is_root
+		{
+			self.state.next()?;
+		}
+		let value = visitor.visit_map(TableHelper {
+			state: &mut *self.state,
+			fields,
+			typed_keys,
+			seen: HashSet::new(),
+		})?;
+		self.finish()?;
+		Ok(value)
+	}
+
+	fn visit_array<V>(self, visitor: V) -> Result<V::Value, Error>
+	where
+		V: Visitor<'a>,
+	{
+		self.state.next()?;
+		let value = visitor.visit_seq(ArrayHelper {
+			state: &mut *self.state,
+		})?;
+		self.finish()?;
+		Ok(value)
+	}
+}
+
+struct TableHelper<'b, 'a, 'p, 'src>
+{
+	state: &'b mut State<'a, 'p, 'src>,
+	/// If set, entries with keys not in this list are skipped.
+	fields: Option<&'static [&'static str]>,
+	/// If set, keys are deserialized like values, see `MapHelper`.
+	typed_keys: bool,
+	/// The fields seen so far, to detect duplicates.
+	seen: HashSet<String>,
+}
+
+impl<'b, 'a, 'p, 'src> de::MapAccess<'a> for TableHelper<'b, 'a, 'p, 'src>
+{
+	type Error = Error;
+
+	fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+	where
+		K: de::DeserializeSeed<'a>,
+	{
+		loop
+		{
+			let name = match self.state.peek()?
+			{
+				Some(Event::StartElement(name)) => name,
+				_ => return Ok(None),
+			};
+			let key_span = name.span;
+			let name = name.to_string();
+			self.state.next()?;
+			let element = ElementDeserializer {
+				state: &mut *self.state,
+				is_root: false,
+			};
+			let wanted = match self.fields
+			{
+				Some(fields) => fields.contains(&&name[..]),
+				None => true,
+			};
+			if !wanted
+			{
+				element.finish()?;
+			}
+			else if self.fields.is_some() && !self.seen.insert(name.clone())
+			{
+				return Err(Error::from_span(
+					key_span,
+					Some(self.state.source),
+					ErrorKind::InvalidRepr,
+					&format!("Duplicate field `{}`.", name),
+				));
+			}
+			else if self.typed_keys
+			{
+				let key = ConfigElement::new_value(name).with_span(key_span);
+				return seed
+					.deserialize(
+						Deserializer::new_owned(key, Some(self.state.source))
+							.with_options(self.state.options),
+					)
+					.map(Some);
+			}
+			else
+			{
+				return seed
+					.deserialize(HackStringDeserializer::new(Cow::Owned(name)))
+					.map(Some);
+			}
+		}
+	}
+
+	fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+	where
+		V: de::DeserializeSeed<'a>,
+	{
+		ElementDeserializer {
+			state: &mut *self.state,
+			is_root: false,
+		}
+		.deserialize_seed(seed)
+	}
+}
+
+struct ArrayHelper<'b, 'a, 'p, 'src>
+{
+	state: &'b mut State<'a, 'p, 'src>,
+}
+
+impl<'b, 'a, 'p, 'src> de::SeqAccess<'a> for ArrayHelper<'b, 'a, 'p, 'src>
+{
+	type Error = Error;
+
+	fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+	where
+		T: de::DeserializeSeed<'a>,
+	{
+		match self.state.peek()?
+		{
+			Some(Event::StartElement(_)) =>
+			{
+				self.state.next()?;
+				ElementDeserializer {
+					state: &mut *self.state,
+					is_root: false,
+				}
+				.deserialize_seed(seed)
+				.map(Some)
+			}
+			_ => Ok(None),
+		}
+	}
+}
+
+macro_rules! forward_to_buffer {
+	($($method: ident($($arg: ident: $ty: ty),*))*) => {
+		$(
+			fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Error>
+			where
+				V: Visitor<'a>,
+			{
+				self.buffer()?.$method($($arg,)* visitor)
+			}
+		)*
+	};
+}
+
+impl<'b, 'a, 'p, 'src> de::Deserializer<'a> for ElementDeserializer<'b, 'a, 'p, 'src>
+{
+	type Error = Error;
+
+	fn deserialize_any<V>(mut self, visitor: V) -> Result<V::Value, Error>
+	where
+		V: Visitor<'a>,
+	{
+		match self.head()?
+		{
+			Head::Table(_) => self.visit_table(None, false, visitor),
+			Head::Array => self.visit_array(visitor),
+			Head::Value => self.buffer()?.deserialize_any(visitor),
+		}
+	}
+
+	fn deserialize_option<V>(mut self, visitor: V) -> Result<V::Value, Error>
+	where
+		V: Visitor<'a>,
+	{
+		if self.state.options.option_encoding == OptionEncoding::Tagged
+		{
+			return self.buffer()?.deserialize_option(visitor);
+		}
+		match self.head()?
+		{
+			Head::Value => self.buffer()?.deserialize_option(visitor),
+			_ => visitor.visit_some(self),
+		}
+	}
+
+	fn deserialize_newtype_struct<V>(
+		self, name: &'static str, visitor: V,
+	) -> Result<V::Value, Error>
+	where
+		V: Visitor<'a>,
+	{
+		if self.state.options.transparent_newtypes && name != element_serde::NAME
+		{
+			visitor.visit_newtype_struct(self)
+		}
+		else
+		{
+			self.buffer()?.deserialize_newtype_struct(name, visitor)
+		}
+	}
+
+	fn deserialize_seq<V>(mut self, visitor: V) -> Result<V::Value, Error>
+	where
+		V: Visitor<'a>,
+	{
+		match self.head()?
+		{
+			Head::Array => self.visit_array(visitor),
+			_ => self.buffer()?.deserialize_seq(visitor),
+		}
+	}
+
+	fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value, Error>
+	where
+		V: Visitor<'a>,
+	{
+		match self.head()?
+		{
+			Head::Table(_) => self.visit_table(None, true, visitor),
+			_ => self.buffer()?.deserialize_map(visitor),
+		}
+	}
+
+	fn deserialize_struct<V>(
+		mut self, name: &'static str, fields: &'static [&'static str], visitor: V,
+	) -> Result<V::Value, Error>
+	where
+		V: Visitor<'a>,
+	{
+		// Tagged tables are buffered so that the tag can be checked.
+		match self.head()?
+		{
+			Head::Table(false) if name != spanned::NAME =>
+			{
+				self.visit_table(Some(fields), false, visitor)
+			}
+			_ => self.buffer()?.deserialize_struct(name, fields, visitor),
+		}
+	}
+
+	fn deserialize_ignored_any<V>(mut self, visitor: V) -> Result<V::Value, Error>
+	where
+		V: Visitor<'a>,
+	{
+		self.skip()?;
+		self.end(false);
+		visitor.visit_unit()
+	}
+
+	forward_to_buffer! {
+		deserialize_bool()
+		deserialize_i8()
+		deserialize_i16()
+		deserialize_i32()
+		deserialize_i64()
+		deserialize_i128()
+		deserialize_u8()
+		deserialize_u16()
+		deserialize_u32()
+		deserialize_u64()
+		deserialize_u128()
+		deserialize_f32()
+		deserialize_f64()
+		deserialize_char()
+		deserialize_str()
+		deserialize_string()
+		deserialize_bytes()
+		deserialize_byte_buf()
+		deserialize_unit()
+		deserialize_unit_struct(name: &'static str)
+		deserialize_tuple(len: usize)
+		deserialize_tuple_struct(name: &'static str, len: usize)
+		deserialize_enum(name: &'static str, variants: &'static [&'static str])
+		deserialize_identifier()
+	}
+}
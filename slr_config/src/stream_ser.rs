@@ -0,0 +1,749 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+use crate::config_element::ConfigElement;
+use crate::element_serde;
+use crate::ser::{
+	metadata_element, tagged_element, EnumEncoding, MapHelper, OptionEncoding, SerializerOptions,
+};
+use serde::ser::{self, Serialize};
+use slr_parser::{Error, ErrorKind, Printer};
+use std::borrow::Cow;
+use std::io;
+
+/// Serialize a value straight to a writer, without building a ConfigElement
+/// first. The value must serialize to a table (e.g. a struct or a map with
+/// string keys).
+///
+/// The output is the same as printing the result of `to_element`, except that
+/// the layout of arrays is decided by their first element only. Maps are built
+/// in full before being printed, since their encoding (table or key/value
+/// pairs) depends on all of their keys.
+pub fn to_writer<T, W>(value: &T, writer: W) -> Result<(), Error>
+where
+	T: Serialize,
+	W: io::Write,
+{
+	to_writer_with_options(value, writer, SerializerOptions::new())
+}
+
+/// Serialize a value straight to a writer, using the specified options. See
+/// `to_writer` for details.
+pub fn to_writer_with_options<T, W>(
+	value: &T, mut writer: W, options: SerializerOptions,
+) -> Result<(), Error>
+where
+	T: Serialize,
+	W: io::Write,
+{
+	let mut state = State {
+		printer: Printer::new(&mut writer),
+		pending: vec![],
+		options,
+	};
+	value.serialize(Serializer {
+		state: &mut state,
+		name: None,
+		is_root: true,
+	})
+}
+
+type Name = Option<Cow<'static, str>>;
+
+fn root_error() -> Error
+{
+	Error::new(
+		ErrorKind::InvalidRepr,
+		"error: Expected a table.\n".to_string(),
+	)
+}
+
+enum StartKind
+{
+	Table
+	{
+		is_root: bool
+	},
+	Array
+	{
+		multi_line: bool
+	},
+}
+
+/// The start of a table or an array that was not printed yet. The layout of
+/// these depends on their contents, so printing them is delayed until the
+/// first element is printed.
+struct Start
+{
+	name: Name,
+	tag: Option<&'static str>,
+	kind: StartKind,
+}
+
+/// What is about to be printed after the pending starts.
+#[derive(Copy, Clone, PartialEq)]
+enum Next
+{
+	/// Nothing, i.e. the innermost pending table/array is empty.
+	Nothing,
+	/// A non-empty table.
+	Table,
+	/// Anything else.
+	Other,
+}
+
+struct State<'l, W: 'l>
+{
+	printer: Printer<'l, W>,
+	pending: Vec<Start>,
+	options: SerializerOptions,
+}
+
+impl<'l, W: io::Write> State<'l, W>
+{
+	/// Decides whether an array with `len` elements is printed on multiple
+	/// lines, mirroring `SerializerOptions::array_layout`.
+	fn multi_line(&self, len: Option<usize>, default_multi_line: bool) -> bool
+	{
+		match (self.options.multi_line_threshold, len)
+		{
+			(Some(threshold), Some(len)) => len > threshold,
+			_ => default_multi_line,
+		}
+	}
+
+	fn push(&mut self, name: Name, tag: Option<&'static str>, kind: StartKind)
+	{
+		self.pending.push(Start { name, tag, kind });
+	}
+
+	/// Prints all the pending starts.
+	fn flush(&mut self, next: Next) -> Result<(), Error>
+	{
+		let pending: Vec<_> = self.pending.drain(..).collect();
+		let len = pending.len();
+		for (i, start) in pending.iter().enumerate()
+		{
+			let is_empty = i + 1 == len && next == Next::Nothing;
+			let child_is_table = if i + 1 < len
+			{
+				let child_is_empty = i + 2 == len && next == Next::Nothing;
+				matches!(pending[i + 1].kind, StartKind::Table { .. }) && !child_is_empty
+			}
+			else
+			{
+				next == Next::Table
+			};
+			let name = start.name.as_deref();
+			match (&start.kind, start.tag)
+			{
+				(&StartKind::Table { is_root }, None) =>
+				{
+					self.printer.start_table(name, is_root, is_empty)?
+				}
+				(&StartKind::Table { is_root }, Some(tag)) => self
+					.printer
+					.start_tagged_table(name, tag, is_root, is_empty)?,
+				(&StartKind::Array { multi_line }, None) => self
+					.printer
+					.start_array(name, !multi_line && !child_is_table)?,
+				(&StartKind::Array { multi_line }, Some(tag)) =>
+				{
+					self.printer
+						.start_tagged_array(name, tag, !multi_line && !child_is_table)?
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn print_element(&mut self, name: Option<&str>, element: &ConfigElement) -> Result<(), Error>
+	{
+		let next = match element.as_table()
+		{
+			Some(table) if !table.is_empty() => Next::Table,
+			_ => Next::Other,
+		};
+		self.flush(next)?;
+		element.print(name, false, &mut self.printer)?;
+		Ok(())
+	}
+
+	/// Prints an element that was built in full instead of being streamed.
+	fn print_built(
+		&mut self, name: Name, is_root: bool, element: &ConfigElement,
+	) -> Result<(), Error>
+	{
+		if !is_root
+		{
+			return self.print_element(name.as_deref(), element);
+		}
+		if element.as_table().is_none()
+		{
+			return Err(root_error());
+		}
+		element.print(None, true, &mut self.printer)?;
+		Ok(())
+	}
+}
+
+struct Serializer<'s, 'l: 's, W: 'l>
+{
+	state: &'s mut State<'l, W>,
+	name: Name,
+	is_root: bool,
+}
+
+impl<'s, 'l, W: io::Write> Serializer<'s, 'l, W>
+{
+	fn value(self, value: &str) -> Result<(), Error>
+	{
+		if self.is_root
+		{
+			return Err(root_error());
+		}
+		self.state.flush(Next::Other)?;
+		self.state.printer.value(self.name.as_deref(), value)?;
+		Ok(())
+	}
+
+	fn table(self, tag: Option<&'static str>) -> Compound<'s, 'l, W>
+	{
+		let index = self.state.pending.len();
+		self.state.push(
+			self.name,
+			tag,
+			StartKind::Table {
+				is_root: self.is_root,
+			},
+		);
+		Compound {
+			state: self.state,
+			index,
+			is_table: true,
+			is_root: self.is_root,
+			wrapper: None,
+		}
+	}
+
+	fn array(
+		self, tag: Option<&'static str>, multi_line: bool,
+	) -> Result<Compound<'s, 'l, W>, Error>
+	{
+		if self.is_root
+		{
+			return Err(root_error());
+		}
+		let index = self.state.pending.len();
+		self.state
+			.push(self.name, tag, StartKind::Array { multi_line });
+		Ok(Compound {
+			state: self.state,
+			index,
+			is_table: false,
+			is_root: false,
+			wrapper: None,
+		})
+	}
+
+	/// Starts a table with a single entry named after the variant, see
+	/// `EnumEncoding::Table`.
+	fn variant_table(self, variant: &'static str) -> Serializer<'s, 'l, W>
+	{
+		self.state.push(
+			self.name,
+			None,
+			StartKind::Table {
+				is_root: self.is_root,
+			},
+		);
+		Serializer {
+			state: self.state,
+			name: Some(Cow::Borrowed(variant)),
+			is_root: false,
+		}
+	}
+
+	fn struct_tag(&self, name: &'static str) -> Option<&'static str>
+	{
+		if self.state.options.struct_tags
+		{
+			Some(name)
+		}
+		else
+		{
+			None
+		}
+	}
+}
+
+/// Used for everything but maps.
+struct Compound<'s, 'l: 's, W: 'l>
+{
+	state: &'s mut State<'l, W>,
+	/// Index of the start of this compound in the pending starts.
+	index: usize,
+	is_table: bool,
+	is_root: bool,
+	/// If this is inside a single entry table (see `EnumEncoding::Table`),
+	/// whether that table is the root.
+	wrapper: Option<bool>,
+}
+
+impl<'s, 'l, W: io::Write> Compound<'s, 'l, W>
+{
+	fn element<T>(&mut self, name: Name, value: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		value.serialize(Serializer {
+			state: &mut *self.state,
+			name,
+			is_root: false,
+		})
+	}
+
+	fn finish(self) -> Result<(), Error>
+	{
+		if self.state.pending.len() > self.index
+		{
+			self.state.flush(Next::Nothing)?;
+		}
+		if self.is_table
+		{
+			self.state.printer.end_table(self.is_root)?;
+		}
+		else
+		{
+			self.state.printer.end_array()?;
+		}
+		if let Some(is_root) = self.wrapper
+		{
+			self.state.printer.end_table(is_root)?;
+		}
+		Ok(())
+	}
+}
+
+impl<'s, 'l, W: io::Write> ser::SerializeSeq for Compound<'s, 'l, W>
+{
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		self.element(None, value)
+	}
+
+	fn end(self) -> Result<(), Error>
+	{
+		self.finish()
+	}
+}
+
+impl<'s, 'l, W: io::Write> ser::SerializeTuple for Compound<'s, 'l, W>
+{
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		self.element(None, value)
+	}
+
+	fn end(self) -> Result<(), Error>
+	{
+		self.finish()
+	}
+}
+
+impl<'s, 'l, W: io::Write> ser::SerializeTupleStruct for Compound<'s, 'l, W>
+{
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		self.element(None, value)
+	}
+
+	fn end(self) -> Result<(), Error>
+	{
+		self.finish()
+	}
+}
+
+impl<'s, 'l, W: io::Write> ser::SerializeTupleVariant for Compound<'s, 'l, W>
+{
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		self.element(None, value)
+	}
+
+	fn end(self) -> Result<(), Error>
+	{
+		self.finish()
+	}
+}
+
+impl<'s, 'l, W: io::Write> ser::SerializeStruct for Compound<'s, 'l, W>
+{
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		self.element(Some(Cow::Borrowed(key)), value)
+	}
+
+	fn end(self) -> Result<(), Error>
+	{
+		self.finish()
+	}
+}
+
+impl<'s, 'l, W: io::Write> ser::SerializeStructVariant for Compound<'s, 'l, W>
+{
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		self.element(Some(Cow::Borrowed(key)), value)
+	}
+
+	fn end(self) -> Result<(), Error>
+	{
+		self.finish()
+	}
+}
+
+/// Maps are built in full before being printed, since whether they are
+/// serialized as tables or as arrays of key/value pairs depends on all of
+/// their keys, see `ser::MapHelper`.
+struct MapCompound<'s, 'l: 's, W: 'l>
+{
+	state: &'s mut State<'l, W>,
+	name: Name,
+	is_root: bool,
+	helper: MapHelper,
+}
+
+impl<'s, 'l, W: io::Write> ser::SerializeMap for MapCompound<'s, 'l, W>
+{
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		self.helper.serialize_key(key)
+	}
+
+	fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		self.helper.serialize_value(value)
+	}
+
+	fn end(self) -> Result<(), Error>
+	{
+		let element = self.helper.end()?;
+		self.state.print_built(self.name, self.is_root, &element)
+	}
+}
+
+impl<'s, 'l, W: io::Write> ser::Serializer for Serializer<'s, 'l, W>
+{
+	type Ok = ();
+	type Error = Error;
+
+	type SerializeSeq = Compound<'s, 'l, W>;
+	type SerializeTuple = Compound<'s, 'l, W>;
+	type SerializeTupleStruct = Compound<'s, 'l, W>;
+	type SerializeTupleVariant = Compound<'s, 'l, W>;
+	type SerializeMap = MapCompound<'s, 'l, W>;
+	type SerializeStruct = Compound<'s, 'l, W>;
+	type SerializeStructVariant = Compound<'s, 'l, W>;
+
+	fn serialize_str(self, v: &str) -> Result<(), Error>
+	{
+		self.value(v)
+	}
+
+	fn serialize_bool(self, v: bool) -> Result<(), Error>
+	{
+		self.value(if v { "true" } else { "false" })
+	}
+
+	fn serialize_i8(self, v: i8) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_i16(self, v: i16) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_i32(self, v: i32) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_i64(self, v: i64) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_i128(self, v: i128) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_u8(self, v: u8) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_u16(self, v: u16) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_u32(self, v: u32) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_u64(self, v: u64) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_u128(self, v: u128) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_f32(self, v: f32) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_f64(self, v: f64) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_char(self, v: char) -> Result<(), Error>
+	{
+		self.value(&v.to_string())
+	}
+
+	fn serialize_bytes(self, v: &[u8]) -> Result<(), Error>
+	{
+		if let Some(string) = self.state.options.bytes_string(v)
+		{
+			return self.value(&string);
+		}
+		let mut compound = self.array(None, false)?;
+		for e in v
+		{
+			compound.element(None, e)?;
+		}
+		compound.finish()
+	}
+
+	fn serialize_none(self) -> Result<(), Error>
+	{
+		match self.state.options.option_encoding
+		{
+			OptionEncoding::Empty => self.value(""),
+			OptionEncoding::Tagged =>
+			{
+				let multi_line = self.state.multi_line(Some(0), false);
+				self.array(Some("None"), multi_line)?.finish()
+			}
+		}
+	}
+
+	fn serialize_some<T>(self, v: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		match self.state.options.option_encoding
+		{
+			OptionEncoding::Empty => v.serialize(self),
+			OptionEncoding::Tagged =>
+			{
+				let multi_line = self.state.multi_line(Some(1), false);
+				let mut compound = self.array(Some("Some"), multi_line)?;
+				compound.element(None, v)?;
+				compound.finish()
+			}
+		}
+	}
+
+	fn serialize_unit(self) -> Result<(), Error>
+	{
+		self.value("")
+	}
+
+	fn serialize_unit_struct(self, name: &'static str) -> Result<(), Error>
+	{
+		self.value(name)
+	}
+
+	fn serialize_unit_variant(
+		self, _name: &'static str, _index: u32, variant: &'static str,
+	) -> Result<(), Error>
+	{
+		self.value(variant)
+	}
+
+	fn serialize_newtype_struct<T>(self, name: &'static str, v: &T) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		if name == element_serde::TAGGED_NAME || name == element_serde::METADATA_NAME
+		{
+			// These are built in full, since they are printed differently from
+			// how they are serialized.
+			let element = if name == element_serde::TAGGED_NAME
+			{
+				tagged_element(v, self.state.options)?
+			}
+			else
+			{
+				metadata_element(v, self.state.options)?
+			};
+			return self.state.print_built(self.name, self.is_root, &element);
+		}
+		if self.state.options.transparent_newtypes
+		{
+			return v.serialize(self);
+		}
+		let tag = self.struct_tag(name);
+		let multi_line = self.state.multi_line(Some(1), false);
+		let mut compound = self.array(tag, multi_line)?;
+		compound.element(None, v)?;
+		compound.finish()
+	}
+
+	fn serialize_newtype_variant<T>(
+		self, _name: &'static str, _variant_index: u32, variant: &'static str, value: &T,
+	) -> Result<(), Error>
+	where
+		T: ?Sized + Serialize,
+	{
+		match self.state.options.enum_encoding
+		{
+			EnumEncoding::Tagged =>
+			{
+				let multi_line = self.state.multi_line(Some(1), false);
+				let mut compound = self.array(Some(variant), multi_line)?;
+				compound.element(None, value)?;
+				compound.finish()
+			}
+			EnumEncoding::Table =>
+			{
+				let mut compound = self.table(None);
+				compound.element(Some(Cow::Borrowed(variant)), value)?;
+				compound.finish()
+			}
+		}
+	}
+
+	fn serialize_seq(self, len: Option<usize>) -> Result<Compound<'s, 'l, W>, Error>
+	{
+		let multi_line = self.state.multi_line(len, false);
+		self.array(None, multi_line)
+	}
+
+	fn serialize_tuple(self, len: usize) -> Result<Compound<'s, 'l, W>, Error>
+	{
+		let multi_line = self.state.multi_line(Some(len), false);
+		self.array(None, multi_line)
+	}
+
+	fn serialize_tuple_struct(
+		self, name: &'static str, len: usize,
+	) -> Result<Compound<'s, 'l, W>, Error>
+	{
+		let tag = self.struct_tag(name);
+		let multi_line = self.state.multi_line(Some(len), true);
+		self.array(tag, multi_line)
+	}
+
+	fn serialize_tuple_variant(
+		self, _name: &'static str, _variant_index: u32, variant: &'static str, len: usize,
+	) -> Result<Compound<'s, 'l, W>, Error>
+	{
+		let multi_line = self.state.multi_line(Some(len), false);
+		match self.state.options.enum_encoding
+		{
+			EnumEncoding::Tagged => self.array(Some(variant), multi_line),
+			EnumEncoding::Table =>
+			{
+				let is_root = self.is_root;
+				let mut compound = self.variant_table(variant).array(None, multi_line)?;
+				compound.wrapper = Some(is_root);
+				Ok(compound)
+			}
+		}
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> Result<MapCompound<'s, 'l, W>, Error>
+	{
+		let options = self.state.options;
+		Ok(MapCompound {
+			state: self.state,
+			name: self.name,
+			is_root: self.is_root,
+			helper: MapHelper::new(options),
+		})
+	}
+
+	fn serialize_struct(self, name: &'static str, _len: usize)
+		-> Result<Compound<'s, 'l, W>, Error>
+	{
+		let tag = self.struct_tag(name);
+		Ok(self.table(tag))
+	}
+
+	fn serialize_struct_variant(
+		self, _name: &'static str, _variant_index: u32, variant: &'static str, _len: usize,
+	) -> Result<Compound<'s, 'l, W>, Error>
+	{
+		match self.state.options.enum_encoding
+		{
+			EnumEncoding::Tagged => Ok(self.table(Some(variant))),
+			EnumEncoding::Table =>
+			{
+				let is_root = self.is_root;
+				let mut compound = self.variant_table(variant).table(None);
+				compound.wrapper = Some(is_root);
+				Ok(compound)
+			}
+		}
+	}
+}
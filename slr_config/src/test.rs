@@ -3,9 +3,20 @@
 // All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
 
 use crate::config_element::*;
-use crate::de::from_element;
-use crate::ser::to_element;
+use crate::convert::{from_element_into, FromElement, ToElement};
+use crate::de::{
+	from_element, from_element_with_options, from_file, from_owned_element, from_reader, from_str,
+	DeserializerOptions,
+};
+use crate::path::{ElementPath, PathSegment};
+use crate::ser::{
+	to_element, to_element_documented, to_element_with_options, to_string, BytesEncoding,
+	EnumEncoding, OptionEncoding, SerializerOptions,
+};
+use crate::stream_de::from_source;
+use crate::stream_ser::{to_writer, to_writer_with_options};
 use serde_derive::{Deserialize, Serialize};
+use slr_config_derive::{FromElement, SlrDocumented, ToElement};
 use std::char;
 
 #[test]
@@ -286,3 +297,2121 @@ fn serde_test()
 
 	assert_eq!(v, v2);
 }
+
+#[test]
+fn spanned_test()
+{
+	use crate::spanned::Spanned;
+	use slr_parser::{ErrorKind, Source};
+	use std::path::Path;
+
+	#[derive(Deserialize)]
+	struct Server
+	{
+		name: Spanned<String>,
+		port: Spanned<u16>,
+	}
+
+	let src_str = "name = main\nport = 8080\n";
+	let mut src = Source::new(Path::new("server.slr"), src_str);
+	let elem = ConfigElement::from_source(&mut src).unwrap();
+	let server: Server = from_element(&elem, Some(&src)).unwrap();
+
+	assert_eq!(server.name.get_ref(), "main");
+	assert_eq!(*server.port.get_ref(), 8080);
+	assert_eq!(server.port.span().start(), 19);
+	assert_eq!(server.port.span().end(), 23);
+	assert_eq!(server.port.filename(), Some(Path::new("server.slr")));
+
+	let err = server
+		.port
+		.error(Some(&src), ErrorKind::Custom(0), "Port already in use");
+	assert_eq!(
+		err.text,
+		"server.slr:2:7-2:10: error: Port already in use\nport = 8080\n       ^~~~\n"
+	);
+	let err = server
+		.port
+		.error(None, ErrorKind::Custom(0), "Port already in use");
+	assert_eq!(err.text, "server.slr: error: Port already in use\n");
+
+	let server: Server = from_element(&elem, None).unwrap();
+	assert_eq!(server.port.filename(), None);
+	assert_eq!(server.port.span().start(), 19);
+
+	// Spans from other deserializers are checked.
+	use crate::spanned::{END_FIELD, START_FIELD, VALUE_FIELD};
+	use serde::de::value::{Error as ValueError, MapDeserializer};
+	use serde::Deserialize;
+	let fields = vec![(START_FIELD, 5usize), (END_FIELD, 3), (VALUE_FIELD, 1)];
+	let err =
+		Spanned::<usize>::deserialize(MapDeserializer::<_, ValueError>::new(fields.into_iter()))
+			.err()
+			.unwrap();
+	assert_eq!(
+		err.to_string(),
+		"Invalid span: the start (5) is after the end (3)."
+	);
+}
+
+#[test]
+fn from_element_into_test()
+{
+	use slr_parser::Source;
+	use std::path::Path;
+
+	#[derive(FromElement, PartialEq, Debug)]
+	struct Window
+	{
+		width: u32,
+		height: u32,
+	}
+
+	#[derive(FromElement, PartialEq, Debug)]
+	struct Config
+	{
+		title: String,
+		window: Window,
+		popup: Option<Window>,
+		plugins: Vec<String>,
+		note: Option<String>,
+		#[slr(skip)]
+		runs: u32,
+	}
+
+	let mut config = Config {
+		title: "Game".to_string(),
+		window: Window {
+			width: 800,
+			height: 600,
+		},
+		popup: Some(Window {
+			width: 200,
+			height: 100,
+		}),
+		plugins: vec!["a".to_string(), "b".to_string()],
+		note: Some(String::new()),
+		runs: 7,
+	};
+
+	let elem = ConfigElement::from_str(
+		"window { height = 768 }, popup { width = 300 }, plugins = [c], runs = 1",
+	)
+	.unwrap();
+	from_element_into(&mut config, &elem, None).unwrap();
+	assert_eq!(
+		config,
+		Config {
+			title: "Game".to_string(),
+			window: Window {
+				width: 800,
+				height: 768,
+			},
+			popup: Some(Window {
+				width: 300,
+				height: 100,
+			}),
+			plugins: vec!["c".to_string()],
+			note: Some(String::new()),
+			runs: 7,
+		}
+	);
+
+	// An empty value still clears an option.
+	let mut elem = ConfigElement::new_table();
+	elem.insert("popup", ConfigElement::new_value(""));
+	from_element_into(&mut config, &elem, None).unwrap();
+	assert_eq!(config.popup, None);
+
+	// Fields read before an error keep their new values.
+	let src_str = "title = Other\nwindow { width = wide }";
+	let mut src = Source::new(Path::new("config.slr"), src_str);
+	let elem = ConfigElement::from_source(&mut src).unwrap();
+	let err = from_element_into(&mut config, &elem, Some(&src)).unwrap_err();
+	assert!(err.text.starts_with("config.slr:2:17-2:20: error:"));
+	assert_eq!(config.title, "Other");
+	assert_eq!(config.window.width, 800);
+
+	let elem = ConfigElement::from_str("window = [1, 2]").unwrap();
+	let err = from_element_into(&mut config, &elem, None).unwrap_err();
+	assert!(err.text.contains("Expected a table."));
+}
+
+#[test]
+fn owned_element_test()
+{
+	use serde::de::{Deserialize, IntoDeserializer};
+	use slr_parser::Source;
+	use std::path::Path;
+
+	#[derive(Deserialize, PartialEq, Debug)]
+	enum Shape
+	{
+		Circle(f32),
+		Rect
+		{
+			w: f32,
+			h: f32,
+		},
+	}
+
+	#[derive(Deserialize, PartialEq, Debug)]
+	struct Scene
+	{
+		name: String,
+		tags: Vec<String>,
+		shapes: Vec<Shape>,
+	}
+
+	let src_str = "name = Test\ntags = [a, b]\nshapes = [Circle [1.5], Rect { w = 1, h = 2 }]";
+	let elem = ConfigElement::from_str(src_str).unwrap();
+	let borrowed: Scene = from_element(&elem, None).unwrap();
+	let owned: Scene = from_owned_element(elem.clone(), None).unwrap();
+	assert_eq!(borrowed, owned);
+	assert_eq!(
+		owned.shapes,
+		vec![Shape::Circle(1.5), Shape::Rect { w: 1., h: 2. }]
+	);
+
+	let via_into = Scene::deserialize(elem.into_deserializer()).unwrap();
+	assert_eq!(borrowed, via_into);
+
+	let src_str = "name = Test\ntags = [a, b]\nshapes = [Circle [big]]";
+	let mut src = Source::new(Path::new("scene.slr"), src_str);
+	let elem = ConfigElement::from_source(&mut src).unwrap();
+	let borrowed_err = from_element::<Scene>(&elem, Some(&src)).unwrap_err();
+	let owned_err = from_owned_element::<Scene>(elem, Some(&src)).unwrap_err();
+	assert_eq!(borrowed_err.text, owned_err.text);
+	assert!(owned_err.text.starts_with("scene.slr:3:18-3:20: error:"));
+}
+
+#[test]
+fn enum_representation_test()
+{
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	#[serde(tag = "type")]
+	enum Internal
+	{
+		Circle
+		{
+			radius: String,
+		},
+		Rect
+		{
+			w: String,
+			h: String,
+			name: String,
+		},
+		Empty,
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	#[serde(tag = "t", content = "c")]
+	enum Adjacent
+	{
+		Scale(String),
+		Move(String, String),
+		Named
+		{
+			name: String,
+		},
+		Stop,
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	#[serde(untagged)]
+	enum Untagged
+	{
+		Text(String),
+		List(Vec<Untagged>),
+		Point
+		{
+			x: String,
+			y: String,
+		},
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Config
+	{
+		shapes: Vec<Internal>,
+		actions: Vec<Adjacent>,
+		values: Vec<Untagged>,
+	}
+
+	let elem = ConfigElement::from_str(
+		r#"
+		shapes = [{type = Circle, radius = 1.5}, {type = Rect, w = 2, h = 3, name = box}, {type = Empty}]
+		actions = [{t = Scale, c = 2}, {c = [1, "-2"], t = Move}, {t = Named, c {name = a}}, {t = Stop}]
+		values = [true, 10, "hello world", [1, text], {x = 1, y = 2.5}]
+		"#,
+	)
+	.unwrap();
+	let config: Config = from_element(&elem, None).unwrap();
+	let expected = Config {
+		shapes: vec![
+			Internal::Circle {
+				radius: "1.5".to_string(),
+			},
+			Internal::Rect {
+				w: "2".to_string(),
+				h: "3".to_string(),
+				name: "box".to_string(),
+			},
+			Internal::Empty,
+		],
+		actions: vec![
+			Adjacent::Scale("2".to_string()),
+			Adjacent::Move("1".to_string(), "-2".to_string()),
+			Adjacent::Named {
+				name: "a".to_string(),
+			},
+			Adjacent::Stop,
+		],
+		values: vec![
+			Untagged::Text("true".to_string()),
+			Untagged::Text("10".to_string()),
+			Untagged::Text("hello world".to_string()),
+			Untagged::List(vec![
+				Untagged::Text("1".to_string()),
+				Untagged::Text("text".to_string()),
+			]),
+			Untagged::Point {
+				x: "1".to_string(),
+				y: "2.5".to_string(),
+			},
+		],
+	};
+	assert_eq!(config, expected);
+
+	let elem = to_element(&expected).unwrap();
+	let config: Config = from_element(&elem, None).unwrap();
+	assert_eq!(config, expected);
+	let config: Config = from_owned_element(elem, None).unwrap();
+	assert_eq!(config, expected);
+}
+
+#[test]
+fn enum_string_value_test()
+{
+	use slr_parser::Source;
+	use std::path::Path;
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	#[serde(tag = "type")]
+	enum Tagged
+	{
+		Named
+		{
+			name: String
+		},
+		Counted
+		{
+			count: u32
+		},
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	#[serde(untagged)]
+	enum Untagged
+	{
+		Text(String),
+		Pair
+		{
+			first: String,
+			second: String,
+		},
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Config
+	{
+		tagged: Vec<Tagged>,
+		untagged: Vec<Untagged>,
+	}
+
+	// Values that look like numbers or booleans are still strings.
+	let names = ["007", "1.0", "123", "true"];
+	let expected = Config {
+		tagged: names
+			.iter()
+			.map(|name| Tagged::Named {
+				name: name.to_string(),
+			})
+			.collect(),
+		untagged: names
+			.iter()
+			.map(|name| Untagged::Text(name.to_string()))
+			.chain(Some(Untagged::Pair {
+				first: "-0".to_string(),
+				second: "false".to_string(),
+			}))
+			.collect(),
+	};
+
+	let elem = to_element(&expected).unwrap();
+	let config: Config = from_element(&elem, None).unwrap();
+	assert_eq!(config, expected);
+	let config: Config = from_owned_element(elem.clone(), None).unwrap();
+	assert_eq!(config, expected);
+	let src_str = elem.to_string();
+	let mut src = Source::new(Path::new("config.slr"), &src_str);
+	let config: Config = from_source(&mut src).unwrap();
+	assert_eq!(config, expected);
+
+	// Errors reported once the buffered contents are read point at the
+	// element.
+	let src_str = "tagged = [{type = Named, name = a}, {type = Counted, count = 5}]\nuntagged = []";
+	let mut src = Source::new(Path::new("config.slr"), src_str);
+	let elem = ConfigElement::from_source(&mut src).unwrap();
+	let err = from_element::<Config>(&elem, Some(&src)).unwrap_err();
+	assert!(err
+		.text
+		.starts_with("config.slr:1:36-1:36: error: invalid type: string \"5\", expected u32"));
+	let mut src = Source::new(Path::new("config.slr"), src_str);
+	let stream_err = from_source::<Config>(&mut src).unwrap_err();
+	assert_eq!(stream_err.text, err.text);
+}
+
+#[test]
+fn integer_literal_test()
+{
+	use slr_parser::Source;
+	use std::path::Path;
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Numbers
+	{
+		big: u64,
+		hex: u32,
+		oct: u16,
+		bin: u8,
+		neg_hex: i8,
+		plus: i32,
+		wide: i128,
+		uwide: u128,
+	}
+
+	let elem = ConfigElement::from_str(
+		r#"big = 1_000_000, hex = 0xff_ff, oct = 0o755, bin = 0b1010_1010,
+		neg_hex = "-0x80", plus = "+42", wide = "-170141183460469231731687303715884105728",
+		uwide = 340_282_366_920_938_463_463_374_607_431_768_211_455"#,
+	)
+	.unwrap();
+	let numbers: Numbers = from_element(&elem, None).unwrap();
+	let expected = Numbers {
+		big: 1_000_000,
+		hex: 0xffff,
+		oct: 0o755,
+		bin: 0b1010_1010,
+		neg_hex: -0x80,
+		plus: 42,
+		wide: i128::MIN,
+		uwide: u128::MAX,
+	};
+	assert_eq!(numbers, expected);
+	let numbers: Numbers = from_element(&to_element(&expected).unwrap(), None).unwrap();
+	assert_eq!(numbers, expected);
+
+	#[derive(Deserialize, Debug)]
+	struct Byte
+	{
+		#[allow(dead_code)]
+		v: u8,
+	}
+
+	for bad in &["_1", "0x", "+-1", "0xfg", "300", "-1"]
+	{
+		let src_str = format!("v = \"{}\"\n", bad);
+		let mut src = Source::new(Path::new("num.slr"), &src_str);
+		let elem = ConfigElement::from_source(&mut src).unwrap();
+		let err = from_element::<Byte>(&elem, Some(&src)).unwrap_err();
+		assert!(err.text.starts_with("num.slr:1:4-1:"), "{}", err.text);
+	}
+}
+
+#[test]
+fn serializer_options_test()
+{
+	#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+	struct Meters(f32);
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+	enum Action
+	{
+		Stop,
+		Scale(f32),
+		Move(i32, i32),
+		Named
+		{
+			name: String,
+		},
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+	struct Inner
+	{
+		a: u32,
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+	struct Config
+	{
+		inner: Inner,
+		distance: Meters,
+		short: Vec<u32>,
+		long: Vec<u32>,
+		actions: Vec<Action>,
+	}
+
+	let config = Config {
+		inner: Inner { a: 1 },
+		distance: Meters(2.5),
+		short: vec![1, 2],
+		long: vec![1, 2, 3, 4],
+		actions: vec![
+			Action::Stop,
+			Action::Scale(2.),
+			Action::Move(1, 2),
+			Action::Named {
+				name: "a".to_string(),
+			},
+		],
+	};
+
+	let options = SerializerOptions::new()
+		.struct_tags(false)
+		.transparent_newtypes(true)
+		.multi_line_threshold(Some(3))
+		.enum_encoding(EnumEncoding::Table);
+	let elem = to_element_with_options(&config, options).unwrap();
+	let table = elem.as_table().unwrap();
+	assert!(elem.tag().is_none());
+	assert!(table["inner"].tag().is_none());
+	assert_eq!(table["distance"].as_value().unwrap(), "2.5");
+	assert_eq!(table["short"].layout(), Layout::Auto);
+	assert_eq!(table["long"].layout(), Layout::MultiLine);
+	let actions = table["actions"].as_array().unwrap();
+	assert_eq!(actions[0].as_value().unwrap(), "Stop");
+	assert_eq!(
+		actions[1].as_table().unwrap()["Scale"].as_value().unwrap(),
+		"2"
+	);
+	assert!(actions[3].as_table().unwrap()["Named"].as_table().is_some());
+
+	// Round trip through text.
+	let elem = ConfigElement::from_str(&elem.to_string()).unwrap();
+	let de_options = DeserializerOptions::new().transparent_newtypes(true);
+	let new_config: Config = from_element_with_options(&elem, None, de_options).unwrap();
+	assert_eq!(new_config, config);
+	assert!(from_element::<Config>(&elem, None).is_err());
+
+	// The default options match to_element.
+	let elem = to_element_with_options(&config, SerializerOptions::new()).unwrap();
+	assert_eq!(elem.to_string(), to_element(&config).unwrap().to_string());
+	assert_eq!(from_element::<Config>(&elem, None).unwrap(), config);
+}
+
+#[test]
+fn map_test()
+{
+	use std::collections::{BTreeMap, HashMap};
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Settings
+	{
+		volume: u32,
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Config
+	{
+		profiles: HashMap<String, Settings>,
+		ids: BTreeMap<u32, String>,
+		pairs: BTreeMap<(u32, u32), String>,
+		empty: BTreeMap<String, u32>,
+	}
+
+	let elem = ConfigElement::from_str(
+		r#"
+		profiles
+		{
+			default { volume = 5 }
+			"quiet mode" { volume = 1 }
+		}
+		ids { 1 = one, 2 = two }
+		pairs = [[[1, 2], a], [[3, 4], b]]
+		empty {}
+		"#,
+	)
+	.unwrap();
+	let config: Config = from_element(&elem, None).unwrap();
+	assert_eq!(config.profiles["default"], Settings { volume: 5 });
+	assert_eq!(config.profiles["quiet mode"], Settings { volume: 1 });
+	assert_eq!(config.ids[&2], "two");
+	assert_eq!(config.pairs[&(3, 4)], "b");
+
+	let elem = to_element(&config).unwrap();
+	let table = elem.as_table().unwrap();
+	assert!(table["profiles"].as_table().is_some());
+	assert_eq!(
+		table["ids"].as_table().unwrap()["1"].as_value().unwrap(),
+		"one"
+	);
+	assert!(table["pairs"].as_array().is_some());
+	assert!(table["empty"].as_table().is_some());
+
+	let elem = ConfigElement::from_str(&elem.to_string()).unwrap();
+	let new_config: Config = from_element(&elem, None).unwrap();
+	assert_eq!(new_config, config);
+
+	#[derive(Deserialize, Debug)]
+	struct Ids
+	{
+		ids: BTreeMap<u32, String>,
+	}
+
+	// Pair arrays are still accepted.
+	let elem = ConfigElement::from_str("ids = [[1, one], [2, two]]").unwrap();
+	let ids: Ids = from_element(&elem, None).unwrap();
+	assert_eq!(ids.ids, config.ids);
+
+	let elem = ConfigElement::from_str("ids { x = one }").unwrap();
+	assert!(from_element::<Ids>(&elem, None).is_err());
+
+	// Distinct keys that serialize to the same value can't share a table.
+	#[derive(PartialEq, Eq, PartialOrd, Ord)]
+	struct Key(u32);
+
+	impl serde::Serialize for Key
+	{
+		fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		{
+			serializer.serialize_str(if self.0 > 0 { "positive" } else { "zero" })
+		}
+	}
+
+	let mut map = BTreeMap::new();
+	map.insert(Key(0), 1);
+	map.insert(Key(1), 2);
+	map.insert(Key(2), 3);
+	let mut root = BTreeMap::new();
+	root.insert("map", map);
+	let elem = to_element(&root).unwrap();
+	assert_eq!(
+		elem.to_string(),
+		"map =\n[\n\t[zero, 1],\n\t[positive, 2],\n\t[positive, 3]\n]\n"
+	);
+	let mut output = vec![];
+	to_writer(&root, &mut output).unwrap();
+	assert_eq!(String::from_utf8(output).unwrap(), elem.to_string());
+
+	// Streaming maps gives the same output as printing `to_element`, even when
+	// only the later keys can't be used in a table.
+	#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+	enum Slot
+	{
+		Any,
+		At(u32, u32),
+	}
+
+	#[derive(Serialize)]
+	struct Maps
+	{
+		slots: BTreeMap<Slot, Vec<u32>>,
+		names: BTreeMap<Key, &'static str>,
+		plain: BTreeMap<&'static str, Option<u32>>,
+		empty: BTreeMap<u32, u32>,
+		nested: Vec<BTreeMap<&'static str, BTreeMap<Key, i32>>>,
+	}
+
+	let mut maps = Maps {
+		slots: BTreeMap::new(),
+		names: BTreeMap::new(),
+		plain: BTreeMap::new(),
+		empty: BTreeMap::new(),
+		nested: vec![root],
+	};
+	maps.slots.insert(Slot::Any, vec![1]);
+	maps.slots.insert(Slot::At(1, 2), vec![2, 3]);
+	maps.names.insert(Key(0), "a");
+	maps.names.insert(Key(3), "b");
+	maps.plain.insert("x y", Some(1));
+	maps.plain.insert("z", None);
+	let options = [
+		SerializerOptions::new(),
+		SerializerOptions::new().multi_line_threshold(Some(1)),
+	];
+	for &options in &options
+	{
+		let mut output = vec![];
+		to_writer_with_options(&maps, &mut output, options).unwrap();
+		assert_eq!(
+			String::from_utf8(output).unwrap(),
+			to_element_with_options(&maps, options).unwrap().to_string()
+		);
+	}
+}
+
+#[test]
+fn to_writer_test()
+{
+	use std::collections::BTreeMap;
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Point(f32, f32);
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Id(u32);
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	enum Shape
+	{
+		Empty,
+		Circle(f32),
+		Line(Point, Point),
+		Rect
+		{
+			w: f32,
+			h: f32,
+		},
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Entity
+	{
+		id: Id,
+		name: String,
+		shape: Shape,
+		tags: Vec<String>,
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Empty {}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Level
+	{
+		title: String,
+		entities: Vec<Entity>,
+		empty: Empty,
+		no_entities: Vec<Entity>,
+		assets: BTreeMap<String, Vec<u8>>,
+		pairs: BTreeMap<(u32, u32), bool>,
+		no_assets: BTreeMap<String, u32>,
+		nested: Vec<Vec<Option<u32>>>,
+	}
+
+	let mut assets = BTreeMap::new();
+	assets.insert("tile.png".to_string(), vec![1, 2, 3]);
+	assets.insert("hero sprite".to_string(), vec![]);
+	let mut pairs = BTreeMap::new();
+	pairs.insert((1, 2), true);
+	let level = Level {
+		title: "The \"first\" level".to_string(),
+		entities: vec![
+			Entity {
+				id: Id(1),
+				name: "player".to_string(),
+				shape: Shape::Rect { w: 1., h: 2. },
+				tags: vec!["hero".to_string(), "blue team".to_string()],
+			},
+			Entity {
+				id: Id(2),
+				name: "rock".to_string(),
+				shape: Shape::Line(Point(0., 0.), Point(1., 1.)),
+				tags: vec![],
+			},
+			Entity {
+				id: Id(3),
+				name: "ball".to_string(),
+				shape: Shape::Circle(0.5),
+				tags: vec![],
+			},
+			Entity {
+				id: Id(4),
+				name: "nothing".to_string(),
+				shape: Shape::Empty,
+				tags: vec![],
+			},
+		],
+		empty: Empty {},
+		no_entities: vec![],
+		assets,
+		pairs,
+		no_assets: BTreeMap::new(),
+		nested: vec![vec![Some(1), None], vec![]],
+	};
+
+	let options = [
+		SerializerOptions::new(),
+		SerializerOptions::new()
+			.struct_tags(false)
+			.transparent_newtypes(true)
+			.multi_line_threshold(Some(2))
+			.enum_encoding(EnumEncoding::Table),
+	];
+	for &options in &options
+	{
+		let mut buf = vec![];
+		to_writer_with_options(&level, &mut buf, options).unwrap();
+		let streamed = String::from_utf8(buf).unwrap();
+		let expected = to_element_with_options(&level, options)
+			.unwrap()
+			.to_string();
+		assert_eq!(streamed, expected);
+	}
+
+	let mut buf = vec![];
+	to_writer(&level, &mut buf).unwrap();
+	let elem = ConfigElement::from_str(std::str::from_utf8(&buf).unwrap()).unwrap();
+	assert_eq!(from_element::<Level>(&elem, None).unwrap(), level);
+
+	assert!(to_writer(&vec![1, 2], &mut vec![]).is_err());
+	assert!(to_writer(&"value", &mut vec![]).is_err());
+}
+
+#[test]
+fn from_source_test()
+{
+	use crate::spanned::Spanned;
+	use slr_parser::Source;
+	use std::collections::BTreeMap;
+	use std::path::Path;
+
+	#[derive(Deserialize, PartialEq, Debug)]
+	enum Shape
+	{
+		Circle(f32),
+		Rect
+		{
+			w: f32,
+			h: f32,
+		},
+	}
+
+	#[derive(Deserialize, PartialEq, Debug)]
+	struct Entity
+	{
+		name: String,
+		pos: (i32, i32),
+		shape: Shape,
+		tags: Option<Vec<String>>,
+	}
+
+	#[derive(Deserialize, Debug)]
+	struct Level
+	{
+		title: String,
+		entities: Vec<Entity>,
+		weights: BTreeMap<u32, f32>,
+		extra: BTreeMap<String, Vec<u32>>,
+		port: Spanned<u16>,
+	}
+
+	let src_str = r#"
+		base = "Level "
+		title = $base ~ 1
+		hero_tags = [hero, "blue team"]
+		ignored = [{a = 1}, $base]
+		entities =
+		[
+			{
+				name = player
+				pos = [1, 2]
+				shape = Circle [0.5]
+				tags = $hero_tags
+			},
+			{
+				name = rock
+				pos = [3, 4]
+				shape = Rect { w = 1, h = 2 }
+			},
+		]
+		entities_copy = $entities
+		weights { 1 = 0.5, 20 = 1 }
+		extra { a = [1, 2], b = [] }
+		port = 8080
+	"#;
+	let mut src = Source::new(Path::new("level.slr"), src_str);
+	let elem = ConfigElement::from_source(&mut src).unwrap();
+	let expected: Level = from_element(&elem, Some(&src)).unwrap();
+	let mut src = Source::new(Path::new("level.slr"), src_str);
+	let level: Level = from_source(&mut src).unwrap();
+
+	assert_eq!(level.title, "Level 1");
+	assert_eq!(level.title, expected.title);
+	assert_eq!(level.entities, expected.entities);
+	assert_eq!(level.weights, expected.weights);
+	assert_eq!(level.extra, expected.extra);
+	assert_eq!(level.port.get_ref(), expected.port.get_ref());
+	assert_eq!(level.port.span(), expected.port.span());
+
+	#[derive(Deserialize, PartialEq, Debug)]
+	struct Copies
+	{
+		a: Vec<u32>,
+		b: Vec<u32>,
+		c: Vec<u32>,
+		d: Entity,
+	}
+
+	// Expansions of tables, arrays and array elements.
+	let src_str = r#"
+		a = [1, 2, 3]
+		b = $a
+		c = [10, 20, $1]
+		e
+		{
+			name = copy
+			pos = [0, 0]
+			shape = Circle [1]
+		}
+		d = $e
+	"#;
+	let mut src = Source::new(Path::new("copies.slr"), src_str);
+	let elem = ConfigElement::from_source(&mut src).unwrap();
+	let expected: Copies = from_element(&elem, Some(&src)).unwrap();
+	let mut src = Source::new(Path::new("copies.slr"), src_str);
+	assert_eq!(from_source::<Copies>(&mut src).unwrap(), expected);
+
+	// Errors are the same as with `from_element`.
+	for bad in &[
+		"a = [1, 2]\nb = [x]\nc = [1]\nd { name = n, pos = [1, 2], shape = Circle [0] }\n",
+		"a = [1, 2]\nb = []\nc = [1]\nd { name = n, pos = [1], shape = Circle [0] }\n",
+		"a = [1, 2]\nb = []\nc = [1]\nd { name = n, pos = [1, 2], shape = Square [0] }\n",
+		"a = [1, 2]\nb = []\nc { }\nd { name = n, pos = [1, 2], shape = Circle [0] }\n",
+	]
+	{
+		let mut src = Source::new(Path::new("bad.slr"), bad);
+		let elem = ConfigElement::from_source(&mut src).unwrap();
+		let expected = from_element::<Copies>(&elem, Some(&src)).unwrap_err();
+		let mut src = Source::new(Path::new("bad.slr"), bad);
+		let err = from_source::<Copies>(&mut src).unwrap_err();
+		assert_eq!(err.text, expected.text);
+	}
+	for bad in &["a = $b\n", "a = [1, 2\n", "x = [1]\na = $x ~ y\n"]
+	{
+		let mut src = Source::new(Path::new("bad.slr"), bad);
+		let expected = ConfigElement::from_source(&mut src).unwrap_err();
+		let mut src = Source::new(Path::new("bad.slr"), bad);
+		let err = from_source::<Copies>(&mut src).unwrap_err();
+		assert_eq!(err.text, expected.text);
+	}
+}
+
+#[test]
+fn option_encoding_test()
+{
+	use crate::stream_de::from_source_with_options;
+	use slr_parser::Source;
+	use std::path::Path;
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Options
+	{
+		a: Option<String>,
+		b: Option<()>,
+		c: Option<Option<u32>>,
+		d: Option<Option<u32>>,
+		e: Option<Option<u32>>,
+		f: Option<Vec<u32>>,
+	}
+
+	let value = Options {
+		a: Some(String::new()),
+		b: Some(()),
+		c: None,
+		d: Some(None),
+		e: Some(Some(1)),
+		f: Some(vec![]),
+	};
+
+	// The default encoding is ambiguous.
+	let elem = to_element(&value).unwrap();
+	let decoded: Options = from_element(&elem, None).unwrap();
+	assert_eq!(decoded.a, None);
+	assert_eq!(decoded.b, None);
+	assert_eq!(decoded.d, None);
+
+	let ser_options = SerializerOptions::new().option_encoding(OptionEncoding::Tagged);
+	let de_options = DeserializerOptions::new().option_encoding(OptionEncoding::Tagged);
+	let elem = to_element_with_options(&value, ser_options).unwrap();
+	assert_eq!(
+		elem.to_string(),
+		"a = Some [\"\"]\nb = Some [\"\"]\nc = None []\nd = Some [None []]\ne = Some [Some [1]]\nf = Some [[]]\n"
+	);
+	let decoded: Options = from_element_with_options(&elem, None, de_options).unwrap();
+	assert_eq!(decoded, value);
+
+	let mut output = vec![];
+	to_writer_with_options(&value, &mut output, ser_options).unwrap();
+	assert_eq!(String::from_utf8(output).unwrap(), elem.to_string());
+
+	let src_str = elem.to_string();
+	let mut src = Source::new(Path::new("options.slr"), &src_str);
+	let decoded: Options = from_source_with_options(&mut src, de_options).unwrap();
+	assert_eq!(decoded, value);
+
+	let src_str = "a = \"\"\n";
+	let mut src = Source::new(Path::new("options.slr"), src_str);
+	let elem = ConfigElement::from_source(&mut src).unwrap();
+	let err = from_element_with_options::<Options>(&elem, Some(&src), de_options).unwrap_err();
+	assert_eq!(
+		err.text,
+		"options.slr:1:4-1:5: error: Expected 'None []' or 'Some [value]'.\na = \"\"\n    ^\n"
+	);
+}
+
+#[test]
+fn bytes_encoding_test()
+{
+	use serde::de::{Deserializer, Visitor};
+	use serde::ser::Serializer;
+	use std::fmt;
+
+	#[derive(PartialEq, Debug)]
+	struct Bytes(Vec<u8>);
+
+	impl serde::Serialize for Bytes
+	{
+		fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		{
+			serializer.serialize_bytes(&self.0)
+		}
+	}
+
+	impl<'de> serde::Deserialize<'de> for Bytes
+	{
+		fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+		{
+			struct BytesVisitor;
+
+			impl<'de> Visitor<'de> for BytesVisitor
+			{
+				type Value = Bytes;
+
+				fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result
+				{
+					f.write_str("bytes")
+				}
+
+				fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Bytes, E>
+				{
+					Ok(Bytes(v))
+				}
+			}
+
+			deserializer.deserialize_byte_buf(BytesVisitor)
+		}
+	}
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Blobs
+	{
+		a: Bytes,
+		b: Bytes,
+		c: Bytes,
+		d: Bytes,
+	}
+
+	let value = Blobs {
+		a: Bytes(vec![]),
+		b: Bytes(vec![0xde]),
+		c: Bytes(vec![0xde, 0xad]),
+		d: Bytes(vec![0xde, 0xad, 0xbe, 0xef]),
+	};
+
+	for &(encoding, expected) in &[
+		(
+			BytesEncoding::Array,
+			"a = []\nb = [222]\nc = [222, 173]\nd = [222, 173, 190, 239]\n",
+		),
+		(
+			BytesEncoding::Hex,
+			"a = \"\"\nb = de\nc = dead\nd = deadbeef\n",
+		),
+		(
+			BytesEncoding::Base64,
+			"a = \"\"\nb = \"3g==\"\nc = \"3q0=\"\nd = \"3q2+7w==\"\n",
+		),
+	]
+	{
+		let ser_options = SerializerOptions::new().bytes_encoding(encoding);
+		let de_options = DeserializerOptions::new().bytes_encoding(encoding);
+		let elem = to_element_with_options(&value, ser_options).unwrap();
+		assert_eq!(elem.to_string(), expected);
+		let mut output = vec![];
+		to_writer_with_options(&value, &mut output, ser_options).unwrap();
+		assert_eq!(String::from_utf8(output).unwrap(), expected);
+		let decoded: Blobs = from_element_with_options(&elem, None, de_options).unwrap();
+		assert_eq!(decoded, value);
+
+		// The array form is always accepted.
+		let elem = to_element(&value).unwrap();
+		let decoded: Blobs = from_element_with_options(&elem, None, de_options).unwrap();
+		assert_eq!(decoded, value);
+	}
+
+	// Whitespace, upper case hex digits and missing padding are accepted.
+	let elem =
+		ConfigElement::from_str("a = \"\", b = 3g, c = \"3q0\", d = \"3q2+ 7w==\"\n").unwrap();
+	let de_options = DeserializerOptions::new().bytes_encoding(BytesEncoding::Base64);
+	assert_eq!(
+		from_element_with_options::<Blobs>(&elem, None, de_options).unwrap(),
+		value
+	);
+	let elem = ConfigElement::from_str("a = \"\", b = DE, c = \"de ad\", d = DeadBeef").unwrap();
+	let de_options = DeserializerOptions::new().bytes_encoding(BytesEncoding::Hex);
+	assert_eq!(
+		from_element_with_options::<Blobs>(&elem, None, de_options).unwrap(),
+		value
+	);
+
+	for bad in &["a = abc", "a = xy", "a = \"=\"\n"]
+	{
+		let elem = ConfigElement::from_str(bad).unwrap();
+		assert!(from_element_with_options::<Bytes>(
+			&elem.as_table().unwrap()["a"],
+			None,
+			DeserializerOptions::new().bytes_encoding(BytesEncoding::Hex)
+		)
+		.is_err());
+	}
+	let elem = ConfigElement::from_str("a = \"3g==3g==\"\n").unwrap();
+	assert!(from_element_with_options::<Bytes>(
+		&elem.as_table().unwrap()["a"],
+		None,
+		DeserializerOptions::new().bytes_encoding(BytesEncoding::Base64)
+	)
+	.is_err());
+}
+
+#[test]
+fn element_serde_test()
+{
+	use crate::stream_de::from_source;
+	use serde::de::value::{Error as ValueError, MapDeserializer, SeqDeserializer};
+	use serde::de::IntoDeserializer;
+	use serde::Deserialize;
+	use slr_parser::Source;
+	use std::path::Path;
+
+	#[derive(Serialize, Deserialize, Debug)]
+	struct Config
+	{
+		name: String,
+		plugin: ConfigElement,
+		extra: Vec<ConfigElement>,
+	}
+
+	let src_str = r#"
+		name = main
+		plugin = Renderer
+		{
+			size = [640, 480]
+			mode = Window { title = "A B" }
+			shaders = Paths [a, b]
+			empty {}
+		}
+		extra = [{a = b}, 1, []]
+	"#;
+	let mut src = Source::new(Path::new("config.slr"), src_str);
+	let root = ConfigElement::from_source(&mut src).unwrap();
+	let config: Config = from_element(&root, Some(&src)).unwrap();
+
+	let plugin = &root.as_table().unwrap()["plugin"];
+	assert_eq!(config.plugin.tag().unwrap(), "Renderer");
+	assert_eq!(config.plugin.to_string(), plugin.to_string());
+	assert_eq!(config.plugin.span(), plugin.span());
+	let mode = &config.plugin.as_table().unwrap()["mode"];
+	assert_eq!(mode.tag().unwrap(), "Window");
+	assert_eq!(mode.span(), plugin.as_table().unwrap()["mode"].span());
+	assert_eq!(config.extra.len(), 3);
+	// All the spans are kept.
+	let spans =
+		|elem: &ConfigElement| (elem.span(), elem.key_span(), elem.tag_span(), elem.extent());
+	assert!(config.plugin.tag_span().is_valid() && config.plugin.extent().is_valid());
+	assert!(mode.key_span().is_valid());
+	assert_eq!(spans(&config.plugin), spans(plugin));
+	assert_eq!(spans(mode), spans(&plugin.as_table().unwrap()["mode"]));
+
+	let mut src = Source::new(Path::new("config.slr"), src_str);
+	let streamed: Config = from_source(&mut src).unwrap();
+	assert_eq!(streamed.plugin.to_string(), plugin.to_string());
+	assert_eq!(streamed.plugin.span(), plugin.span());
+	assert_eq!(
+		spans(&streamed.plugin.as_table().unwrap()["mode"]),
+		spans(&plugin.as_table().unwrap()["mode"])
+	);
+
+	// Serializing keeps the tags, but not the quote types.
+	let expected = root.to_string().replace(r#""A B""#, "A B");
+	let elem = to_element(&config).unwrap();
+	assert_eq!(elem.to_string(), expected);
+	let mut output = vec![];
+	to_writer(&config, &mut output).unwrap();
+	assert_eq!(String::from_utf8(output).unwrap(), expected);
+	let elem = to_element(&root).unwrap();
+	assert_eq!(elem.to_string(), expected);
+
+	// Spans from other deserializers are checked.
+	use crate::element_serde::{END_FIELD, START_FIELD};
+	let fields = vec![(START_FIELD, 5usize), (END_FIELD, 3)];
+	let err = ConfigElement::deserialize(MapDeserializer::<_, ValueError>::new(fields.into_iter()))
+		.unwrap_err();
+	assert_eq!(
+		err.to_string(),
+		"Invalid span: the start (5) is after the end (3)."
+	);
+
+	// Other formats.
+	let elem = ConfigElement::deserialize(IntoDeserializer::<ValueError>::into_deserializer(5u32))
+		.unwrap();
+	assert_eq!(elem.as_value().unwrap(), "5");
+	let elem = ConfigElement::deserialize(SeqDeserializer::<_, ValueError>::new(
+		vec![true, false].into_iter(),
+	))
+	.unwrap();
+	assert_eq!(elem.to_string(), "\n[true, false]");
+	let elem = ConfigElement::deserialize(MapDeserializer::<_, ValueError>::new(
+		vec![("a", 1), ("b", 2)].into_iter(),
+	))
+	.unwrap();
+	assert_eq!(elem.to_string(), "a = 1\nb = 2\n");
+}
+
+#[test]
+fn convenience_test()
+{
+	use slr_parser::ErrorKind;
+	use std::fs;
+
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Window
+	{
+		title: String,
+		size: (u32, u32),
+	}
+
+	let window = Window {
+		title: "Main window".to_string(),
+		size: (640, 480),
+	};
+	let string = to_string(&window).unwrap();
+	assert_eq!(string, "title = Main window\nsize = [640, 480]\n");
+	assert_eq!(from_str::<Window>(&string).unwrap(), window);
+	assert_eq!(from_reader::<Window, _>(string.as_bytes()).unwrap(), window);
+	let err = from_reader::<Window, _>(&b"title = \xff\n"[..]).unwrap_err();
+	assert_eq!(err.kind, ErrorKind::Io);
+	assert!(err.text.starts_with("<reader>: error: "));
+	let err = from_reader::<Window, _>("title = a\nsize = 1\n".as_bytes()).unwrap_err();
+	assert_eq!(
+		err.text,
+		"<reader>:2:7-2:7: error: Expected an array.\nsize = 1\n       ^\n"
+	);
+
+	let path = std::env::temp_dir().join(format!("slr_config_test_{}.slr", std::process::id()));
+	fs::write(&path, "title = Main window\nsize = [640, x]\n").unwrap();
+	let err = from_file::<Window, _>(&path).unwrap_err();
+	fs::remove_file(&path).unwrap();
+	assert_eq!(
+		err.text,
+		format!(
+			"{}:2:13-2:13: error: Can't parse 'x' as an integer: invalid digit found in \
+			 string.\nsize = [640, x]\n             ^\n",
+			path.display()
+		)
+	);
+	let err = from_file::<Window, _>(&path).unwrap_err();
+	assert_eq!(err.kind, ErrorKind::Io);
+	assert!(err
+		.text
+		.starts_with(&format!("{}: error: ", path.display())));
+
+	let err = from_str::<Window>("title = a\nsize = 1\n").unwrap_err();
+	assert_eq!(
+		err.text,
+		"<anon>:2:7-2:7: error: Expected an array.\nsize = 1\n       ^\n"
+	);
+	assert!(to_string(&vec![1, 2]).is_err());
+}
+
+#[test]
+fn documented_test()
+{
+	/// The window settings.
+	#[derive(Serialize, SlrDocumented)]
+	#[serde(rename_all = "kebab-case")]
+	struct Window
+	{
+		/// The title of the window.
+		title: String,
+		/// The size of the window,
+		/// in pixels.
+		window_size: (u32, u32),
+		#[slr(nested)]
+		modes: Vec<Mode>,
+		/// Not documented when skipped.
+		#[serde(skip)]
+		_hidden: u32,
+	}
+
+	#[derive(Serialize, SlrDocumented)]
+	enum Mode
+	{
+		/// Fills the whole screen.
+		Fullscreen
+		{
+			/// Which monitor to use.
+			monitor: u32,
+		},
+		/// A regular window.
+		Windowed,
+	}
+
+	let window = Window {
+		title: "Main".to_string(),
+		window_size: (640, 480),
+		modes: vec![Mode::Fullscreen { monitor: 1 }, Mode::Windowed],
+		_hidden: 0,
+	};
+	let elem = to_element_documented(&window).unwrap();
+	assert_eq!(elem.comment().unwrap(), "The window settings.");
+	assert_eq!(
+		elem.as_table().unwrap()["window-size"].comment().unwrap(),
+		"The size of the window,\nin pixels."
+	);
+	assert_eq!(
+		elem.to_string(),
+		r#"# The window settings.
+
+# The title of the window.
+title = Main
+
+# The size of the window,
+# in pixels.
+window-size = [640, 480]
+modes =
+[
+	# Fills the whole screen.
+	Fullscreen
+	{
+		# Which monitor to use.
+		monitor = 1
+	},
+
+	# A regular window.
+	Windowed
+]
+"#
+	);
+	assert!(to_element(&window).unwrap().comment().is_none());
+
+	// Serializing the element keeps the comments and the layouts.
+	let mut elem = elem;
+	*elem["modes"][0].layout_mut() = Layout::OneLine;
+	assert_eq!(to_element(&elem).unwrap().to_string(), elem.to_string());
+	assert_eq!(to_string(&elem).unwrap(), elem.to_string());
+	let mut output = vec![];
+	to_writer(&elem, &mut output).unwrap();
+	assert_eq!(String::from_utf8(output).unwrap(), elem.to_string());
+	assert!(crate::element_serde::take_metadata().is_none());
+
+	// Raw identifiers are documented under the names serde uses.
+	#[derive(Serialize, SlrDocumented)]
+	struct Item
+	{
+		/// The kind of the item.
+		r#type: u32,
+	}
+	let elem = to_element_documented(&Item { r#type: 1 }).unwrap();
+	assert_eq!(elem.to_string(), "# The kind of the item.\ntype = 1\n");
+}
+
+#[test]
+fn from_element_test()
+{
+	use slr_parser::{Error, Source};
+	use std::collections::BTreeMap;
+	use std::path::Path;
+
+	// Accepts both `640x480` and `[640, 480]`.
+	#[derive(Debug, PartialEq)]
+	struct Size(u32, u32);
+
+	impl FromElement for Size
+	{
+		fn from_element(element: &ConfigElement, source: Option<&Source>) -> Result<Size, Error>
+		{
+			if let Some((w, h)) = element.as_value().and_then(|v| v.split_once('x'))
+			{
+				let w = ConfigElement::new_value(w).with_span(element.span());
+				let h = ConfigElement::new_value(h).with_span(element.span());
+				return Ok(Size(
+					u32::from_element(&w, source)?,
+					u32::from_element(&h, source)?,
+				));
+			}
+			let (w, h) = <(u32, u32)>::from_element(element, source)?;
+			Ok(Size(w, h))
+		}
+	}
+
+	impl ToElement for Size
+	{
+		fn to_element(&self) -> ConfigElement
+		{
+			ConfigElement::new_value(format!("{}x{}", self.0, self.1))
+		}
+	}
+
+	#[derive(FromElement, ToElement, Debug, PartialEq)]
+	enum Mode
+	{
+		Windowed,
+		Fullscreen(u32),
+		Scaled
+		{
+			factor: f32,
+		},
+	}
+
+	#[derive(FromElement, ToElement, Debug, PartialEq)]
+	struct Window
+	{
+		title: String,
+		#[slr(rename = "window-size")]
+		size: Size,
+		modes: Vec<Mode>,
+		icon: Option<String>,
+		#[slr(default)]
+		monitors: BTreeMap<u32, String>,
+		#[slr(skip)]
+		hidden: bool,
+	}
+
+	let src = "title = Main\nwindow-size = 640x480\nmodes = [Windowed, Fullscreen [1], Scaled { factor = 2 }]\n";
+	let mut source = Source::new(Path::new("<anon>"), src);
+	let elem = ConfigElement::from_source(&mut source).unwrap();
+	let window = Window::from_element(&elem, Some(&source)).unwrap();
+	assert_eq!(
+		window,
+		Window {
+			title: "Main".to_string(),
+			size: Size(640, 480),
+			modes: vec![
+				Mode::Windowed,
+				Mode::Fullscreen(1),
+				Mode::Scaled { factor: 2.0 }
+			],
+			icon: None,
+			monitors: BTreeMap::new(),
+			hidden: false,
+		}
+	);
+	assert_eq!(
+		window.to_element().to_string(),
+		"title = Main\nwindow-size = 640x480\nmodes =\n[\n\tWindowed,\n\tFullscreen [1],\n\tScaled\n\t{\n\t\tfactor = \
+		 2\n\t}\n]\nicon = \"\"\nmonitors {}\n"
+	);
+	let elem = ConfigElement::from_str("window-size = [640, 480]").unwrap();
+	assert_eq!(
+		elem.as_table().unwrap()["window-size"].to_string(),
+		"\n[640, 480]"
+	);
+	assert_eq!(
+		Size::from_element(&elem.as_table().unwrap()["window-size"], None).unwrap(),
+		Size(640, 480)
+	);
+
+	let check_error = |src: &str, expected: &str| {
+		let mut source = Source::new(Path::new("<anon>"), src);
+		let elem = ConfigElement::from_source(&mut source).unwrap();
+		let err = Window::from_element(&elem, Some(&source)).unwrap_err();
+		assert_eq!(err.text, expected);
+	};
+	check_error(
+		"title = a, window-size = 640xy, modes = []",
+		"<anon>:1:25-1:29: error: Can't parse 'y' as an integer: invalid digit found in \
+		 string.\ntitle = a, window-size = 640xy, modes = []\n                         ^~~~~\n",
+	);
+	check_error(
+		"title = a, window-size = 1x1, modes = [Windowed, Tiled]",
+		"<anon>:1:49-1:53: error: Unknown variant 'Tiled', expected one of: Windowed, \
+		 Fullscreen, Scaled.\ntitle = a, window-size = 1x1, modes = [Windowed, Tiled]\n\
+		 \u{20}                                                ^~~~~\n",
+	);
+	check_error(
+		"title = a, window-size = 1x1, modes = [], monitors { 1 = a, x = b }",
+		"<anon>:1:60-1:60: error: Can't parse 'x' as an integer: invalid digit found in \
+		 string.\ntitle = a, window-size = 1x1, modes = [], monitors { 1 = a, x = b }\n\
+		 \u{20}                                                           ^\n",
+	);
+	check_error(
+		"title = a, window-size = 1x1, modes = [Scaled {}]",
+		"<anon>:1:39-1:44: error: Missing field 'factor'.\ntitle = a, window-size = 1x1, \
+		 modes = [Scaled {}]\n                                       ^~~~~~\n",
+	);
+
+	// Skipped tuple fields are neither written nor read.
+	#[derive(FromElement, ToElement, Debug, PartialEq)]
+	struct Pair(u32, #[slr(skip)] u32);
+	#[derive(FromElement, ToElement, Debug, PartialEq)]
+	struct Hidden(#[slr(skip)] u32);
+	let elem = Pair(1, 2).to_element();
+	assert_eq!(elem.to_string(), "\n[1]");
+	assert_eq!(Pair::from_element(&elem, None).unwrap(), Pair(1, 0));
+	let elem = Hidden(3).to_element();
+	assert_eq!(elem.as_value().unwrap(), "");
+	assert_eq!(Hidden::from_element(&elem, None).unwrap(), Hidden(0));
+
+	// Raw identifiers are named without the `r#` prefix.
+	#[derive(FromElement, ToElement, Debug, PartialEq)]
+	enum Kind
+	{
+		r#Box,
+	}
+	#[derive(FromElement, ToElement, Debug, PartialEq)]
+	struct Item
+	{
+		r#type: Kind,
+	}
+	let elem = ConfigElement::from_str("type = Box").unwrap();
+	let item = Item::from_element(&elem, None).unwrap();
+	assert_eq!(
+		item,
+		Item {
+			r#type: Kind::r#Box
+		}
+	);
+	assert_eq!(item.to_element().to_string(), "type = Box\n");
+}
+
+#[test]
+fn path_test()
+{
+	use slr_parser::ErrorKind;
+
+	let mut root = ConfigElement::from_str(
+		r#"
+		a = [x, y, { b = 1 }]
+		servers
+		{
+			"example.com" { port = 80 }
+		}
+		t = Tag [1]
+		"#,
+	)
+	.unwrap();
+	assert_eq!(root.get_path("a[2].b").unwrap().as_value().unwrap(), "1");
+	assert_eq!(root.get_path("a.1").unwrap().as_value().unwrap(), "y");
+	assert_eq!(root.get_path("t[0]").unwrap().as_value().unwrap(), "1");
+	assert_eq!(
+		root.get_path(r#"servers."example.com".port"#)
+			.unwrap()
+			.as_value()
+			.unwrap(),
+		"80"
+	);
+	assert!(root.get_path("").unwrap().as_table().is_some());
+
+	*root.get_path_mut("a[0]").unwrap() = ConfigElement::new_value("z");
+	assert_eq!(root.get_path("a[0]").unwrap().as_value().unwrap(), "z");
+
+	let path = ElementPath::parse(r#"servers."example.com".port"#).unwrap();
+	assert_eq!(
+		path.segments(),
+		&[
+			PathSegment::Key("servers".to_string()),
+			PathSegment::Key("example.com".to_string()),
+			PathSegment::Key("port".to_string()),
+		]
+	);
+	assert_eq!(path.to_string(), r#"servers."example.com".port"#);
+	let path = ElementPath::from(vec![
+		PathSegment::Key("a".to_string()),
+		PathSegment::Index(2),
+		PathSegment::Key("say \"hi\"".to_string()),
+	]);
+	assert_eq!(path.to_string(), r#"a[2]."say \"hi\"""#);
+	assert_eq!(ElementPath::parse(&path.to_string()).unwrap(), path);
+
+	let check_error = |path: &str, expected: &str| {
+		let err = root.get_path(path).unwrap_err();
+		assert_eq!(err.kind, ErrorKind::InvalidPath);
+		assert_eq!(err.text, expected);
+	};
+	check_error(
+		"a[3]",
+		"error: Can't follow path 'a[3]' at segment '[3]': index 3 is out of bounds, the array \
+		 has 3 elements.\n",
+	);
+	check_error(
+		"a[2].c",
+		"error: Can't follow path 'a[2].c' at segment 'c': there is no element with that key.\n",
+	);
+	check_error(
+		"a[0].c",
+		"error: Can't follow path 'a[0].c' at segment 'c': expected a table, found a value.\n",
+	);
+	check_error(
+		"servers[0]",
+		"error: Can't follow path 'servers[0]' at segment '[0]': expected an array, found a \
+		 table.\n",
+	);
+	check_error(
+		"a[x]",
+		"error: Invalid path 'a[x]': expected a digit or ']' at position 2.\n",
+	);
+	check_error(
+		"a..b",
+		"error: Invalid path 'a..b': expected a key at position 2.\n",
+	);
+	check_error(
+		"a[0]b",
+		"error: Invalid path 'a[0]b': expected '.' or '[' at position 4.\n",
+	);
+	check_error(
+		"\"a",
+		"error: Invalid path '\"a': unterminated quoted key at position 2.\n",
+	);
+}
+
+#[test]
+fn container_test()
+{
+	let mut root = ConfigElement::from_str("a = [x, y, z], t { b = 1, c = 2 }, v = w").unwrap();
+	assert_eq!(root["a"][1].as_value().unwrap(), "y");
+	assert_eq!(root["t"]["c"].as_value().unwrap(), "2");
+	assert_eq!(root[&"t".to_string()].len(), 2);
+	assert!(root.get("missing").is_none());
+	assert!(root.get(0).is_none());
+	assert!(root["v"].get("x").is_none());
+	assert_eq!(root["v"].len(), 0);
+	assert!(root["v"].is_empty());
+	assert_eq!(
+		root["a"]
+			.get(PathSegment::Key("2".to_string()))
+			.unwrap()
+			.as_value()
+			.unwrap(),
+		"z"
+	);
+
+	root["a"][0] = ConfigElement::new_value("q");
+	*root.get_mut("v").unwrap() = ConfigElement::new_value("u");
+	assert_eq!(root["a"].remove(1).unwrap().as_value().unwrap(), "y");
+	assert!(root["a"].remove(5).is_none());
+	assert_eq!(root["t"].remove("b").unwrap().as_value().unwrap(), "1");
+	root["t"]
+		.entry("d")
+		.or_insert_with(|| ConfigElement::new_value(3));
+	*root["t"]
+		.entry("c")
+		.or_insert_with(|| ConfigElement::new_value(0))
+		.as_value_mut()
+		.unwrap() += "0";
+	assert_eq!(
+		root.to_string(),
+		"a = [q, z]\nt\n{\n\tc = 20\n\td = 3\n}\nv = u\n"
+	);
+
+	let keys: Vec<_> = root.iter().map(|(k, _)| k.unwrap()).collect();
+	assert_eq!(keys, ["a", "t", "v"]);
+	let values: Vec<_> = root["a"]
+		.iter()
+		.map(|(k, v)| (k, v.as_value().unwrap().clone()))
+		.collect();
+	assert_eq!(values, [(None, "q".to_string()), (None, "z".to_string())]);
+	for (_, v) in root["t"].iter_mut()
+	{
+		v.as_value_mut().unwrap().push('!');
+	}
+	assert_eq!(root["t"]["d"].as_value().unwrap(), "3!");
+	assert_eq!(root["v"].iter().count(), 0);
+
+	let array: ConfigElement = (0..3).map(ConfigElement::new_value).collect();
+	assert_eq!(array.to_string(), "\n[0, 1, 2]");
+	let mut table: ConfigElement = vec![("x", ConfigElement::new_value(1))]
+		.into_iter()
+		.collect();
+	table.extend(vec![("y", array)]);
+	assert_eq!(table.to_string(), "x = 1\ny = [0, 1, 2]\n");
+	let mut tagged = ConfigElement::new_tagged_array("T".to_string());
+	tagged.extend(vec![ConfigElement::new_value("a")]);
+	assert_eq!(tagged[0].as_value().unwrap(), "a");
+}
+
+#[test]
+fn typed_getter_test()
+{
+	use slr_parser::{Error, Source};
+	use std::net::IpAddr;
+	use std::path::Path;
+
+	let src = "port = 80, host = localhost, ratio = abc, arr = [1, x], t { }\n";
+	let mut source = Source::new(Path::new("<anon>"), src);
+	let root = ConfigElement::from_source(&mut source).unwrap();
+	let source = Some(&source);
+	assert_eq!(root.get_as::<u16, _>("port", source).unwrap(), 80);
+	assert_eq!(root["port"].parse::<u16>(source).unwrap(), 80);
+	assert_eq!(root["arr"].get_as::<u8, _>(0, source).unwrap(), 1);
+	assert_eq!(
+		root.get_as::<String, _>("host", source).unwrap(),
+		"localhost"
+	);
+
+	let check_error = |err: Error, expected: &str| assert_eq!(err.text, expected);
+	let point_at = |col: usize, msg: &str| {
+		format!(
+			"<anon>:1:{}-1:{}: error: {}\n{}{}^\n",
+			col,
+			col,
+			msg,
+			src,
+			" ".repeat(col)
+		)
+	};
+	check_error(
+		root.get_as::<u16, _>("host", source).unwrap_err(),
+		"<anon>:1:18-1:26: error: Can't parse `localhost`: invalid digit found in string.\nport \
+		 = 80, host = localhost, ratio = abc, arr = [1, x], t { }\n                  \
+		 ^~~~~~~~~\n",
+	);
+	check_error(
+		root["ratio"].parse::<f32>(source).unwrap_err(),
+		"<anon>:1:37-1:39: error: Can't parse `abc`: invalid float literal.\nport = 80, host = \
+		 localhost, ratio = abc, arr = [1, x], t { }\n                                     \
+		 ^~~\n",
+	);
+	check_error(
+		root["arr"].get_as::<IpAddr, _>(1, source).unwrap_err(),
+		&point_at(52, "Can't parse `x`: invalid IP address syntax."),
+	);
+	check_error(
+		root.get_as::<bool, _>("arr", source).unwrap_err(),
+		&point_at(48, "Expected a value, found an array."),
+	);
+	check_error(
+		root["t"].get_as::<u32, _>("x", source).unwrap_err(),
+		&point_at(58, "Missing key `x`."),
+	);
+	check_error(
+		root["arr"].get_as::<u32, _>(2, source).unwrap_err(),
+		&point_at(48, "Missing element at index `2`."),
+	);
+	check_error(
+		ConfigElement::new_value(300).parse::<u8>(None).unwrap_err(),
+		"error: Can't parse `300`: number too large to fit in target type.\n",
+	);
+	check_error(
+		root.get_as::<u32, _>("missing", None).unwrap_err(),
+		"error: Missing key `missing`.\n",
+	);
+}
+
+#[test]
+fn equality_test()
+{
+	use std::collections::HashSet;
+
+	let elem1 = ConfigElement::from_str("a = 1, b = [x, y], t { c = z }").unwrap();
+	let mut elem2 = ConfigElement::from_str("a = 1\nb = [x, y]\nt\n{\n\tc = z\n}\n").unwrap();
+	*elem2.comment_mut() = Some("comment".to_string());
+	assert_eq!(elem1, elem2);
+	assert_ne!(elem1["a"], elem1["b"]);
+
+	let mut multi_line = ConfigElement::new_multi_line_array();
+	multi_line.insert("", ConfigElement::new_value("x"));
+	multi_line.insert("", ConfigElement::new_value("y"));
+	assert_eq!(elem1["b"], multi_line);
+	let tagged = ConfigElement::from_str("b = tag [x, y]").unwrap();
+	assert_ne!(elem1["b"], tagged["b"]);
+
+	let reordered = ConfigElement::from_str("b = [x, y], a = 1, t { c = z }").unwrap();
+	assert_ne!(elem1, reordered);
+	assert!(elem1.eq_unordered(&reordered));
+	assert!(
+		!elem1.eq_unordered(&ConfigElement::from_str("b = [y, x], a = 1, t { c = z }").unwrap())
+	);
+
+	let set: HashSet<_> = vec![elem1.clone(), elem2, multi_line, reordered]
+		.into_iter()
+		.collect();
+	assert_eq!(set.len(), 3);
+	assert!(set.contains(&elem1));
+}
+
+#[test]
+fn slr_macro_test()
+{
+	#[derive(Serialize)]
+	struct Point
+	{
+		x: i32,
+		y: i32,
+	}
+
+	let name = "server";
+	let mut inner = ConfigElement::new_tagged_array("inner".to_string());
+	inner.insert("", ConfigElement::new_value("z"));
+	let root = crate::slr! {
+		a = 1,
+		"key with spaces" = "value with spaces",
+		negative = -2.50,
+		arr = [a, -1, [b], { c = d }, tag [e], tag { f = g }],
+		tagged_arr = tag [1, 2],
+		tab { x = 1, nested { } },
+		tagged_tab = tag { y = true },
+		(name) = (inner.clone()),
+		point = (Point { x: 1, y: 2 }),
+		list = (vec![1, 2]),
+		("dynamic".to_string() + "_key") = ("string"),
+	};
+	let expected = ConfigElement::from_str(
+		r#"
+		a = 1
+		"key with spaces" = "value with spaces"
+		negative = -2.50
+		arr = [a, -1, [b], { c = d }, tag [e], tag { f = g }]
+		tagged_arr = tag [1, 2]
+		tab { x = 1, nested { } }
+		tagged_tab = tag { y = true }
+		server = inner [z]
+		point = Point { x = 1, y = 2 }
+		list = [1, 2]
+		dynamic_key = string
+		"#,
+	)
+	.unwrap();
+	assert_eq!(root, expected);
+	assert_eq!(root["server"], inner);
+	assert_eq!(crate::slr! {}, ConfigElement::new_table());
+
+	// Numbers keep their text, whatever their size.
+	let root = crate::slr! {
+		big = 3000000000,
+		huge = 340282366920938463463374607431768211455,
+		hex = 0xff,
+		byte = 1_000u8,
+		float = 1.0e400,
+	};
+	assert_eq!(root["big"].as_value().unwrap(), "3000000000");
+	assert_eq!(
+		root["huge"].as_value().unwrap(),
+		"340282366920938463463374607431768211455"
+	);
+	assert_eq!(root["hex"].as_value().unwrap(), "0xff");
+	assert_eq!(root["byte"].as_value().unwrap(), "1000");
+	assert_eq!(root["float"].as_value().unwrap(), "1.0e400");
+}
+
+#[test]
+fn key_span_test()
+{
+	use slr_parser::{Source, Span};
+	use std::collections::BTreeMap;
+	use std::path::Path;
+
+	let src = "b = y, a = x ~ $b\nt = tag { c = [1, { d = 2 }] }\n\"e f\"\n{\n}\n";
+	let mut source = Source::new(Path::new("<anon>"), src);
+	let root = ConfigElement::from_source(&mut source).unwrap();
+	let text = |span: Span| &src[span.start()..span.end()];
+	assert_eq!(text(root["a"].key_span()), "a");
+	assert_eq!(text(root["a"].extent()), "x ~ $b");
+	assert_eq!(text(root["t"].key_span()), "t");
+	assert_eq!(text(root["t"].tag_span()), "tag");
+	assert_eq!(text(root["t"].extent()), "tag { c = [1, { d = 2 }] }");
+	assert_eq!(text(root["t"]["c"].extent()), "[1, { d = 2 }]");
+	assert_eq!(text(root["t"]["c"][1].extent()), "{ d = 2 }");
+	assert!(!root["t"]["c"][1].key_span().is_valid());
+	assert_eq!(text(root["e f"].key_span()), "\"e f\"");
+	assert_eq!(text(root["e f"].extent()), "{\n}");
+	assert!(!root.extent().is_valid());
+
+	// Map keys and struct tags are underlined in errors.
+	let mut source = Source::new(Path::new("<anon>"), "m { 1 = a, x = b }\n");
+	let root = ConfigElement::from_source(&mut source).unwrap();
+	assert_eq!(
+		BTreeMap::<u32, String>::from_element(&root["m"], Some(&source))
+			.unwrap_err()
+			.text,
+		"<anon>:1:11-1:11: error: Can't parse 'x' as an integer: invalid digit found in string.\nm { 1 = a, x = b }\n           ^\n"
+	);
+
+	#[derive(Deserialize, Debug)]
+	struct Point
+	{
+		#[allow(dead_code)]
+		x: u32,
+	}
+
+	#[derive(Deserialize, Debug)]
+	struct Test
+	{
+		#[allow(dead_code)]
+		p: Point,
+	}
+
+	let mut source = Source::new(Path::new("<anon>"), "p = Pt { x = 1 }\n");
+	let root = ConfigElement::from_source(&mut source).unwrap();
+	assert_eq!(
+		from_element::<Test>(&root, Some(&source)).unwrap_err().text,
+		"<anon>:1:4-1:5: error: Cannot deserialize struct 'Point' from a table with tag \
+		 'Pt'.\np = Pt { x = 1 }\n    ^\n"
+	);
+
+	// Duplicate fields are only seen when streaming.
+	let mut source = Source::new(Path::new("<anon>"), "p { x = 1, x = 2 }\n");
+	assert_eq!(
+		from_source::<Test>(&mut source).unwrap_err().text,
+		"<anon>:1:11-1:11: error: Duplicate field `x`.\np { x = 1, x = 2 }\n           ^\n"
+	);
+}
+
+#[test]
+fn quote_type_test()
+{
+	use slr_parser::StringQuoteType;
+
+	let src = r#"a = "quoted"
+"key" = {{"raw "quotes" \n"}}
+b = {{{"a"}}"}}}
+c = tag1 [x]
+d = "tag2" {}
+e = x ~ "y"
+f = $a
+"#;
+	let mut root = ConfigElement::from_str(src).unwrap();
+	assert_eq!(root["a"].quote_type(), Some(StringQuoteType::Quoted(0)));
+	assert_eq!(root["a"].key_quote_type(), Some(StringQuoteType::Naked));
+	assert_eq!(
+		root["key"].key_quote_type(),
+		Some(StringQuoteType::Quoted(0))
+	);
+	assert_eq!(root["key"].quote_type(), Some(StringQuoteType::Quoted(2)));
+	assert_eq!(root["b"].quote_type(), Some(StringQuoteType::Quoted(3)));
+	assert_eq!(root["d"].quote_type(), Some(StringQuoteType::Quoted(0)));
+	assert_eq!(root["e"].quote_type(), None);
+	assert_eq!(
+		root.to_string(),
+		r#"a = "quoted"
+"key" = {{"raw "quotes" \n"}}
+b = {{{"a"}}"}}}
+c = tag1 [x]
+d = "tag2" {}
+e = xy
+f = "quoted"
+"#
+	);
+
+	// Quote types that can't represent the new contents are ignored.
+	*root["a"].as_value_mut().unwrap() = "x\"y".to_string();
+	*root["b"].as_value_mut().unwrap() = "x\"}}}".to_string();
+	root.insert("g", ConfigElement::new_value("a = b"));
+	*root["g"].quote_type_mut() = Some(StringQuoteType::Naked);
+	assert_eq!(
+		root.to_string(),
+		r#"a = {{"x"y"}}
+"key" = {{"raw "quotes" \n"}}
+b = {{{{"x"}}}"}}}}
+c = tag1 [x]
+d = "tag2" {}
+e = xy
+f = "quoted"
+g = "a = b"
+"#
+	);
+}
+
+#[test]
+fn layout_test()
+{
+	let mut root = ConfigElement::from_str("a { b = 1, c = 2 }\nd = [x, y]\ne {}").unwrap();
+	assert_eq!(root["a"].layout(), Layout::Auto);
+	assert_eq!(
+		root.to_string(),
+		"a\n{\n\tb = 1\n\tc = 2\n}\nd = [x, y]\ne {}\n"
+	);
+
+	*root["a"].layout_mut() = Layout::OneLine;
+	*root["d"].layout_mut() = Layout::MultiLine;
+	*root["e"].layout_mut() = Layout::MultiLine;
+	let printed = root.to_string();
+	assert_eq!(printed, "a {b = 1, c = 2}\nd =\n[\n\tx,\n\ty\n]\ne\n{}\n");
+	// The layout only affects printing.
+	let reparsed = ConfigElement::from_str(&printed).unwrap();
+	assert_eq!(reparsed, root);
+	assert_eq!(reparsed["d"].layout(), Layout::Auto);
+
+	// Layouts survive the changes of kind made when tagging.
+	let mut array = ConfigElement::new_multi_line_array();
+	array.insert("", ConfigElement::new_value("x"));
+	assert!(matches!(array.kind(), Array(_)));
+	*array.kind_mut() = TaggedArray("T".to_string(), array.as_array().unwrap().clone());
+	assert_eq!(array.layout(), Layout::MultiLine);
+	let mut root = ConfigElement::new_table();
+	root.insert("t", array);
+	assert_eq!(root.to_string(), "t = T\n[\n\tx\n]\n");
+}
+
+#[test]
+fn walk_test()
+{
+	use crate::visit::WalkControl;
+
+	let src = "a { b = 1, secret = x }\nc = [2, { d = 3 }]\ne = T { f = 4 }";
+	let mut root = ConfigElement::from_str(src).unwrap();
+
+	let mut paths = vec![];
+	root.walk(&mut |path: &ElementPath, element: &ConfigElement| {
+		if path.to_string() == "c[1].d"
+		{
+			assert_eq!(element.span().start(), 38);
+		}
+		paths.push(path.to_string());
+		WalkControl::Continue
+	});
+	let expected = [
+		"", "a", "a.b", "a.secret", "c", "c[0]", "c[1]", "c[1].d", "e", "e.f",
+	];
+	assert_eq!(paths, expected);
+
+	// Skipping and stopping.
+	let mut paths = vec![];
+	root.walk(&mut |path: &ElementPath, element: &ConfigElement| {
+		paths.push(path.to_string());
+		match element.as_array()
+		{
+			Some(_) => WalkControl::SkipChildren,
+			None if path.to_string() == "e" => WalkControl::Stop,
+			None => WalkControl::Continue,
+		}
+	});
+	assert_eq!(paths, ["", "a", "a.b", "a.secret", "c", "e"]);
+
+	// Masking secrets and renaming keys.
+	root.walk_mut(&mut |path: &ElementPath, element: &mut ConfigElement| {
+		if path.to_string() == "a.secret"
+		{
+			*element = ConfigElement::new_value("***");
+		}
+		if let Some(table) = element.as_table_mut()
+		{
+			if let Some(value) = table.shift_remove("f")
+			{
+				table.insert("g".to_string(), value);
+			}
+		}
+		WalkControl::Continue
+	});
+	assert_eq!(
+		root,
+		ConfigElement::from_str("a { b = 1, secret = *** }\nc = [2, { d = 3 }]\ne = T { g = 4 }")
+			.unwrap()
+	);
+
+	// Substituting values.
+	root.map_values(|path, value| match value
+	{
+		"***" => None,
+		_ => Some(format!("{}={}", path, value)),
+	});
+	assert_eq!(root["a"]["b"].as_value().unwrap(), "a.b=1");
+	assert_eq!(root["a"]["secret"].as_value().unwrap(), "***");
+	assert_eq!(root["c"][0].as_value().unwrap(), "c[0]=2");
+	assert_eq!(root["e"]["g"].as_value().unwrap(), "e.g=4");
+	let mut value = ConfigElement::new_value("x");
+	value.map_values(|path, value| Some(format!("[{}]{}", path, value)));
+	assert_eq!(value.as_value().unwrap(), "[]x");
+}
+
+#[test]
+fn shared_contents_test()
+{
+	use crate::visit::WalkControl;
+	use std::ptr;
+
+	let src = "defaults { size = 1, name = x }\na = $defaults\nb = $defaults";
+	let mut root = ConfigElement::from_str(src).unwrap();
+	// Expansions share the contents of the expanded element.
+	let defaults = root["defaults"].as_table().unwrap();
+	assert!(ptr::eq(root["a"].as_table().unwrap(), defaults));
+	assert!(ptr::eq(root["b"].as_table().unwrap(), defaults));
+
+	// Modifying a copy leaves the other copies untouched.
+	root["b"].insert("size", ConfigElement::new_value(2));
+	assert_eq!(root["b"]["size"].as_value().unwrap(), "2");
+	assert_eq!(root["defaults"]["size"].as_value().unwrap(), "1");
+	assert_eq!(root["a"]["size"].as_value().unwrap(), "1");
+	assert!(ptr::eq(
+		root["a"].as_table().unwrap(),
+		root["defaults"].as_table().unwrap()
+	));
+	// The unmodified children are still shared.
+	assert!(ptr::eq(
+		root["b"]["name"].as_value().unwrap(),
+		root["defaults"]["name"].as_value().unwrap()
+	));
+
+	let clone = root.clone();
+	assert!(ptr::eq(clone.as_table().unwrap(), root.as_table().unwrap()));
+	*root["a"]["name"].as_value_mut().unwrap() = "y".to_string();
+	assert_eq!(clone["a"]["name"].as_value().unwrap(), "x");
+	assert_eq!(root["a"]["name"].as_value().unwrap(), "y");
+	assert_eq!(root["defaults"]["name"].as_value().unwrap(), "x");
+	assert_eq!(clone["a"], clone["defaults"]);
+
+	// Walks only copy what they change.
+	let src = "defaults { size = 1, sub { name = x } }\na = $defaults\nb = $defaults";
+	let mut root = ConfigElement::from_str(src).unwrap();
+	let shared = |root: &ConfigElement, key: &str, path: &str| {
+		ptr::eq(
+			root[key].get_path(path).unwrap().as_table().unwrap(),
+			root["defaults"].get_path(path).unwrap().as_table().unwrap(),
+		)
+	};
+	root.walk_mut(&mut |_: &ElementPath, _: &mut ConfigElement| WalkControl::Continue);
+	root.map_values(|_, _| None);
+	assert!(shared(&root, "a", "") && shared(&root, "b", ""));
+	root.map_values(|path, value| match path.to_string().as_str()
+	{
+		"b.size" => Some(format!("{}0", value)),
+		_ => None,
+	});
+	assert_eq!(root["b"]["size"].as_value().unwrap(), "10");
+	assert_eq!(root["defaults"]["size"].as_value().unwrap(), "1");
+	assert!(shared(&root, "a", ""));
+	assert!(!shared(&root, "b", ""));
+	assert!(shared(&root, "b", "sub"));
+
+	// Changes made before stopping are kept.
+	root.walk_mut(&mut |path: &ElementPath, element: &mut ConfigElement| {
+		match path.to_string().as_str()
+		{
+			"a.size" => *element = ConfigElement::new_value(3),
+			"a.sub" => return WalkControl::Stop,
+			_ => (),
+		}
+		WalkControl::Continue
+	});
+	assert_eq!(root["a"]["size"].as_value().unwrap(), "3");
+	assert_eq!(root["defaults"]["size"].as_value().unwrap(), "1");
+	assert!(shared(&root, "a", "sub"));
+}
+
+#[test]
+fn naked_string_test()
+{
+	// The second character of a string has to be checked like the other
+	// middle characters, or these would be printed naked and not reparse.
+	for s in &["x\"y", "a=b", "a[b", "a,b", "a~b", "a#b", "a$b"]
+	{
+		let mut root = ConfigElement::new_table();
+		root.insert("a", ConfigElement::new_value(*s));
+		let printed = root.to_string();
+		let reparsed = ConfigElement::from_str(&printed).unwrap();
+		assert_eq!(reparsed["a"].as_value().map(|v| &v[..]), Some(*s));
+	}
+}
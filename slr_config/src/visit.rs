@@ -0,0 +1,57 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+use crate::config_element::ConfigElement;
+use crate::path::ElementPath;
+
+/// What to do after visiting an element, see `ConfigElement::walk`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WalkControl
+{
+	/// Visit the children of the element, then carry on with its siblings.
+	Continue,
+	/// Don't visit the children of the element.
+	SkipChildren,
+	/// Stop the walk, visiting no more elements.
+	Stop,
+}
+
+/// A visitor of the elements of a tree, see `ConfigElement::walk`.
+///
+/// This is implemented for closures taking the same arguments as `visit`.
+pub trait Visit
+{
+	/// Called for every element, before its children. `path` is the path of
+	/// the element relative to the element the walk started from, with table
+	/// children identified by their key and array children by their index.
+	fn visit(&mut self, path: &ElementPath, element: &ConfigElement) -> WalkControl;
+}
+
+impl<F: FnMut(&ElementPath, &ConfigElement) -> WalkControl> Visit for F
+{
+	fn visit(&mut self, path: &ElementPath, element: &ConfigElement) -> WalkControl
+	{
+		self(path, element)
+	}
+}
+
+/// A visitor that can modify the elements of a tree, see
+/// `ConfigElement::walk_mut`.
+///
+/// This is implemented for closures taking the same arguments as `visit_mut`.
+pub trait VisitMut
+{
+	/// Called for every element, before its children, see `Visit::visit`. The
+	/// element can be modified or replaced, in which case the children of the
+	/// new element are visited.
+	fn visit_mut(&mut self, path: &ElementPath, element: &mut ConfigElement) -> WalkControl;
+}
+
+impl<F: FnMut(&ElementPath, &mut ConfigElement) -> WalkControl> VisitMut for F
+{
+	fn visit_mut(&mut self, path: &ElementPath, element: &mut ConfigElement) -> WalkControl
+	{
+		self(path, element)
+	}
+}
@@ -0,0 +1,404 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+use crate::SlrAttrs;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{parse_quote, Data, DeriveInput, Error, Fields, Generics, Ident, Index};
+
+pub fn expand_from_element(mut input: DeriveInput) -> Result<TokenStream2, Error>
+{
+	let body = match input.data
+	{
+		Data::Struct(ref data) => fields_from_element(&data.fields, quote!(Self), true)?,
+		Data::Enum(ref data) =>
+		{
+			let mut names = vec![];
+			let mut unit_arms = vec![];
+			let mut array_arms = vec![];
+			let mut table_arms = vec![];
+			for variant in &data.variants
+			{
+				let attrs = SlrAttrs::from_attrs(&variant.attrs)?;
+				if attrs.skip
+				{
+					continue;
+				}
+				let ident = &variant.ident;
+				let name = attrs.rename.unwrap_or_else(|| ident.unraw().to_string());
+				let construct = fields_from_element(&variant.fields, quote!(Self::#ident), false)?;
+				match variant.fields
+				{
+					Fields::Unit => unit_arms.push(quote!(#name => return Ok(Self::#ident),)),
+					Fields::Unnamed(_) => array_arms.push(quote!(#name => return { #construct },)),
+					Fields::Named(_) => table_arms.push(quote!(#name => return { #construct },)),
+				}
+				names.push(name);
+			}
+			let unknown = format!(
+				"Unknown variant '{{}}', expected one of: {}.",
+				names.join(", ")
+			);
+			quote! {
+				if let Some(value) = element.as_value()
+				{
+					match &value[..]
+					{
+						#(#unit_arms)*
+						_ => (),
+					}
+					return Err(::slr_config::__error(element, source, &format!(#unknown, value)));
+				}
+				let tag = match element.tag()
+				{
+					Some(tag) => tag,
+					None => return Err(::slr_config::__error(
+						element,
+						source,
+						"Expected a value, a tagged array or a tagged table.",
+					)),
+				};
+				if element.as_array().is_some()
+				{
+					match &tag[..]
+					{
+						#(#array_arms)*
+						_ => (),
+					}
+				}
+				else
+				{
+					match &tag[..]
+					{
+						#(#table_arms)*
+						_ => (),
+					}
+				}
+				Err(::slr_config::__error(element, source, &format!(#unknown, tag)))
+			}
+		}
+		Data::Union(ref data) =>
+		{
+			return Err(Error::new(
+				data.union_token.span,
+				"FromElement cannot be derived for unions",
+			))
+		}
+	};
+
+	let merge = match input.data
+	{
+		Data::Struct(ref data) => merge_fields(&data.fields)?,
+		_ => None,
+	};
+
+	add_bounds(&mut input.generics, quote!(::slr_config::FromElement));
+	let name = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+	Ok(quote! {
+		impl #impl_generics ::slr_config::FromElement for #name #ty_generics #where_clause
+		{
+			fn from_element(
+				element: &::slr_config::ConfigElement,
+				source: ::std::option::Option<&::slr_config::Source>,
+			) -> ::std::result::Result<Self, ::slr_config::Error>
+			{
+				#body
+			}
+
+			#merge
+		}
+	})
+}
+
+/// Generates `merge_element` for structs with named fields, which reads the
+/// fields present in the table on top of the current ones. Other structs are
+/// replaced, like any other value.
+fn merge_fields(fields: &Fields) -> Result<Option<TokenStream2>, Error>
+{
+	let fields = match *fields
+	{
+		Fields::Named(ref fields) => fields,
+		_ => return Ok(None),
+	};
+	let mut merges = vec![];
+	for field in &fields.named
+	{
+		let attrs = SlrAttrs::from_attrs(&field.attrs)?;
+		if attrs.skip
+		{
+			continue;
+		}
+		let ident = field.ident.as_ref().unwrap();
+		let name = attrs.rename.unwrap_or_else(|| ident.unraw().to_string());
+		merges
+			.push(quote!(::slr_config::__merge_field(&mut self.#ident, element, source, #name)?;));
+	}
+	Ok(Some(quote! {
+		fn merge_element(
+			&mut self,
+			element: &::slr_config::ConfigElement,
+			source: ::std::option::Option<&::slr_config::Source>,
+		) -> ::std::result::Result<(), ::slr_config::Error>
+		{
+			if element.as_table().is_none()
+			{
+				return Err(::slr_config::__error(element, source, "Expected a table."));
+			}
+			#(#merges)*
+			Ok(())
+		}
+	}))
+}
+
+/// Generates the code that constructs `path` from `element`. Tables are
+/// read into named fields and arrays into unnamed fields. If `transparent`
+/// is set, a single unnamed field is read from the element itself.
+fn fields_from_element(
+	fields: &Fields, path: TokenStream2, transparent: bool,
+) -> Result<TokenStream2, Error>
+{
+	match *fields
+	{
+		Fields::Named(ref fields) =>
+		{
+			let mut inits = vec![];
+			for field in &fields.named
+			{
+				let attrs = SlrAttrs::from_attrs(&field.attrs)?;
+				let ident = field.ident.as_ref().unwrap();
+				if attrs.skip
+				{
+					inits.push(quote!(#ident: ::std::default::Default::default()));
+					continue;
+				}
+				let name = attrs.rename.unwrap_or_else(|| ident.unraw().to_string());
+				let default = if attrs.default
+				{
+					quote!(Some(::std::default::Default::default))
+				}
+				else
+				{
+					quote!(None)
+				};
+				inits
+					.push(quote!(#ident: ::slr_config::__field(element, source, #name, #default)?));
+			}
+			Ok(quote! {
+				if element.as_table().is_none()
+				{
+					return Err(::slr_config::__error(element, source, "Expected a table."));
+				}
+				Ok(#path { #(#inits),* })
+			})
+		}
+		Fields::Unnamed(ref fields) if transparent && fields.unnamed.len() == 1 =>
+		{
+			if SlrAttrs::from_attrs(&fields.unnamed[0].attrs)?.skip
+			{
+				return Ok(quote!(Ok(#path(::std::default::Default::default()))));
+			}
+			Ok(quote! {
+				Ok(#path(::slr_config::FromElement::from_element(element, source)?))
+			})
+		}
+		Fields::Unnamed(ref fields) =>
+		{
+			// Skipped fields are not written, so the array only has the others.
+			let mut inits = vec![];
+			let mut len = 0usize;
+			for field in &fields.unnamed
+			{
+				if SlrAttrs::from_attrs(&field.attrs)?.skip
+				{
+					inits.push(quote!(::std::default::Default::default()));
+				}
+				else
+				{
+					inits.push(
+						quote!(::slr_config::FromElement::from_element(&array[#len], source)?),
+					);
+					len += 1;
+				}
+			}
+			let wrong_len = format!("Expected an array with {} elements.", len);
+			Ok(quote! {
+				let array = match element.as_array()
+				{
+					Some(array) => array,
+					None => return Err(::slr_config::__error(element, source, "Expected an array.")),
+				};
+				if array.len() != #len
+				{
+					return Err(::slr_config::__error(element, source, #wrong_len));
+				}
+				Ok(#path(#(#inits),*))
+			})
+		}
+		Fields::Unit => Ok(quote! {
+			<() as ::slr_config::FromElement>::from_element(element, source)?;
+			Ok(#path)
+		}),
+	}
+}
+
+pub fn expand_to_element(mut input: DeriveInput) -> Result<TokenStream2, Error>
+{
+	let body = match input.data
+	{
+		Data::Struct(ref data) =>
+		{
+			let (pattern, construct) = fields_to_element(&data.fields, None)?;
+			quote! {
+				let Self #pattern = self;
+				#construct
+			}
+		}
+		Data::Enum(ref data) =>
+		{
+			let mut arms = vec![];
+			for variant in &data.variants
+			{
+				let attrs = SlrAttrs::from_attrs(&variant.attrs)?;
+				let ident = &variant.ident;
+				let name = attrs.rename.unwrap_or_else(|| ident.unraw().to_string());
+				if attrs.skip
+				{
+					return Err(Error::new(
+						ident.span(),
+						"#[slr(skip)] on variants is not supported by ToElement, since every \
+						 value must be convertible",
+					));
+				}
+				let (pattern, construct) = fields_to_element(&variant.fields, Some(&name))?;
+				arms.push(quote!(Self::#ident #pattern => { #construct }));
+			}
+			quote! {
+				match self
+				{
+					#(#arms)*
+				}
+			}
+		}
+		Data::Union(ref data) =>
+		{
+			return Err(Error::new(
+				data.union_token.span,
+				"ToElement cannot be derived for unions",
+			))
+		}
+	};
+
+	add_bounds(&mut input.generics, quote!(::slr_config::ToElement));
+	let name = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+	Ok(quote! {
+		impl #impl_generics ::slr_config::ToElement for #name #ty_generics #where_clause
+		{
+			#[allow(unused_variables)]
+			fn to_element(&self) -> ::slr_config::ConfigElement
+			{
+				#body
+			}
+		}
+	})
+}
+
+/// Generates a pattern that binds the fields, and the code that creates the
+/// element from them. If `tag` is set, tables and arrays are tagged with it and
+/// a unit variant is written as a value equal to the tag.
+fn fields_to_element(
+	fields: &Fields, tag: Option<&str>,
+) -> Result<(TokenStream2, TokenStream2), Error>
+{
+	let bindings: Vec<Ident> = (0..fields.len())
+		.map(|idx| format_ident!("__field{}", idx))
+		.collect();
+	let mut inserts = vec![];
+	for (field, binding) in fields.iter().zip(&bindings)
+	{
+		let attrs = SlrAttrs::from_attrs(&field.attrs)?;
+		if attrs.skip
+		{
+			continue;
+		}
+		let name = match (attrs.rename, &field.ident)
+		{
+			(Some(name), _) => name,
+			(None, Some(ident)) => ident.unraw().to_string(),
+			(None, None) => String::new(),
+		};
+		inserts.push(quote!(element.insert(#name, ::slr_config::ToElement::to_element(#binding));));
+	}
+	let pattern = match *fields
+	{
+		Fields::Named(ref fields) =>
+		{
+			let idents = fields.named.iter().map(|f| f.ident.as_ref().unwrap());
+			quote!({ #(#idents: #bindings),* })
+		}
+		Fields::Unnamed(_) =>
+		{
+			let indices = (0..fields.len()).map(Index::from);
+			quote!({ #(#indices: #bindings),* })
+		}
+		Fields::Unit => quote!(),
+	};
+	let construct = match (fields, tag)
+	{
+		(Fields::Named(_), None) => quote!(::slr_config::ConfigElement::new_table()),
+		(Fields::Named(_), Some(tag)) =>
+		{
+			quote!(::slr_config::ConfigElement::new_tagged_table(#tag.to_string()))
+		}
+		(Fields::Unnamed(fields), None) if fields.unnamed.len() == 1 =>
+		{
+			if SlrAttrs::from_attrs(&fields.unnamed[0].attrs)?.skip
+			{
+				return Ok((pattern, quote!(::slr_config::ConfigElement::new_value(""))));
+			}
+			let binding = &bindings[0];
+			return Ok((
+				pattern,
+				quote!(::slr_config::ToElement::to_element(#binding)),
+			));
+		}
+		(Fields::Unnamed(_), None) => quote!(::slr_config::ConfigElement::new_array()),
+		(Fields::Unnamed(_), Some(tag)) =>
+		{
+			quote!(::slr_config::ConfigElement::new_tagged_array(#tag.to_string()))
+		}
+		(Fields::Unit, None) =>
+		{
+			return Ok((pattern, quote!(::slr_config::ConfigElement::new_value(""))))
+		}
+		(Fields::Unit, Some(tag)) =>
+		{
+			return Ok((
+				pattern,
+				quote!(::slr_config::ConfigElement::new_value(#tag)),
+			))
+		}
+	};
+	Ok((
+		pattern,
+		quote! {
+			let mut element = #construct;
+			#(#inserts)*
+			element
+		},
+	))
+}
+
+/// Requires every type parameter to implement `bound`.
+fn add_bounds(generics: &mut Generics, bound: TokenStream2)
+{
+	let params: Vec<Ident> = generics.type_params().map(|p| p.ident.clone()).collect();
+	let where_clause = generics.make_where_clause();
+	for param in params
+	{
+		where_clause.predicates.push(parse_quote!(#param: #bound));
+	}
+}
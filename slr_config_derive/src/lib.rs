@@ -0,0 +1,423 @@
+// Copyright (c) 2014 by SiegeLord
+//
+// All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
+
+/*!
+This crate implements the derive macros of the `slr_config` crate, use them
+through the re-exports in that crate.
+
+`#[derive(SlrDocumented)]` captures the doc comments of a struct or an enum so
+that `slr_config::to_element_documented` can attach them as comments to the
+serialized configuration. Fields and variants are matched by their serialized
+names, taking the `rename`, `rename_all`, `skip`, `skip_serializing` and
+`flatten` Serde attributes into account. Fields whose types also implement
+`SlrDocumented` must be marked with `#[slr(nested)]` to have their
+documentation included.
+
+`#[derive(FromElement)]` and `#[derive(ToElement)]` implement the
+`slr_config::FromElement` and `slr_config::ToElement` traits. Fields and
+variants can be marked with `#[slr(rename = "name")]` and `#[slr(skip)]`,
+fields can also be marked with `#[slr(default)]`. Skipped fields are set to
+their default value when reading. Skipped variants cannot be read, and are
+rejected by `#[derive(ToElement)]` since they could not be written.
+*/
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::{
+	parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Expr, ExprLit, Fields,
+	Lit, LitStr, Meta, Type,
+};
+
+mod convert;
+
+#[proc_macro_derive(SlrDocumented, attributes(slr))]
+pub fn derive_slr_documented(input: TokenStream) -> TokenStream
+{
+	let input = parse_macro_input!(input as DeriveInput);
+	match expand(input)
+	{
+		Ok(tokens) => tokens.into(),
+		Err(error) => error.to_compile_error().into(),
+	}
+}
+
+#[proc_macro_derive(FromElement, attributes(slr))]
+pub fn derive_from_element(input: TokenStream) -> TokenStream
+{
+	let input = parse_macro_input!(input as DeriveInput);
+	match convert::expand_from_element(input)
+	{
+		Ok(tokens) => tokens.into(),
+		Err(error) => error.to_compile_error().into(),
+	}
+}
+
+#[proc_macro_derive(ToElement, attributes(slr))]
+pub fn derive_to_element(input: TokenStream) -> TokenStream
+{
+	let input = parse_macro_input!(input as DeriveInput);
+	match convert::expand_to_element(input)
+	{
+		Ok(tokens) => tokens.into(),
+		Err(error) => error.to_compile_error().into(),
+	}
+}
+
+fn expand(mut input: DeriveInput) -> Result<TokenStream2, Error>
+{
+	let container = SerdeAttrs::from_attrs(&input.attrs)?;
+	let mut nested_types = vec![];
+	let mut docs = match input.data
+	{
+		Data::Struct(ref data) => fields_docs(
+			&data.fields,
+			container.rename_all.as_deref(),
+			&mut nested_types,
+		)?,
+		Data::Enum(ref data) =>
+		{
+			let mut docs = quote!(::slr_config::Docs::new());
+			for variant in &data.variants
+			{
+				let attrs = SerdeAttrs::from_attrs(&variant.attrs)?;
+				let slr_attrs = SlrAttrs::from_attrs(&variant.attrs)?;
+				if attrs.skip || slr_attrs.skip
+				{
+					continue;
+				}
+				let name = match slr_attrs.rename.or(attrs.rename)
+				{
+					Some(name) => name,
+					None => rename(
+						&variant.ident.unraw().to_string(),
+						container.rename_all.as_deref(),
+						true,
+					),
+				};
+				let mut variant_docs = fields_docs(
+					&variant.fields,
+					attrs.rename_all.as_deref(),
+					&mut nested_types,
+				)?;
+				if let Some(doc) = doc_string(&variant.attrs)
+				{
+					variant_docs = quote!(#variant_docs.doc(#doc));
+				}
+				docs = quote!(#docs.variant(#name, #variant_docs));
+			}
+			docs
+		}
+		Data::Union(ref data) =>
+		{
+			return Err(Error::new(
+				data.union_token.span,
+				"SlrDocumented cannot be derived for unions",
+			))
+		}
+	};
+	if let Some(doc) = doc_string(&input.attrs)
+	{
+		docs = quote!(#docs.doc(#doc));
+	}
+
+	let where_clause = input.generics.make_where_clause();
+	for ty in nested_types
+	{
+		where_clause
+			.predicates
+			.push(parse_quote!(#ty: ::slr_config::SlrDocumented));
+	}
+	let name = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+	Ok(quote! {
+		impl #impl_generics ::slr_config::SlrDocumented for #name #ty_generics #where_clause
+		{
+			fn docs() -> ::slr_config::Docs
+			{
+				#docs
+			}
+		}
+	})
+}
+
+/// Generates the documentation of the fields of a struct or a variant.
+fn fields_docs(
+	fields: &Fields, rename_all: Option<&str>, nested_types: &mut Vec<Type>,
+) -> Result<TokenStream2, Error>
+{
+	let mut docs = quote!(::slr_config::Docs::new());
+	let is_newtype = match *fields
+	{
+		Fields::Unnamed(ref fields) => fields.unnamed.len() == 1,
+		_ => false,
+	};
+	for (idx, field) in fields.iter().enumerate()
+	{
+		let attrs = SerdeAttrs::from_attrs(&field.attrs)?;
+		let slr_attrs = SlrAttrs::from_attrs(&field.attrs)?;
+		if attrs.skip || slr_attrs.skip
+		{
+			continue;
+		}
+		let mut field_docs = quote!(::slr_config::Docs::new());
+		if slr_attrs.nested
+		{
+			let ty = &field.ty;
+			field_docs = quote!(<#ty as ::slr_config::SlrDocumented>::docs());
+			nested_types.push(ty.clone());
+		}
+		if attrs.flatten
+		{
+			docs = quote!(#docs.flatten(#field_docs));
+			continue;
+		}
+		if let Some(doc) = doc_string(&field.attrs)
+		{
+			field_docs = quote!(#field_docs.doc(#doc));
+		}
+		if is_newtype
+		{
+			docs = quote!(#docs.newtype(#field_docs));
+			continue;
+		}
+		let name = match (slr_attrs.rename.or(attrs.rename), &field.ident)
+		{
+			(Some(name), _) => name,
+			(None, Some(ident)) => rename(&ident.unraw().to_string(), rename_all, false),
+			(None, None) => idx.to_string(),
+		};
+		docs = quote!(#docs.field(#name, #field_docs));
+	}
+	Ok(docs)
+}
+
+/// Joins the `///` comments into a single string, removing the leading space
+/// of each line.
+fn doc_string(attrs: &[Attribute]) -> Option<String>
+{
+	let mut lines = vec![];
+	for attr in attrs
+	{
+		if !attr.path().is_ident("doc")
+		{
+			continue;
+		}
+		if let Meta::NameValue(ref meta) = attr.meta
+		{
+			if let Expr::Lit(ExprLit {
+				lit: Lit::Str(ref lit),
+				..
+			}) = meta.value
+			{
+				let line = lit.value();
+				for line in line.lines()
+				{
+					lines.push(
+						line.strip_prefix(' ')
+							.unwrap_or(line)
+							.trim_end()
+							.to_string(),
+					);
+				}
+			}
+		}
+	}
+	while lines.last().is_some_and(|l| l.is_empty())
+	{
+		lines.pop();
+	}
+	while lines.first().is_some_and(|l| l.is_empty())
+	{
+		lines.remove(0);
+	}
+	if lines.is_empty()
+	{
+		None
+	}
+	else
+	{
+		Some(lines.join("\n"))
+	}
+}
+
+/// The attributes in `#[slr(...)]`.
+#[derive(Default)]
+struct SlrAttrs
+{
+	/// The field's type implements `SlrDocumented`.
+	nested: bool,
+	rename: Option<String>,
+	/// Use `Default::default()` when the field is absent.
+	default: bool,
+	skip: bool,
+}
+
+impl SlrAttrs
+{
+	fn from_attrs(attrs: &[Attribute]) -> Result<SlrAttrs, Error>
+	{
+		let mut ret = SlrAttrs::default();
+		for attr in attrs
+		{
+			if !attr.path().is_ident("slr")
+			{
+				continue;
+			}
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("nested")
+				{
+					ret.nested = true;
+				}
+				else if meta.path.is_ident("rename")
+				{
+					ret.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+				}
+				else if meta.path.is_ident("default")
+				{
+					ret.default = true;
+				}
+				else if meta.path.is_ident("skip")
+				{
+					ret.skip = true;
+				}
+				else
+				{
+					return Err(meta.error("unknown slr attribute"));
+				}
+				Ok(())
+			})?;
+		}
+		Ok(ret)
+	}
+}
+
+/// The Serde attributes that affect the serialized names.
+#[derive(Default)]
+struct SerdeAttrs
+{
+	rename: Option<String>,
+	rename_all: Option<String>,
+	skip: bool,
+	flatten: bool,
+}
+
+impl SerdeAttrs
+{
+	fn from_attrs(attrs: &[Attribute]) -> Result<SerdeAttrs, Error>
+	{
+		let mut ret = SerdeAttrs::default();
+		for attr in attrs
+		{
+			if !attr.path().is_ident("serde")
+			{
+				continue;
+			}
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("rename") || meta.path.is_ident("rename_all")
+				{
+					let name = if meta.input.peek(syn::Token![=])
+					{
+						Some(meta.value()?.parse::<LitStr>()?.value())
+					}
+					else
+					{
+						// rename(serialize = "...", deserialize = "...")
+						let mut name = None;
+						meta.parse_nested_meta(|inner| {
+							let value = inner.value()?.parse::<LitStr>()?.value();
+							if inner.path.is_ident("serialize")
+							{
+								name = Some(value);
+							}
+							Ok(())
+						})?;
+						name
+					};
+					if meta.path.is_ident("rename")
+					{
+						ret.rename = name;
+					}
+					else
+					{
+						ret.rename_all = name;
+					}
+				}
+				else if meta.path.is_ident("skip") || meta.path.is_ident("skip_serializing")
+				{
+					ret.skip = true;
+				}
+				else if meta.path.is_ident("flatten")
+				{
+					ret.flatten = true;
+				}
+				else if meta.input.peek(syn::Token![=])
+				{
+					meta.value()?.parse::<Expr>()?;
+				}
+				else if !meta.input.is_empty() && !meta.input.peek(syn::Token![,])
+				{
+					meta.parse_nested_meta(|inner| {
+						if inner.input.peek(syn::Token![=])
+						{
+							inner.value()?.parse::<Expr>()?;
+						}
+						Ok(())
+					})?;
+				}
+				Ok(())
+			})?;
+		}
+		Ok(ret)
+	}
+}
+
+/// Applies a Serde `rename_all` rule to a field or a variant name.
+fn rename(name: &str, rule: Option<&str>, is_variant: bool) -> String
+{
+	// Variants are written in PascalCase, fields in snake_case.
+	let words: Vec<String> = if is_variant
+	{
+		let mut words = vec![];
+		for c in name.chars()
+		{
+			if c.is_uppercase() || words.is_empty()
+			{
+				words.push(String::new());
+			}
+			words.last_mut().unwrap().push(c.to_ascii_lowercase());
+		}
+		words
+	}
+	else
+	{
+		name.split('_').map(|w| w.to_string()).collect()
+	};
+	let capitalize = |w: &String| {
+		let mut chars = w.chars();
+		match chars.next()
+		{
+			Some(c) => c.to_uppercase().chain(chars).collect(),
+			None => String::new(),
+		}
+	};
+	match rule
+	{
+		Some("lowercase") => name.to_ascii_lowercase(),
+		Some("UPPERCASE") => name.to_ascii_uppercase(),
+		Some("PascalCase") => words.iter().map(capitalize).collect(),
+		Some("camelCase") =>
+		{
+			let mut ret = words[0].clone();
+			ret.extend(words[1..].iter().map(capitalize));
+			ret
+		}
+		Some("snake_case") => words.join("_"),
+		Some("SCREAMING_SNAKE_CASE") => words.join("_").to_uppercase(),
+		Some("kebab-case") => words.join("-"),
+		Some("SCREAMING-KEBAB-CASE") => words.join("-").to_uppercase(),
+		_ => name.to_string(),
+	}
+}
@@ -6,16 +6,39 @@ use serde::{de, ser};
 use std;
 use std::cmp::{max, min};
 use std::fmt::{self, Display};
+use std::io;
 use std::path::Path;
 use std::str::CharIndices;
 use std::usize;
 
+/// How a string is written: naked, or in quotes surrounded by the given number
+/// of braces. With no braces, the string is an escaped string, with two or
+/// more it is a raw string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum StringQuoteType
 {
 	Naked,
 	Quoted(usize),
 }
 
+impl StringQuoteType
+{
+	/// Returns whether `s` can be written with this quote type as is, without
+	/// escaping any characters.
+	pub fn can_represent(&self, s: &str) -> bool
+	{
+		match *self
+		{
+			StringQuoteType::Naked => get_string_quote_type(s) == StringQuoteType::Naked,
+			StringQuoteType::Quoted(0) => !s.contains('"') && !s.contains('\\'),
+			StringQuoteType::Quoted(num_braces) =>
+			{
+				num_braces >= 2 && !s.contains(&format!("\"{}", "}".repeat(num_braces)))
+			}
+		}
+	}
+}
+
 pub fn get_string_quote_type(s: &str) -> StringQuoteType
 {
 	if s.is_empty()
@@ -36,7 +59,7 @@ pub fn get_string_quote_type(s: &str) -> StringQuoteType
 		{
 			naked = false;
 		}
-		if i > 1 && i < s.len() - 1 && !is_string_middle(c)
+		if i > 0 && i < s.len() - 1 && !is_string_middle(c)
 		{
 			naked = false;
 		}
@@ -88,7 +111,7 @@ fn grow_str(string: &mut String, count: usize, ch: char)
 }
 
 /// Type representing a certain sub-section of the source.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Span
 {
 	start: usize,
@@ -105,11 +128,38 @@ impl Span
 		}
 	}
 
+	/// Creates a span covering the bytes between `start` and `end`, or returns
+	/// `None` if `end` is before `start`. Passing the `start` and `end` of an
+	/// invalid span creates an invalid span.
+	pub fn from_start_end(start: usize, end: usize) -> Option<Span>
+	{
+		if end < start
+		{
+			return None;
+		}
+		Some(Span {
+			start,
+			len: end - start,
+		})
+	}
+
 	pub fn is_valid(&self) -> bool
 	{
 		self.start != usize::MAX
 	}
 
+	/// Returns the byte offset where this span starts.
+	pub fn start(&self) -> usize
+	{
+		self.start
+	}
+
+	/// Returns the byte offset one past where this span ends.
+	pub fn end(&self) -> usize
+	{
+		self.start + self.len
+	}
+
 	pub fn combine(&mut self, other: Span)
 	{
 		if !self.is_valid()
@@ -240,6 +290,12 @@ impl<'l> Source<'l>
 		src
 	}
 
+	/// Returns the filename this source was created with.
+	pub fn filename(&self) -> &'l Path
+	{
+		self.filename
+	}
+
 	fn reset(&mut self)
 	{
 		*self = Source::new(self.filename, self.source);
@@ -400,6 +456,11 @@ pub enum ErrorKind
 	/// A custom error available to 3rd party implementors. The semantics are
 	/// defined by the 3rd party.
 	Custom(i32),
+	/// An I/O error occured while reading or writing a configuration.
+	Io,
+	/// A path to an element could not be parsed, or did not lead to an
+	/// element.
+	InvalidPath,
 }
 
 /// The error type used throughout this crate.
@@ -535,6 +596,14 @@ impl ser::Error for Error
 	}
 }
 
+impl From<io::Error> for Error
+{
+	fn from(error: io::Error) -> Self
+	{
+		Error::new(ErrorKind::Io, format!("error: {}\n", error))
+	}
+}
+
 impl de::Error for Error
 {
 	fn custom<T: Display>(msg: T) -> Self
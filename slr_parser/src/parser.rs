@@ -2,9 +2,10 @@
 //
 // All rights reserved. Distributed under LGPL 3.0. For full terms see the file LICENSE.
 
-use crate::lexer::{Error, ErrorKind, Lexer, Source, Span, Token, TokenKind};
+use crate::lexer::{Error, ErrorKind, Lexer, Source, Span, StringQuoteType, Token, TokenKind};
 use crate::visitor::Visitor;
 use std::char;
+use std::collections::VecDeque;
 use std::u32;
 
 #[derive(Clone, Copy, Debug)]
@@ -40,7 +41,9 @@ impl<'l> ConfigString<'l>
 		}
 	}
 
-	fn from_token(tok: Token<'l>) -> ConfigString<'l>
+	/// Creates a string from a string token. Panics if the token is not a
+	/// string.
+	pub fn from_token(tok: Token<'l>) -> ConfigString<'l>
 	{
 		let kind = match tok.kind
 		{
@@ -55,6 +58,23 @@ impl<'l> ConfigString<'l>
 		}
 	}
 
+	/// Returns how this string was written in the source, or `None` if it
+	/// was not read from a source.
+	pub fn quote_type(&self) -> Option<StringQuoteType>
+	{
+		if !self.span.is_valid()
+		{
+			return None;
+		}
+		let len = self.span.end() - self.span.start();
+		Some(match self.kind
+		{
+			StringKind::EscapedString(s) if s.len() == len => StringQuoteType::Naked,
+			StringKind::EscapedString(_) => StringQuoteType::Quoted(0),
+			StringKind::RawString(s) => StringQuoteType::Quoted((len - s.len() - 2) / 2),
+		})
+	}
+
 	pub fn append_to_string(&self, dest: &mut String)
 	{
 		match self.kind
@@ -135,12 +155,80 @@ impl<'l> ConfigString<'l>
 	}
 }
 
-struct Parser<'l, 's, 'm, V: 'm>
+/// An event produced by `EventParser`. The events correspond to the methods of
+/// `Visitor`.
+#[derive(Clone, Copy, Debug)]
+pub enum Event<'l>
+{
+	/// Start of an element, with its name. Elements of arrays have an empty
+	/// name.
+	StartElement(ConfigString<'l>),
+	/// End of the most recently started element, with the extent of its value.
+	EndElement(Span),
+	/// The current element is a table.
+	Table(Span),
+	/// The current element is a tagged table.
+	TaggedTable(Span, ConfigString<'l>),
+	/// The current element is an array.
+	Array(Span),
+	/// The current element is a tagged array.
+	TaggedArray(Span, ConfigString<'l>),
+	/// A string is appended to the current element.
+	String(ConfigString<'l>),
+	/// An expansion of a previously defined element is appended to the
+	/// current element.
+	Expand(ConfigString<'l>),
+}
+
+/// What the parser expects to see next.
+enum Frame
+{
+	/// Inside a table (the root table if `left_brace` is not set), expecting
+	/// an element or the end of the table.
+	Table
+	{
+		left_brace: Option<Span>
+	},
+	/// Inside a table, after an element.
+	TableComma
+	{
+		left_brace: Option<Span>
+	},
+	/// Inside an array, expecting an element or the end of the array.
+	Array
+	{
+		left_bracket: Span
+	},
+	/// Inside an array, after an element.
+	ArrayComma
+	{
+		left_bracket: Span
+	},
+	/// Inside a string expression, expecting a string or an expansion.
+	StringExpr
+	{
+		last_span: Option<Span>
+	},
+	/// Inside a string expression, after a string or an expansion.
+	StringTilde,
+	/// The value of an element, starting at `start`, was parsed.
+	EndElement
+	{
+		start: usize
+	},
+}
+
+/// A pull parser, which parses a source into a stream of events.
+pub struct EventParser<'l, 's>
 where
 	's: 'l,
 {
 	lexer: Lexer<'l, 's>,
-	visitor: &'m mut V,
+	stack: Vec<Frame>,
+	events: VecDeque<Event<'s>>,
+	error: Option<Error>,
+	/// The end of the last consumed token.
+	last_end: usize,
 }
 
 macro_rules! get_token {
@@ -167,8 +255,53 @@ macro_rules! try_eof {
 	};
 }
 
-impl<'l, 's, 'm, V: Visitor<'l>> Parser<'l, 's, 'm, V>
+impl<'l, 's> EventParser<'l, 's>
 {
+	/// Creates a new parser. The source will be reset by this operation, and
+	/// must not be used with any spans created from a previous parsing done
+	/// with that source.
+	pub fn new(source: &'l mut Source<'s>) -> EventParser<'l, 's>
+	{
+		let mut lexer = Lexer::new(source);
+		lexer.next();
+		EventParser {
+			lexer,
+			stack: vec![Frame::Table { left_brace: None }],
+			events: VecDeque::new(),
+			error: None,
+			last_end: 0,
+		}
+	}
+
+	/// Returns the source being parsed.
+	pub fn get_source(&self) -> &Source<'s>
+	{
+		self.lexer.get_source()
+	}
+
+	/// Returns the next event, or `None` once the whole source was parsed.
+	/// After an error is returned, the parser must not be used anymore.
+	pub fn next_event(&mut self) -> Result<Option<Event<'s>>, Error>
+	{
+		while self.events.is_empty() && self.error.is_none() && !self.stack.is_empty()
+		{
+			if let Err(error) = self.step()
+			{
+				self.stack.clear();
+				self.error = Some(error);
+			}
+		}
+		match self.events.pop_front()
+		{
+			Some(event) => Ok(Some(event)),
+			None => match self.error.take()
+			{
+				Some(error) => Err(error),
+				None => Ok(None),
+			},
+		}
+	}
+
 	fn parse_error<T>(&self, span: Span, msg: &str) -> Result<T, Error>
 	{
 		Err(Error::from_span(
@@ -179,6 +312,81 @@ impl<'l, 's, 'm, V: Visitor<'l>> Parser<'l, 's, 'm, V>
 		))
 	}
 
+	/// Consumes the current token, returning the next one.
+	fn advance(&mut self) -> Option<Result<Token<'s>, Error>>
+	{
+		if let Some(Ok(ref token)) = self.lexer.cur_token
+		{
+			self.last_end = token.span.end();
+		}
+		self.lexer.next()
+	}
+
+	fn replace_top(&mut self, frame: Frame)
+	{
+		*self.stack.last_mut().unwrap() = frame;
+	}
+
+	fn step(&mut self) -> Result<(), Error>
+	{
+		match *self.stack.last().unwrap()
+		{
+			Frame::Table { left_brace } => self.parse_table_element(left_brace),
+			Frame::TableComma { left_brace } =>
+			{
+				let comma = try_eof!(self.lexer.cur_token, {
+					self.replace_top(Frame::Table { left_brace });
+					Ok(())
+				});
+				if comma.kind == TokenKind::Comma
+				{
+					self.advance();
+				}
+				self.replace_top(Frame::Table { left_brace });
+				Ok(())
+			}
+			Frame::Array { left_bracket } => self.parse_array_element(left_bracket),
+			Frame::ArrayComma { left_bracket } =>
+			{
+				let comma = try_eof!(self.lexer.cur_token, self.end_array(left_bracket));
+				if comma.kind != TokenKind::Comma
+				{
+					return self.end_array(left_bracket);
+				}
+				self.advance();
+				self.replace_top(Frame::Array { left_bracket });
+				Ok(())
+			}
+			Frame::StringExpr { last_span } => self.parse_string_expr(last_span),
+			Frame::StringTilde =>
+			{
+				let tilde = try_eof!(self.lexer.cur_token, {
+					self.stack.pop();
+					Ok(())
+				});
+				if tilde.kind != TokenKind::Tilde
+				{
+					self.stack.pop();
+					return Ok(());
+				}
+				self.advance();
+				self.replace_top(Frame::StringExpr {
+					last_span: Some(tilde.span),
+				});
+				Ok(())
+			}
+			Frame::EndElement { start } =>
+			{
+				self.stack.pop();
+				// Tokens are consumed in order, so the end is never before the start.
+				let span = Span::from_start_end(start, self.last_end).unwrap();
+				self.events.push_back(Event::EndElement(span));
+				Ok(())
+			}
+		}
+	}
+
+	/// Tries to start a tagged array, returns false if there isn't one.
 	fn parse_tagged_array(&mut self) -> Result<bool, Error>
 	{
 		let tag = try_eof!(self.lexer.cur_token, Ok(false));
@@ -191,15 +399,13 @@ impl<'l, 's, 'm, V: Visitor<'l>> Parser<'l, 's, 'm, V>
 		{
 			return Ok(false);
 		}
-		self.lexer.next();
-		self.visitor.set_tagged_array(
-			self.lexer.get_source(),
-			tag.span,
-			ConfigString::from_token(tag),
-		)?;
+		self.advance();
+		self.events
+			.push_back(Event::TaggedArray(tag.span, ConfigString::from_token(tag)));
 		self.parse_array(true)
 	}
 
+	/// Tries to start a tagged table, returns false if there isn't one.
 	fn parse_tagged_table(&mut self) -> Result<bool, Error>
 	{
 		let tag = try_eof!(self.lexer.cur_token, Ok(false));
@@ -212,15 +418,13 @@ impl<'l, 's, 'm, V: Visitor<'l>> Parser<'l, 's, 'm, V>
 		{
 			return Ok(false);
 		}
-		self.lexer.next();
-		self.visitor.set_tagged_table(
-			self.lexer.get_source(),
-			tag.span,
-			ConfigString::from_token(tag),
-		)?;
+		self.advance();
+		self.events
+			.push_back(Event::TaggedTable(tag.span, ConfigString::from_token(tag)));
 		self.parse_table(true)
 	}
 
+	/// Tries to start a table, returns false if there isn't one.
 	fn parse_table(&mut self, is_tagged: bool) -> Result<bool, Error>
 	{
 		let left_brace = try_eof!(self.lexer.cur_token, Ok(false));
@@ -228,114 +432,114 @@ impl<'l, 's, 'm, V: Visitor<'l>> Parser<'l, 's, 'm, V>
 		{
 			return Ok(false);
 		}
-		self.lexer.next();
+		self.advance();
 		if !is_tagged
 		{
-			self.visitor
-				.set_table(self.lexer.get_source(), left_brace.span)?;
+			self.events.push_back(Event::Table(left_brace.span));
 		}
-		self.parse_table_contents()?;
-		let right_brace = try_eof!(
-			self.lexer.cur_token,
-			self.parse_error(left_brace.span, "Unterminated table")
+		self.stack.push(Frame::Table {
+			left_brace: Some(left_brace.span),
+		});
+		Ok(true)
+	}
+
+	fn parse_table_element(&mut self, left_brace: Option<Span>) -> Result<(), Error>
+	{
+		let token = get_token!(self.lexer.cur_token);
+		let token = match token
+		{
+			Some(token) if token.kind.is_string() => token,
+			_ => return self.end_table(left_brace),
+		};
+
+		self.events
+			.push_back(Event::StartElement(ConfigString::from_token(token)));
+		self.replace_top(Frame::TableComma { left_brace });
+
+		let assign = try_eof!(
+			self.advance(),
+			self.parse_error(token.span, "Expected '=' or '{' to follow, but got EOF")
 		);
-		if right_brace.kind != TokenKind::RightBrace
+		if assign.kind == TokenKind::Assign
 		{
-			let error_str = if right_brace.kind == TokenKind::Comma
+			let value = try_eof!(
+				self.advance(),
+				self.parse_error(
+					assign.span,
+					"Expected '[' or a string to follow, but got EOF"
+				)
+			);
+			self.stack.push(Frame::EndElement {
+				start: value.span.start(),
+			});
+			if self.parse_array(false)?
+				|| self.parse_tagged_array()?
+				|| self.parse_tagged_table()?
+				|| self.parse_string_expr_start()?
 			{
-				"Expected '}' or a string"
+				Ok(())
 			}
 			else
 			{
-				"Expected '}', ',' or a string"
-			};
-			self.parse_error(right_brace.span, error_str)
+				self.parse_error(value.span, "Expected '[' or a string")
+			}
 		}
 		else
 		{
-			self.lexer.next();
-			Ok(true)
-		}
-	}
-
-	fn parse_table_contents(&mut self) -> Result<(), Error>
-	{
-		while self.parse_table_element()?
-		{
-			let comma = try_eof!(self.lexer.cur_token, Ok(()));
-			if comma.kind == TokenKind::Comma
+			self.stack.push(Frame::EndElement {
+				start: assign.span.start(),
+			});
+			if self.parse_table(false)?
 			{
-				self.lexer.next();
+				Ok(())
+			}
+			else
+			{
+				self.parse_error(assign.span, "Expected '=' or '{'")
 			}
 		}
-
-		/* Error checking will be done by the calling code */
-		Ok(())
 	}
 
-	fn parse_table_element(&mut self) -> Result<bool, Error>
+	fn end_table(&mut self, left_brace: Option<Span>) -> Result<(), Error>
 	{
-		let token = try_eof!(self.lexer.cur_token, Ok(false));
-		let ret = if token.kind.is_string()
+		self.stack.pop();
+		let left_brace = match left_brace
 		{
-			self.visitor
-				.start_element(self.lexer.get_source(), ConfigString::from_token(token))?;
-
-			let assign = try_eof!(
-				self.lexer.next(),
-				self.parse_error(token.span, "Expected '=' or '{' to follow, but got EOF")
-			);
-			if assign.kind == TokenKind::Assign
+			Some(left_brace) => left_brace,
+			None =>
 			{
-				self.lexer.next();
-				if self.parse_array(false)?
-				{
-					true
-				}
-				else if self.parse_tagged_array()?
-				{
-					true
-				}
-				else if self.parse_tagged_table()?
-				{
-					true
-				}
-				else if self.parse_string_expr()?
-				{
-					true
-				}
-				else
+				// This is the root table.
+				return match get_token!(self.lexer.cur_token)
 				{
-					let token = try_eof!(
-						self.lexer.cur_token,
-						self.parse_error(
-							assign.span,
-							"Expected '[' or a string to follow, but got EOF"
-						)
-					);
-					return self.parse_error(token.span, "Expected '[' or a string");
-				}
+					Some(token) => self.parse_error(token.span, "Expected a string"),
+					None => Ok(()),
+				};
 			}
-			else if self.parse_table(false)?
+		};
+		let right_brace = try_eof!(
+			self.lexer.cur_token,
+			self.parse_error(left_brace, "Unterminated table")
+		);
+		if right_brace.kind != TokenKind::RightBrace
+		{
+			let error_str = if right_brace.kind == TokenKind::Comma
 			{
-				true
+				"Expected '}' or a string"
 			}
 			else
 			{
-				return self.parse_error(assign.span, "Expected '=' or '{'");
-			}
+				"Expected '}', ',' or a string"
+			};
+			self.parse_error(right_brace.span, error_str)
 		}
 		else
 		{
-			false
-		};
-		if ret
-		{
-			self.visitor.end_element()?;
+			self.advance();
+			Ok(())
 		}
-		Ok(ret)
 	}
 
+	/// Tries to start an array, returns false if there isn't one.
 	fn parse_array(&mut self, is_tagged: bool) -> Result<bool, Error>
 	{
 		let left_bracket = try_eof!(self.lexer.cur_token, Ok(false));
@@ -343,16 +547,61 @@ impl<'l, 's, 'm, V: Visitor<'l>> Parser<'l, 's, 'm, V>
 		{
 			return Ok(false);
 		}
-		self.lexer.next();
+		self.advance();
 		if !is_tagged
 		{
-			self.visitor
-				.set_array(self.lexer.get_source(), left_bracket.span)?;
+			self.events.push_back(Event::Array(left_bracket.span));
+		}
+		self.stack.push(Frame::Array {
+			left_bracket: left_bracket.span,
+		});
+		Ok(true)
+	}
+
+	fn parse_array_element(&mut self, left_bracket: Span) -> Result<(), Error>
+	{
+		let token = match get_token!(self.lexer.cur_token)
+		{
+			Some(token) => token,
+			None => return self.end_array(left_bracket),
+		};
+		let is_element = token.kind.is_string()
+			|| token.kind == TokenKind::Dollar
+			|| token.kind == TokenKind::LeftBrace
+			|| token.kind == TokenKind::LeftBracket;
+		if !is_element
+		{
+			return self.end_array(left_bracket);
+		}
+
+		self.events
+			.push_back(Event::StartElement(ConfigString::new()));
+		self.replace_top(Frame::ArrayComma { left_bracket });
+		self.stack.push(Frame::EndElement {
+			start: token.span.start(),
+		});
+
+		if token.kind == TokenKind::LeftBrace
+		{
+			self.parse_table(false)?;
 		}
-		self.parse_array_contents()?;
+		else if token.kind == TokenKind::LeftBracket
+		{
+			self.parse_array(false)?;
+		}
+		else if !self.parse_tagged_array()? && !self.parse_tagged_table()?
+		{
+			self.parse_string_expr_start()?;
+		}
+		Ok(())
+	}
+
+	fn end_array(&mut self, left_bracket: Span) -> Result<(), Error>
+	{
+		self.stack.pop();
 		let right_bracket = try_eof!(
 			self.lexer.cur_token,
-			self.parse_error(left_bracket.span, "Unterminated array")
+			self.parse_error(left_bracket, "Unterminated array")
 		);
 		if right_bracket.kind != TokenKind::RightBracket
 		{
@@ -368,131 +617,71 @@ impl<'l, 's, 'm, V: Visitor<'l>> Parser<'l, 's, 'm, V>
 		}
 		else
 		{
-			self.lexer.next();
-			Ok(true)
+			self.advance();
+			Ok(())
 		}
 	}
 
-	fn parse_array_contents(&mut self) -> Result<(), Error>
+	/// Tries to start a string expression, returns false if there isn't one.
+	fn parse_string_expr_start(&mut self) -> Result<bool, Error>
 	{
-		while self.parse_array_element()?
+		let token = try_eof!(self.lexer.cur_token, Ok(false));
+		if token.kind.is_string() || token.kind == TokenKind::Dollar
 		{
-			let comma = try_eof!(self.lexer.cur_token, Ok(()));
-			if comma.kind != TokenKind::Comma
-			{
-				break;
-			}
-			self.lexer.next();
+			self.stack.push(Frame::StringExpr { last_span: None });
+			Ok(true)
+		}
+		else
+		{
+			Ok(false)
 		}
-		/* Error checking will be done by the calling code */
-		Ok(())
 	}
 
-	fn parse_array_element(&mut self) -> Result<bool, Error>
+	fn parse_string_expr(&mut self, last_span: Option<Span>) -> Result<(), Error>
 	{
-		let token = try_eof!(self.lexer.cur_token, Ok(false));
-		let ret = if token.kind.is_string() || token.kind == TokenKind::Dollar
+		let expected_string = |parser: &Self| match last_span
 		{
-			self.visitor
-				.start_element(self.lexer.get_source(), ConfigString::new())?;
-			if self.parse_tagged_array()?
-			{
-				true
-			}
-			else if self.parse_tagged_table()?
+			Some(span) =>
 			{
-				true
+				parser.parse_error(span, "Expected a string or '$' to follow, but got EOF")
 			}
-			else
+			None =>
 			{
-				self.parse_string_expr()?
+				// Can't happen, as the first token is checked by
+				// `parse_string_expr_start`.
+				unreachable!()
 			}
-		}
-		else if token.kind == TokenKind::LeftBrace
-		{
-			self.visitor
-				.start_element(self.lexer.get_source(), ConfigString::new())?;
-			self.parse_table(false)?
-		}
-		else if token.kind == TokenKind::LeftBracket
-		{
-			self.visitor
-				.start_element(self.lexer.get_source(), ConfigString::new())?;
-			self.parse_array(false)?
-		}
-		else
-		{
-			false
 		};
-		if ret
+		let token = try_eof!(self.lexer.cur_token, expected_string(self));
+		if token.kind.is_string()
 		{
-			self.visitor.end_element()?;
+			self.events
+				.push_back(Event::String(ConfigString::from_token(token)));
+			self.advance();
 		}
-		Ok(ret)
-	}
-
-	fn parse_string_expr(&mut self) -> Result<bool, Error>
-	{
-		let mut last_span = None;
-		loop
+		else if token.kind == TokenKind::Dollar
 		{
-			let token = try_eof!(
-				self.lexer.cur_token,
-				match last_span
-				{
-					Some(span) =>
-					{
-						self.parse_error(span, "Expected a string or '$' to follow, but got EOF")
-					}
-					None => Ok(false),
-				}
+			let string_token = try_eof!(
+				self.advance(),
+				self.parse_error(token.span, "Expected a string to follow, but got EOF")
 			);
-			if token.kind.is_string()
+			if string_token.kind.is_string()
 			{
-				self.visitor
-					.append_string(self.lexer.get_source(), ConfigString::from_token(token))?;
-				self.lexer.next();
-			}
-			else if token.kind == TokenKind::Dollar
-			{
-				let string_token = try_eof!(
-					self.lexer.next(),
-					self.parse_error(token.span, "Expected a string to follow, but got EOF")
-				);
-				if string_token.kind.is_string()
-				{
-					self.visitor.expand(
-						self.lexer.get_source(),
-						ConfigString::from_token(string_token),
-					)?;
-					self.lexer.next();
-				}
-				else
-				{
-					return self.parse_error(string_token.span, "Expected a string");
-				}
+				self.events
+					.push_back(Event::Expand(ConfigString::from_token(string_token)));
+				self.advance();
 			}
 			else
 			{
-				match last_span
-				{
-					Some(span) =>
-					{
-						return self
-							.parse_error(span, "Expected a string or '$' to follow, but got EOF");
-					}
-					None => return Ok(false),
-				}
-			}
-
-			let tilde = try_eof!(self.lexer.cur_token, Ok(true));
-			if tilde.kind != TokenKind::Tilde
-			{
-				return Ok(true);
+				return self.parse_error(string_token.span, "Expected a string");
 			}
-			self.lexer.next();
-			last_span = Some(tilde.span);
 		}
+		else
+		{
+			return expected_string(self);
+		}
+		self.replace_top(Frame::StringTilde);
+		Ok(())
 	}
 }
 
@@ -500,16 +689,21 @@ pub fn parse_source<'l, 'm, V: Visitor<'m>>(
 	source: &'m mut Source<'l>, visitor: &mut V,
 ) -> Result<(), Error>
 {
-	let mut lexer = Lexer::new(source);
-	lexer.next();
-	let mut parser = Parser {
-		lexer: lexer,
-		visitor: visitor,
-	};
-	parser.parse_table_contents()?;
-	match get_token!(parser.lexer.cur_token)
+	let mut parser = EventParser::new(source);
+	while let Some(event) = parser.next_event()?
 	{
-		Some(token) => parser.parse_error(token.span, "Expected a string"),
-		None => Ok(()),
+		let src = parser.get_source();
+		match event
+		{
+			Event::StartElement(name) => visitor.start_element(src, name)?,
+			Event::EndElement(extent) => visitor.end_element(src, extent)?,
+			Event::Table(span) => visitor.set_table(src, span)?,
+			Event::TaggedTable(span, tag) => visitor.set_tagged_table(src, span, tag)?,
+			Event::Array(span) => visitor.set_array(src, span)?,
+			Event::TaggedArray(span, tag) => visitor.set_tagged_array(src, span, tag)?,
+			Event::String(string) => visitor.append_string(src, string)?,
+			Event::Expand(name) => visitor.expand(src, name)?,
+		}
 	}
+	Ok(())
 }
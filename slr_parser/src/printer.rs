@@ -14,6 +14,9 @@ pub struct Printer<'l, W: 'l>
 	one_line: Vec<bool>,
 	is_empty: Vec<bool>,
 	in_root: bool,
+	comment: Option<String>,
+	key_quote_type: Option<StringQuoteType>,
+	quote_type: Option<StringQuoteType>,
 }
 
 impl<'l, W: io::Write> Printer<'l, W>
@@ -27,6 +30,9 @@ impl<'l, W: io::Write> Printer<'l, W>
 			one_line: vec![false],
 			is_empty: vec![true],
 			in_root: false,
+			comment: None,
+			key_quote_type: None,
+			quote_type: None,
 		}
 	}
 
@@ -60,18 +66,45 @@ impl<'l, W: io::Write> Printer<'l, W>
 		self.is_empty[l - 1] = empty;
 	}
 
+	fn write_comment(&mut self, comment: &str) -> Result<(), io::Error>
+	{
+		for line in comment.lines()
+		{
+			self.write_indent()?;
+			if line.is_empty()
+			{
+				writeln!(self.writer, "#")?;
+			}
+			else
+			{
+				writeln!(self.writer, "# {}", line)?;
+			}
+		}
+		Ok(())
+	}
+
 	fn start_value(&mut self) -> Result<(), io::Error>
 	{
 		if (self.in_array() || self.one_line()) && !self.is_empty()
 		{
 			write!(self.writer, ",")?;
 		}
+		let comment = self.comment.take();
 		if !self.one_line()
 		{
 			if !(self.depth == 0 && self.in_root && self.is_empty())
 			{
 				write!(self.writer, "\n")?;
 			}
+			if let Some(comment) = comment
+			{
+				// Separate commented elements from the previous ones.
+				if !self.is_empty()
+				{
+					writeln!(self.writer)?;
+				}
+				self.write_comment(&comment)?;
+			}
 			self.write_indent()?;
 		}
 		else
@@ -84,9 +117,42 @@ impl<'l, W: io::Write> Printer<'l, W>
 		Ok(())
 	}
 
-	fn write_string(&mut self, s: &str) -> Result<(), io::Error>
+	/// Attaches a comment to the next element. The comment is printed before
+	/// the element, one `#` line per line of the comment. Comments of elements
+	/// inside single-line arrays and tables are not printed. A comment
+	/// attached to the root table is printed at the top, followed by an empty
+	/// line.
+	pub fn comment(&mut self, comment: &str)
+	{
+		self.comment = Some(comment.to_string());
+	}
+
+	/// Sets the preferred quote types of the name and of the value or the tag
+	/// of the next element. A quote type is only used if it can represent the
+	/// string, otherwise one is picked automatically.
+	pub fn quote_types(
+		&mut self, key_quote_type: Option<StringQuoteType>, quote_type: Option<StringQuoteType>,
+	)
 	{
-		match get_string_quote_type(s)
+		self.key_quote_type = key_quote_type;
+		self.quote_type = quote_type;
+	}
+
+	fn take_quote_types(&mut self) -> (Option<StringQuoteType>, Option<StringQuoteType>)
+	{
+		(self.key_quote_type.take(), self.quote_type.take())
+	}
+
+	fn write_string(
+		&mut self, s: &str, quote_type: Option<StringQuoteType>,
+	) -> Result<(), io::Error>
+	{
+		let quote_type = match quote_type
+		{
+			Some(quote_type) if quote_type.can_represent(s) => quote_type,
+			_ => get_string_quote_type(s),
+		};
+		match quote_type
 		{
 			StringQuoteType::Naked => write!(self.writer, "{}", s)?,
 			StringQuoteType::Quoted(num_braces) =>
@@ -107,29 +173,31 @@ impl<'l, W: io::Write> Printer<'l, W>
 
 	pub fn value(&mut self, name: Option<&str>, value: &str) -> Result<(), io::Error>
 	{
+		let (key_quote_type, quote_type) = self.take_quote_types();
 		self.start_value()?;
 		match name
 		{
 			Some(name) =>
 			{
-				self.write_string(name)?;
+				self.write_string(name, key_quote_type)?;
 				write!(self.writer, " = ")?;
 			}
 			_ => (),
 		}
-		self.write_string(value)?;
+		self.write_string(value, quote_type)?;
 		self.set_empty(false);
 		Ok(())
 	}
 
 	pub fn start_array(&mut self, name: Option<&str>, one_line: bool) -> Result<(), io::Error>
 	{
+		let (key_quote_type, _) = self.take_quote_types();
 		self.start_value()?;
 		match name
 		{
 			Some(name) =>
 			{
-				self.write_string(name)?;
+				self.write_string(name, key_quote_type)?;
 				write!(self.writer, " =")?;
 				if one_line
 				{
@@ -156,17 +224,18 @@ impl<'l, W: io::Write> Printer<'l, W>
 		&mut self, name: Option<&str>, tag: &str, one_line: bool,
 	) -> Result<(), io::Error>
 	{
+		let (key_quote_type, quote_type) = self.take_quote_types();
 		self.start_value()?;
 		match name
 		{
 			Some(name) =>
 			{
-				self.write_string(name)?;
+				self.write_string(name, key_quote_type)?;
 				write!(self.writer, " = ")?;
 			}
 			_ => (),
 		}
-		self.write_string(tag)?;
+		self.write_string(tag, quote_type)?;
 		if one_line
 		{
 			write!(self.writer, " ")?;
@@ -189,22 +258,23 @@ impl<'l, W: io::Write> Printer<'l, W>
 		&mut self, name: Option<&str>, tag: &str, is_root: bool, one_line: bool,
 	) -> Result<(), io::Error>
 	{
+		let (key_quote_type, quote_type) = self.take_quote_types();
 		if is_root
 		{
 			self.in_root = true;
-			return Ok(());
+			return self.root_comment();
 		}
 		self.start_value()?;
 		match name
 		{
 			Some(name) =>
 			{
-				self.write_string(name)?;
+				self.write_string(name, key_quote_type)?;
 				write!(self.writer, " = ")?;
 			}
 			_ => (),
 		}
-		self.write_string(tag)?;
+		self.write_string(tag, quote_type)?;
 		if one_line
 		{
 			write!(self.writer, " ")?;
@@ -224,6 +294,16 @@ impl<'l, W: io::Write> Printer<'l, W>
 		Ok(())
 	}
 
+	fn root_comment(&mut self) -> Result<(), io::Error>
+	{
+		if let Some(comment) = self.comment.take()
+		{
+			self.write_comment(&comment)?;
+			writeln!(self.writer)?;
+		}
+		Ok(())
+	}
+
 	pub fn end_array(&mut self) -> Result<(), io::Error>
 	{
 		if !self.one_line() && !self.is_empty()
@@ -246,17 +326,18 @@ impl<'l, W: io::Write> Printer<'l, W>
 		&mut self, name: Option<&str>, is_root: bool, one_line: bool,
 	) -> Result<(), io::Error>
 	{
+		let (key_quote_type, _) = self.take_quote_types();
 		if is_root
 		{
 			self.in_root = true;
-			return Ok(());
+			return self.root_comment();
 		}
 		self.start_value()?;
 		match name
 		{
 			Some(name) =>
 			{
-				self.write_string(name)?;
+				self.write_string(name, key_quote_type)?;
 				if one_line
 				{
 					write!(self.writer, " ")?;
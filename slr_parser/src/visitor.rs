@@ -8,7 +8,7 @@ use crate::parser::ConfigString;
 pub trait Visitor<'l>
 {
 	fn start_element(&mut self, src: &Source<'l>, name: ConfigString<'l>) -> Result<(), Error>;
-	fn end_element(&mut self) -> Result<(), Error>;
+	fn end_element(&mut self, src: &Source<'l>, extent: Span) -> Result<(), Error>;
 
 	fn set_table(&mut self, src: &Source<'l>, span: Span) -> Result<(), Error>;
 	fn set_tagged_array(